@@ -0,0 +1,313 @@
+// Static measurements of terms
+use std::collections::HashMap;
+
+use crate::subst::free_vars;
+use crate::{EvalError, Expr};
+
+// Number of nodes. Walks with an explicit stack, so it works on terms too
+// deep to recurse over.
+pub fn size(expr: &Expr) -> usize {
+    let mut pending = vec![expr];
+    let mut count = 0;
+    while let Some(expr) = pending.pop() {
+        count += 1;
+        match expr {
+            Expr::Var(_) | Expr::Lit(_) => {}
+            Expr::Abs(_, body) => pending.push(body),
+            Expr::App(f, arg) => pending.extend([f.as_ref(), arg.as_ref()]),
+            Expr::LetRecGroup(bindings, body) => {
+                pending.extend(bindings.iter().map(|(_, expr)| expr));
+                pending.push(body);
+            }
+            Expr::If(cond, then, otherwise) => pending.extend([&**cond, then, otherwise]),
+        }
+    }
+    count
+}
+
+// Number of nodes on the longest path from the root to a variable
+pub fn depth(expr: &Expr) -> usize {
+    match expr {
+        Expr::Var(_) | Expr::Lit(_) => 1,
+        Expr::Abs(_, body) => 1 + depth(body),
+        Expr::App(f, arg) => 1 + depth(f).max(depth(arg)),
+        Expr::LetRecGroup(bindings, body) => {
+            1 + bindings
+                .iter()
+                .map(|(_, expr)| depth(expr))
+                .fold(depth(body), usize::max)
+        }
+        Expr::If(cond, then, otherwise) => 1 + depth(cond).max(depth(then)).max(depth(otherwise)),
+    }
+}
+
+// Parameters of the abstractions whose body never mentions them, outermost
+// first
+pub fn unused_binders(expr: &Expr) -> Vec<String> {
+    let mut unused = Vec::new();
+    collect_unused(expr, &mut unused);
+    unused
+}
+
+fn collect_unused(expr: &Expr, unused: &mut Vec<String>) {
+    match expr {
+        Expr::Var(_) | Expr::Lit(_) => {}
+        Expr::Abs(param, body) => {
+            if !free_vars(body).contains(param) {
+                unused.push(param.clone());
+            }
+            collect_unused(body, unused);
+        }
+        Expr::App(f, arg) => {
+            collect_unused(f, unused);
+            collect_unused(arg, unused);
+        }
+        Expr::LetRecGroup(bindings, body) => {
+            for (_, expr) in bindings {
+                collect_unused(expr, unused);
+            }
+            collect_unused(body, unused);
+        }
+        Expr::If(cond, then, otherwise) => {
+            for child in [cond, then, otherwise] {
+                collect_unused(child, unused);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RedexCounts {
+    pub beta: usize,
+    pub eta: usize,
+}
+
+// Redexes present in the term as written, without reducing any of them
+pub fn count_redexes(expr: &Expr) -> RedexCounts {
+    let mut counts = RedexCounts::default();
+    collect_redexes(expr, &mut counts);
+    counts
+}
+
+fn collect_redexes(expr: &Expr, counts: &mut RedexCounts) {
+    match expr {
+        Expr::Var(_) | Expr::Lit(_) => {}
+        Expr::Abs(param, body) => {
+            if let Expr::App(f, arg) = body.as_ref() {
+                if **arg == Expr::Var(param.clone()) && !free_vars(f).contains(param) {
+                    counts.eta += 1;
+                }
+            }
+            collect_redexes(body, counts);
+        }
+        Expr::App(f, arg) => {
+            if let Expr::Abs(..) = f.as_ref() {
+                counts.beta += 1;
+            }
+            collect_redexes(f, counts);
+            collect_redexes(arg, counts);
+        }
+        Expr::LetRecGroup(bindings, body) => {
+            for (_, expr) in bindings {
+                collect_redexes(expr, counts);
+            }
+            collect_redexes(body, counts);
+        }
+        Expr::If(cond, then, otherwise) => {
+            for child in [cond, then, otherwise] {
+                collect_redexes(child, counts);
+            }
+        }
+    }
+}
+
+// How often each parameter is used in its abstraction's body: 0 if it is
+// discarded, 1 if it is linear and more if it is duplicated. A name bound by
+// several abstractions gets their total.
+pub fn usage_counts(expr: &Expr) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    collect_usage(expr, &mut Vec::new(), &mut counts);
+    counts
+}
+
+fn collect_usage<'a>(
+    expr: &'a Expr,
+    bound: &mut Vec<&'a str>,
+    counts: &mut HashMap<String, usize>,
+) {
+    match expr {
+        Expr::Var(name) => {
+            if bound.contains(&name.as_str()) {
+                *counts.get_mut(name).unwrap() += 1;
+            }
+        }
+        Expr::Lit(_) => {}
+        Expr::Abs(param, body) => {
+            counts.entry(param.clone()).or_insert(0);
+            bound.push(param);
+            collect_usage(body, bound, counts);
+            bound.pop();
+        }
+        Expr::App(f, arg) => {
+            collect_usage(f, bound, counts);
+            collect_usage(arg, bound, counts);
+        }
+        Expr::LetRecGroup(bindings, body) => {
+            for (name, _) in bindings {
+                counts.entry(name.clone()).or_insert(0);
+                bound.push(name);
+            }
+            for (_, expr) in bindings {
+                collect_usage(expr, bound, counts);
+            }
+            collect_usage(body, bound, counts);
+            bound.truncate(bound.len() - bindings.len());
+        }
+        Expr::If(cond, then, otherwise) => {
+            for child in [cond, then, otherwise] {
+                collect_usage(child, bound, counts);
+            }
+        }
+    }
+}
+
+// Longest run of arguments applied to a single head, 3 for `f a b c`
+pub fn max_spine(expr: &Expr) -> usize {
+    match expr {
+        Expr::Var(_) | Expr::Lit(_) => 0,
+        Expr::Abs(_, body) => max_spine(body),
+        Expr::LetRecGroup(bindings, body) => bindings
+            .iter()
+            .map(|(_, expr)| max_spine(expr))
+            .fold(max_spine(body), usize::max),
+        Expr::If(cond, then, otherwise) => max_spine(cond)
+            .max(max_spine(then))
+            .max(max_spine(otherwise)),
+        Expr::App(..) => {
+            let mut head = expr;
+            let mut args = Vec::new();
+            while let Expr::App(f, arg) = head {
+                args.push(arg.as_ref());
+                head = f;
+            }
+            args.iter()
+                .map(|arg| max_spine(arg))
+                .fold(args.len().max(max_spine(head)), usize::max)
+        }
+    }
+}
+
+// Bounds checked by `validate_sandbox`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SandboxLimits {
+    pub max_size: usize,
+    pub max_depth: usize,
+    pub max_spine: usize,
+}
+
+// Rejects terms too big for the sandbox without evaluating them. `size`
+// goes first because it doesn't recurse, so the other measures only ever
+// see terms small enough to walk.
+pub fn validate_sandbox(expr: &Expr, limits: &SandboxLimits) -> Result<(), EvalError> {
+    if size(expr) > limits.max_size {
+        return Err(EvalError::SandboxLimit("size", limits.max_size));
+    }
+    if depth(expr) > limits.max_depth {
+        return Err(EvalError::SandboxLimit("depth", limits.max_depth));
+    }
+    if max_spine(expr) > limits.max_spine {
+        return Err(EvalError::SandboxLimit("spine", limits.max_spine));
+    }
+    Ok(())
+}
+
+#[test]
+fn size_counts_every_node() {
+    use crate::church::church_numeral;
+
+    assert_eq!(size(&church_numeral(0)), 3);
+    assert_eq!(size(&church_numeral(2)), 7);
+}
+
+#[test]
+fn depth_follows_the_longest_branch() {
+    use crate::{abs, app, var};
+
+    assert_eq!(depth(&var("x")), 1);
+    assert_eq!(depth(&app(abs("x", abs("y", var("x"))), var("z"))), 4);
+}
+
+#[test]
+fn unused_binders_are_reported() {
+    use crate::{abs, app, var};
+
+    assert_eq!(unused_binders(&abs("u", var("x"))), vec!["u".to_string()]);
+    assert!(unused_binders(&abs("x", var("x"))).is_empty());
+    // \x. \y. x (\z. x)
+    let term = abs("x", abs("y", app(var("x"), abs("z", var("x")))));
+    assert_eq!(
+        unused_binders(&term),
+        vec!["y".to_string(), "z".to_string()]
+    );
+}
+
+#[test]
+fn redexes_are_counted_as_written() {
+    use crate::{abs, app, var};
+
+    // (\x. x) ((\y. y) z)
+    let term = app(abs("x", var("x")), app(abs("y", var("y")), var("z")));
+    assert_eq!(count_redexes(&term), RedexCounts { beta: 2, eta: 0 });
+
+    // \x. (\y. f y) x
+    let term = abs("x", app(abs("y", app(var("f"), var("y"))), var("x")));
+    assert_eq!(count_redexes(&term), RedexCounts { beta: 1, eta: 2 });
+}
+
+#[test]
+fn usage_counts_tell_linear_from_duplicated() {
+    use crate::{abs, app, var};
+
+    let counts = usage_counts(&abs("x", app(var("x"), var("x"))));
+    assert_eq!(counts, HashMap::from([("x".to_string(), 2)]));
+
+    let counts = usage_counts(&abs("x", abs("y", var("x"))));
+    assert_eq!(
+        counts,
+        HashMap::from([("x".to_string(), 1), ("y".to_string(), 0)])
+    );
+
+    // Free variables are not counted
+    assert!(usage_counts(&app(var("f"), var("f"))).is_empty());
+}
+
+#[test]
+fn sandbox_limits_are_checked_independently() {
+    use crate::{abs, app, var};
+
+    let limits = SandboxLimits {
+        max_size: 8,
+        max_depth: 4,
+        max_spine: 2,
+    };
+    // \x. f x x
+    let term = abs("x", app(app(var("f"), var("x")), var("x")));
+    assert_eq!(validate_sandbox(&term, &limits), Ok(()));
+
+    let wide = app(term.clone(), term.clone());
+    assert_eq!(
+        validate_sandbox(&wide, &limits),
+        Err(EvalError::SandboxLimit("size", 8))
+    );
+    let deep = abs("a", abs("b", abs("c", abs("d", var("a")))));
+    assert_eq!(
+        validate_sandbox(&deep, &limits),
+        Err(EvalError::SandboxLimit("depth", 4))
+    );
+    let long = app(app(app(var("f"), var("a")), var("b")), var("c"));
+    assert_eq!(max_spine(&long), 3);
+    assert_eq!(
+        validate_sandbox(&long, &limits),
+        Err(EvalError::SandboxLimit("spine", 2))
+    );
+}
@@ -0,0 +1,235 @@
+// Compact binary form: a version byte, then the term in prefix order with
+// bound variables as De Bruijn indices, so binder names are not stored. Free
+// variables keep their names. Indices and lengths are little-endian u32,
+// literals little-endian i64.
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::subst::{free_vars, readable_name};
+use crate::Expr;
+
+const VERSION: u8 = 1;
+
+const TAG_BOUND: u8 = 0;
+const TAG_FREE: u8 = 1;
+const TAG_ABS: u8 = 2;
+const TAG_APP: u8 = 3;
+const TAG_LIT: u8 = 4;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DecodeError {
+    UnsupportedVersion(u8),
+    UnexpectedEnd,
+    UnknownTag(u8),
+    // A De Bruijn index that points past the outermost binder
+    UnboundIndex(u32),
+    InvalidName,
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported encoding version {}", version)
+            }
+            DecodeError::UnexpectedEnd => write!(f, "Unexpected end of input"),
+            DecodeError::UnknownTag(tag) => write!(f, "Unknown tag {}", tag),
+            DecodeError::UnboundIndex(index) => write!(f, "Index {} has no binder", index),
+            DecodeError::InvalidName => write!(f, "Invalid variable name"),
+            DecodeError::TrailingBytes(count) => write!(f, "{} bytes after the term", count),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+pub fn to_bytes(expr: &Expr) -> Vec<u8> {
+    let mut bytes = vec![VERSION];
+    encode(expr, &mut Vec::new(), &mut bytes);
+    bytes
+}
+
+fn encode<'a>(expr: &'a Expr, bound: &mut Vec<&'a str>, bytes: &mut Vec<u8>) {
+    match expr {
+        Expr::Var(name) => match bound.iter().rev().position(|bound| bound == name) {
+            Some(index) => {
+                bytes.push(TAG_BOUND);
+                bytes.extend((index as u32).to_le_bytes());
+            }
+            None => {
+                bytes.push(TAG_FREE);
+                bytes.extend((name.len() as u32).to_le_bytes());
+                bytes.extend(name.as_bytes());
+            }
+        },
+        Expr::Lit(n) => {
+            bytes.push(TAG_LIT);
+            bytes.extend(n.to_le_bytes());
+        }
+        Expr::Abs(param, body) => {
+            bytes.push(TAG_ABS);
+            bound.push(param);
+            encode(body, bound, bytes);
+            bound.pop();
+        }
+        Expr::App(f, arg) => {
+            bytes.push(TAG_APP);
+            encode(f, bound, bytes);
+            encode(arg, bound, bytes);
+        }
+        // There is no tag for it, so it decodes as the term it stands for
+        Expr::LetRecGroup(..) | Expr::If(..) => encode(&expr.expand(), &mut bound.clone(), bytes),
+    }
+}
+
+// Binders come back as v0, v1, ... skipping the names of free variables, so
+// the result is alpha-equivalent to what was encoded
+pub fn from_bytes(bytes: &[u8]) -> Result<Expr, DecodeError> {
+    let (&version, rest) = bytes.split_first().ok_or(DecodeError::UnexpectedEnd)?;
+    if version != VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    let mut decoder = Decoder {
+        bytes: rest,
+        pos: 0,
+    };
+    let expr = decoder.term(&mut Vec::new())?;
+    let trailing = rest.len() - decoder.pos;
+    if trailing > 0 {
+        return Err(DecodeError::TrailingBytes(trailing));
+    }
+    Ok(rename_binders(expr))
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Decoder<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], DecodeError> {
+        let end = self
+            .pos
+            .checked_add(count)
+            .ok_or(DecodeError::UnexpectedEnd)?;
+        let taken = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or(DecodeError::UnexpectedEnd)?;
+        self.pos = end;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    // Binders are named by depth for now and made unique by `rename_binders`
+    fn term(&mut self, bound: &mut Vec<String>) -> Result<Expr, DecodeError> {
+        match self.take(1)?[0] {
+            TAG_BOUND => {
+                let index = self.u32()?;
+                let name = (index as usize)
+                    .checked_add(1)
+                    .and_then(|distance| bound.len().checked_sub(distance))
+                    .map(|at| bound[at].clone())
+                    .ok_or(DecodeError::UnboundIndex(index))?;
+                Ok(Expr::Var(name))
+            }
+            TAG_FREE => {
+                let len = self.u32()? as usize;
+                let name =
+                    std::str::from_utf8(self.take(len)?).map_err(|_| DecodeError::InvalidName)?;
+                Ok(Expr::Var(name.to_string()))
+            }
+            TAG_ABS => {
+                // A byte no identifier can contain keeps these apart from free names
+                let param = format!("#{}", bound.len());
+                bound.push(param.clone());
+                let body = self.term(bound);
+                bound.pop();
+                Ok(Expr::Abs(param, Box::new(body?)))
+            }
+            TAG_LIT => Ok(Expr::Lit(i64::from_le_bytes(
+                self.take(8)?.try_into().unwrap(),
+            ))),
+            TAG_APP => {
+                let f = self.term(bound)?;
+                let arg = self.term(bound)?;
+                Ok(Expr::App(Box::new(f), Box::new(arg)))
+            }
+            tag => Err(DecodeError::UnknownTag(tag)),
+        }
+    }
+}
+
+fn rename_binders(expr: Expr) -> Expr {
+    let avoid = free_vars(&expr);
+    rename_inner(expr, &avoid, &mut Vec::new())
+}
+
+fn rename_inner(expr: Expr, avoid: &HashSet<String>, names: &mut Vec<String>) -> Expr {
+    match expr {
+        // Free names from `fresh_name` start with `#` too, but not with a digit
+        Expr::Var(name) => match name
+            .strip_prefix('#')
+            .and_then(|depth| depth.parse::<usize>().ok())
+        {
+            Some(depth) => Expr::Var(names[depth].clone()),
+            None => Expr::Var(name),
+        },
+        Expr::Lit(_) => expr,
+        Expr::Abs(_, body) => {
+            let mut taken = avoid.clone();
+            taken.extend(names.iter().cloned());
+            names.push(readable_name("v", &taken));
+            let body = rename_inner(*body, avoid, names);
+            Expr::Abs(names.pop().unwrap(), Box::new(body))
+        }
+        Expr::App(f, arg) => Expr::App(
+            Box::new(rename_inner(*f, avoid, names)),
+            Box::new(rename_inner(*arg, avoid, names)),
+        ),
+        sugar @ (Expr::LetRecGroup(..) | Expr::If(..)) => {
+            rename_inner(sugar.expand(), avoid, names)
+        }
+    }
+}
+
+#[test]
+fn encoding_round_trips_up_to_alpha() {
+    use crate::church::{add, church_numeral};
+    use crate::diff::alpha_eq;
+    use crate::{abs, app, var};
+
+    let terms = [
+        var("x"),
+        abs("x", var("x")),
+        abs("v0", app(var("v0"), var("v0"))),
+        abs("x", abs("y", app(var("x"), var("v0")))),
+        app(var("f"), Expr::Lit(-7)),
+        app(add(), church_numeral(3)),
+    ];
+    for term in terms {
+        let decoded = from_bytes(&to_bytes(&term)).unwrap();
+        assert!(alpha_eq(&decoded, &term), "{} became {}", term, decoded);
+    }
+}
+
+#[test]
+fn malformed_input_is_an_error() {
+    use crate::{abs, app, var};
+
+    let bytes = to_bytes(&abs("x", app(var("x"), var("free"))));
+    for len in 0..bytes.len() {
+        assert_eq!(from_bytes(&bytes[..len]), Err(DecodeError::UnexpectedEnd));
+    }
+
+    assert_eq!(from_bytes(&[2, 3]), Err(DecodeError::UnsupportedVersion(2)));
+    assert_eq!(from_bytes(&[VERSION, 9]), Err(DecodeError::UnknownTag(9)));
+    assert_eq!(
+        from_bytes(&[VERSION, TAG_BOUND, 0, 0, 0, 0]),
+        Err(DecodeError::UnboundIndex(0))
+    );
+}
@@ -0,0 +1,140 @@
+// Tromp's binary lambda calculus: `00` then the body for an abstraction,
+// `01` then both sides for an application, and `1^n 0` for the variable
+// bound by the n-th enclosing binder, counting from 1. Only closed terms
+// without literals have an encoding.
+use crate::debruijn::{from_debruijn, to_debruijn, DeBruijn};
+use crate::parser::ParseError;
+use crate::Expr;
+
+// Panics on free variables and literals, which the format can't express
+pub fn to_blc(expr: &Expr) -> String {
+    fn go(term: &DeBruijn, out: &mut String) {
+        match term {
+            DeBruijn::Var(index) => {
+                out.extend(std::iter::repeat_n('1', index + 1));
+                out.push('0');
+            }
+            DeBruijn::Abs(body) => {
+                out.push_str("00");
+                go(body, out);
+            }
+            DeBruijn::App(f, arg) => {
+                out.push_str("01");
+                go(f, out);
+                go(arg, out);
+            }
+            DeBruijn::Free(name) => panic!("Free variable {} has no BLC encoding", name),
+            DeBruijn::Lit(n) => panic!("Literal {} has no BLC encoding", n),
+        }
+    }
+    let mut out = String::new();
+    go(&to_debruijn(expr), &mut out);
+    out
+}
+
+pub fn from_blc(bits: &str) -> Result<Expr, ParseError> {
+    let bits: Vec<char> = bits.chars().collect();
+    let mut pos = 0;
+    let term = decode(&bits, &mut pos, 0)?;
+    if pos < bits.len() {
+        return Err(ParseError {
+            offset: pos,
+            message: "Unexpected bits after the term".to_string(),
+        });
+    }
+    // Every index was checked against its depth while decoding
+    Ok(from_debruijn(&term).unwrap())
+}
+
+fn next_bit(bits: &[char], pos: &mut usize) -> Result<bool, ParseError> {
+    let offset = *pos;
+    let bit = match bits.get(offset) {
+        Some('0') => false,
+        Some('1') => true,
+        Some(c) => {
+            return Err(ParseError {
+                offset,
+                message: format!("Unexpected character '{}'", c),
+            })
+        }
+        None => {
+            return Err(ParseError {
+                offset,
+                message: "Unexpected end of input".to_string(),
+            })
+        }
+    };
+    *pos += 1;
+    Ok(bit)
+}
+
+fn decode(bits: &[char], pos: &mut usize, depth: usize) -> Result<DeBruijn, ParseError> {
+    let start = *pos;
+    if next_bit(bits, pos)? {
+        let mut ones = 1;
+        while next_bit(bits, pos)? {
+            ones += 1;
+        }
+        if ones > depth {
+            return Err(ParseError {
+                offset: start,
+                message: format!("Variable {} has no binder", ones),
+            });
+        }
+        return Ok(DeBruijn::Var(ones - 1));
+    }
+    if next_bit(bits, pos)? {
+        let f = decode(bits, pos, depth)?;
+        let arg = decode(bits, pos, depth)?;
+        Ok(DeBruijn::App(Box::new(f), Box::new(arg)))
+    } else {
+        Ok(DeBruijn::Abs(Box::new(decode(bits, pos, depth + 1)?)))
+    }
+}
+
+#[test]
+fn combinators_round_trip() {
+    use crate::diff::alpha_eq;
+    use crate::{abs, app, var};
+
+    let id = abs("x", var("x"));
+    let k = abs("x", abs("y", var("x")));
+    let s = abs(
+        "x",
+        abs(
+            "y",
+            abs("z", app(app(var("x"), var("z")), app(var("y"), var("z")))),
+        ),
+    );
+
+    assert_eq!(to_blc(&id), "0010");
+    assert_eq!(to_blc(&k), "0000110");
+    assert_eq!(to_blc(&s), "00000001011110100111010");
+    for term in [
+        id,
+        k,
+        s,
+        app(abs("f", app(var("f"), var("f"))), abs("a", var("a"))),
+    ] {
+        let back = from_blc(&to_blc(&term)).unwrap();
+        assert!(alpha_eq(&back, &term), "{} became {}", term, back);
+    }
+}
+
+#[test]
+fn malformed_bits_are_rejected() {
+    let error = |bits| from_blc(bits).unwrap_err();
+
+    assert_eq!(error("").offset, 0);
+    assert_eq!(error("001").message, "Unexpected end of input");
+    assert_eq!(error("0010 ").message, "Unexpected bits after the term");
+    assert_eq!(error("00102").offset, 4);
+    assert_eq!(error("0012").message, "Unexpected character '2'");
+    // The only binder is one level up
+    let unbound = error("00110");
+    assert_eq!(
+        (unbound.offset, unbound.message.as_str()),
+        (2, "Variable 2 has no binder")
+    );
+    assert_eq!(error("10").offset, 0);
+}
@@ -0,0 +1,493 @@
+use std::fmt;
+
+#[cfg(feature = "primitive")]
+use crate::desugar::desugar;
+use crate::normalize::normalize;
+use crate::subst::free_vars;
+#[cfg(feature = "primitive")]
+use crate::subst::subst;
+use crate::{abs, app, quote, var, EvalError, Expr, Value};
+
+// \f. \x. f (f (... x)), built bottom-up so any `n` that fits in memory
+// works. Most other code recurses once per node, though, including printing
+// and the default drop, so past about 10_000 on a 2 MiB thread stick to
+// `analysis::size` and free the result with `drop_iteratively`.
+pub fn church_numeral(n: usize) -> Expr {
+    let body = (0..n).fold(var("x"), |body, _| app(var("f"), body));
+    abs("f", abs("x", body))
+}
+
+// \n. \f. \x. f (n f x)
+pub fn succ() -> Expr {
+    abs(
+        "n",
+        abs(
+            "f",
+            abs("x", app(var("f"), app(app(var("n"), var("f")), var("x")))),
+        ),
+    )
+}
+
+// \m. \n. \f. \x. m f (n f x)
+pub fn add() -> Expr {
+    abs(
+        "m",
+        abs(
+            "n",
+            abs(
+                "f",
+                abs(
+                    "x",
+                    app(
+                        app(var("m"), var("f")),
+                        app(app(var("n"), var("f")), var("x")),
+                    ),
+                ),
+            ),
+        ),
+    )
+}
+
+// \m. \n. \f. m (n f)
+pub fn mul() -> Expr {
+    abs(
+        "m",
+        abs("n", abs("f", app(var("m"), app(var("n"), var("f"))))),
+    )
+}
+
+// Reads back the number a Church numeral stands for, `None` if it isn't one
+// or if its normal form takes more than `max_steps` steps to reach. Without
+// the `primitive` feature, the normal form is found by substitution and
+// matched structurally.
+#[cfg(not(feature = "primitive"))]
+pub fn decode_church(expr: &Expr, max_steps: usize) -> Option<u64> {
+    decode_by_structure(expr, max_steps)
+}
+
+// With the `primitive` feature, numerals are counted by reducing `n succ 0`
+// with `succ` a native successor over literals, open or closed
+#[cfg(feature = "primitive")]
+pub fn decode_church(expr: &Expr, max_steps: usize) -> Option<u64> {
+    decode_by_literals(expr, max_steps)
+}
+
+// Stands for the native successor. The parser rejects `#`, so it can't be
+// one of the term's own variables.
+#[cfg(feature = "primitive")]
+const NATIVE_SUCC: &str = "#succ";
+
+// Reduces `expr #succ 0` in normal order, where `#succ k` for a literal `k`
+// is one step to `k + 1`. A numeral reduces to the literal it stands for and
+// anything else gets stuck on something that isn't a literal.
+#[cfg(feature = "primitive")]
+fn decode_by_literals(expr: &Expr, max_steps: usize) -> Option<u64> {
+    let mut current = app(app(desugar(expr), var(NATIVE_SUCC)), Expr::Lit(0));
+    for _ in 0..max_steps {
+        if let Expr::Lit(n) = current {
+            return u64::try_from(n).ok();
+        }
+        current = step_with_succ(current).ok()?;
+    }
+    match current {
+        Expr::Lit(n) => u64::try_from(n).ok(),
+        _ => None,
+    }
+}
+
+// One leftmost-outermost step, folding `#succ` on a literal. Takes the term
+// by value so no subterm is copied, and hands it back unchanged as `Err` if
+// there is no step to take. Each level of the term costs a frame, so it
+// takes no sugar, which would only make them bigger.
+#[cfg(feature = "primitive")]
+fn step_with_succ(expr: Expr) -> Result<Expr, Expr> {
+    match expr {
+        Expr::Var(_) | Expr::Lit(_) => Err(expr),
+        Expr::Abs(param, body) => match step_with_succ(*body) {
+            Ok(body) => Ok(Expr::Abs(param, Box::new(body))),
+            Err(body) => Err(Expr::Abs(param, Box::new(body))),
+        },
+        Expr::App(f, arg) => match (*f, *arg) {
+            (Expr::Var(name), Expr::Lit(n)) if name == NATIVE_SUCC => Ok(Expr::Lit(n + 1)),
+            (Expr::Abs(param, body), arg) => Ok(subst(*body, &param, arg)),
+            (f, arg) => match step_with_succ(f) {
+                Ok(f) => Ok(app(f, arg)),
+                Err(f) => match step_with_succ(arg) {
+                    Ok(arg) => Ok(app(f, arg)),
+                    Err(arg) => Err(app(f, arg)),
+                },
+            },
+        },
+        // `decode_by_literals` desugars the term first
+        Expr::LetRecGroup(..) | Expr::If(..) => unreachable!(),
+    }
+}
+
+fn decode_by_structure(expr: &Expr, max_steps: usize) -> Option<u64> {
+    numeral_value(&normalize(expr.clone(), max_steps).ok()?)
+}
+
+// `n` for a term that is exactly `\f. \x. f (... (f x))` with `n` `f`s
+fn numeral_value(normal: &Expr) -> Option<u64> {
+    let Expr::Abs(f, inner) = normal else {
+        return None;
+    };
+    let Expr::Abs(x, body) = inner.as_ref() else {
+        return None;
+    };
+    let mut body = body.as_ref();
+
+    let mut count = 0;
+    loop {
+        match body {
+            Expr::Var(name) if name == x => return Some(count),
+            Expr::App(head, rest) if f != x && head.as_var() == Some(f) => {
+                count += 1;
+                body = rest;
+            }
+            _ => return None,
+        }
+    }
+}
+
+// Like `decode_church`, saying why it failed: a free variable, no normal form
+// within `max_steps` steps or a normal form that isn't a numeral
+pub fn eval_to_number(expr: &Expr, max_steps: usize) -> Result<u64, EvalError> {
+    let mut free: Vec<_> = free_vars(expr).into_iter().collect();
+    free.sort();
+    if let Some(name) = free.into_iter().next() {
+        return Err(EvalError::UnboundVariable(name));
+    }
+    let normal = normalize(expr.clone(), max_steps)?;
+    numeral_value(&normal).ok_or(EvalError::NotANumeral(normal))
+}
+
+// `eval_to_number` over a batch, one result per term
+pub fn eval_all_to_numbers(terms: &[Expr], max_steps: usize) -> Vec<Result<u64, EvalError>> {
+    terms
+        .iter()
+        .map(|term| eval_to_number(term, max_steps))
+        .collect()
+}
+
+// \t. \f. t for true and \t. \f. f for false
+pub fn church_bool(b: bool) -> Expr {
+    abs("t", abs("f", var(if b { "t" } else { "f" })))
+}
+
+// Normalizes and tells which of the two arguments a boolean selects
+pub fn decode_bool(expr: &Expr, max_steps: usize) -> Option<bool> {
+    let normal = normalize(expr.clone(), max_steps).ok()?;
+    let Expr::Abs(t, inner) = normal else {
+        return None;
+    };
+    let Expr::Abs(f, body) = *inner else {
+        return None;
+    };
+    match *body {
+        Expr::Var(ref name) if t == f => None,
+        Expr::Var(name) if name == f => Some(false),
+        Expr::Var(name) if name == t => Some(true),
+        _ => None,
+    }
+}
+
+// \a. \b. \s. s a b
+pub fn pair() -> Expr {
+    abs(
+        "a",
+        abs("b", abs("s", app(app(var("s"), var("a")), var("b")))),
+    )
+}
+
+// \p. p (\a. \b. a)
+pub fn fst() -> Expr {
+    abs("p", app(var("p"), abs("a", abs("b", var("a")))))
+}
+
+// \p. p (\a. \b. b)
+pub fn snd() -> Expr {
+    abs("p", app(var("p"), abs("a", abs("b", var("b")))))
+}
+
+// Signed integers are pairs `(pos, neg)` of numerals standing for pos - neg,
+// so every integer has many representations
+
+// \n. pair n 0
+pub fn mk_int() -> Expr {
+    abs("n", app(app(pair(), var("n")), church_numeral(0)))
+}
+
+// \i. \j. pair (add (fst i) (fst j)) (add (snd i) (snd j))
+pub fn int_add() -> Expr {
+    let sum = |part: fn() -> Expr| app(app(add(), app(part(), var("i"))), app(part(), var("j")));
+    abs("i", abs("j", app(app(pair(), sum(fst)), sum(snd))))
+}
+
+// \i. pair (snd i) (fst i)
+pub fn int_neg() -> Expr {
+    abs(
+        "i",
+        app(app(pair(), app(snd(), var("i"))), app(fst(), var("i"))),
+    )
+}
+
+// Decodes both halves of a signed pair and subtracts them
+pub fn decode_signed(expr: &Expr, max_steps: usize) -> Option<i64> {
+    let pos = decode_church(&app(fst(), expr.clone()), max_steps)?;
+    let neg = decode_church(&app(snd(), expr.clone()), max_steps)?;
+    i64::try_from(pos)
+        .ok()?
+        .checked_sub(i64::try_from(neg).ok()?)
+}
+
+// \c. \n. n
+pub fn nil() -> Expr {
+    abs("c", abs("n", var("n")))
+}
+
+// \h. \t. \c. \n. c h (t c n)
+pub fn cons() -> Expr {
+    abs(
+        "h",
+        abs(
+            "t",
+            abs(
+                "c",
+                abs(
+                    "n",
+                    app(
+                        app(var("c"), var("h")),
+                        app(app(var("t"), var("c")), var("n")),
+                    ),
+                ),
+            ),
+        ),
+    )
+}
+
+pub fn church_list(items: &[Expr]) -> Expr {
+    items
+        .iter()
+        .rev()
+        .fold(nil(), |tail, item| app(app(cons(), item.clone()), tail))
+}
+
+// Normalizes and unfolds `\c. \n. c a (c b (... n))` into `[a, b, ...]`
+pub fn decode_church_list(expr: &Expr, max_steps: usize) -> Option<Vec<Expr>> {
+    let normal = normalize(expr.clone(), max_steps).ok()?;
+    let Expr::Abs(c, inner) = normal else {
+        return None;
+    };
+    let Expr::Abs(n, mut body) = *inner else {
+        return None;
+    };
+
+    let mut items = Vec::new();
+    loop {
+        match *body {
+            Expr::Var(ref name) if *name == n => return Some(items),
+            Expr::App(cell, tail) if c != n => match *cell {
+                Expr::App(head, item) if *head == Expr::Var(c.clone()) => {
+                    let item_vars = free_vars(&item);
+                    if item_vars.contains(&c) || item_vars.contains(&n) {
+                        return None;
+                    }
+                    items.push(*item);
+                    body = tail;
+                }
+                _ => return None,
+            },
+            _ => return None,
+        }
+    }
+}
+
+// How long `TryFrom<&Value>` may normalize before giving up
+const CONVERSION_STEPS: usize = 100_000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConversionError {
+    pub expected: &'static str,
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Value is not a Church {}", self.expected)
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+impl TryFrom<&Value> for u64 {
+    type Error = ConversionError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        decode_church(&quote(value), CONVERSION_STEPS).ok_or(ConversionError {
+            expected: "numeral",
+        })
+    }
+}
+
+impl TryFrom<&Value> for bool {
+    type Error = ConversionError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        decode_bool(&quote(value), CONVERSION_STEPS).ok_or(ConversionError {
+            expected: "boolean",
+        })
+    }
+}
+
+#[test]
+fn decodes_numerals_both_ways() {
+    use crate::parser::parse;
+    use crate::{empty_context, eval};
+
+    assert_eq!(decode_church(&church_numeral(0), 100), Some(0));
+    assert_eq!(decode_church(&app(succ(), church_numeral(4)), 100), Some(5));
+
+    // Open, so decoded structurally
+    let open = app(abs("y", church_numeral(2)), var("y"));
+    assert_eq!(decode_church(&open, 100), Some(2));
+
+    assert_eq!(decode_church(&abs("x", abs("y", var("x"))), 100), None);
+    assert_eq!(decode_church(&var("n"), 100), None);
+
+    // Not numerals, though they only apply `f` and `x`
+    let extra_zero = parse("\\a. \\b. a b b").unwrap();
+    let extra_succ = parse("\\f. \\x. f x (f x)").unwrap();
+    assert_eq!(decode_church(&extra_zero, 100), None);
+    assert_eq!(decode_church(&extra_succ, 100), None);
+    assert_eq!(
+        u64::try_from(&eval(extra_zero, empty_context()).unwrap()).ok(),
+        None
+    );
+}
+
+#[test]
+fn decode_gives_up_after_max_steps() {
+    let omega = app(
+        abs("x", app(var("x"), var("x"))),
+        abs("x", app(var("x"), var("x"))),
+    );
+
+    assert_eq!(decode_church(&omega, 1_000), None);
+    assert_eq!(decode_church(&app(omega, var("y")), 1_000), None);
+}
+
+#[test]
+fn decodes_large_numerals_either_way() {
+    let thousand = app(app(mul(), church_numeral(10)), church_numeral(100));
+
+    assert_eq!(decode_church(&thousand, 100_000), Some(1_000));
+    assert_eq!(decode_church(&church_numeral(1_000), 100_000), Some(1_000));
+}
+
+#[cfg(feature = "primitive")]
+#[test]
+fn native_successor_counts_in_one_step_per_unit() {
+    let thousand = app(app(mul(), church_numeral(10)), church_numeral(100));
+
+    assert_eq!(decode_by_literals(&thousand, 20_000), Some(1_000));
+    assert_eq!(decode_by_literals(&thousand, 1_000), None);
+    assert_eq!(decode_by_literals(&abs("f", var("f")), 10), Some(1));
+    // Extra arguments apply a literal, which gets stuck
+    let extra = abs("a", abs("b", app(app(var("a"), var("b")), var("b"))));
+    assert_eq!(decode_by_literals(&extra, 100), None);
+    assert_eq!(decode_by_literals(&church_bool(true), 100), None);
+}
+
+#[test]
+fn batches_decode_to_native_numbers() {
+    let two = || church_numeral(2);
+    let three = || church_numeral(3);
+    let terms = [
+        app(succ(), two()),
+        app(app(add(), two()), three()),
+        app(app(mul(), two()), three()),
+    ];
+    assert_eq!(
+        eval_all_to_numbers(&terms, 1_000),
+        vec![Ok(3), Ok(5), Ok(6)]
+    );
+
+    let omega = app(
+        abs("x", app(var("x"), var("x"))),
+        abs("x", app(var("x"), var("x"))),
+    );
+    let failing = [church_bool(true), omega, app(succ(), var("n"))];
+    assert_eq!(
+        eval_all_to_numbers(&failing, 100),
+        vec![
+            Err(EvalError::NotANumeral(church_bool(true))),
+            Err(EvalError::StepLimit(100)),
+            Err(EvalError::UnboundVariable("n".to_string())),
+        ]
+    );
+}
+
+#[test]
+fn signed_pairs_add_and_negate() {
+    let int = |n| app(mk_int(), church_numeral(n));
+    let sum = app(app(int_add(), int(3)), app(int_neg(), int(5)));
+
+    assert_eq!(decode_signed(&sum, 10_000), Some(-2));
+    assert_eq!(decode_signed(&int(4), 10_000), Some(4));
+    assert_eq!(decode_signed(&church_numeral(2), 10_000), None);
+}
+
+#[test]
+fn church_list_round_trips() {
+    let items = vec![var("a"), var("b"), var("c")];
+
+    assert_eq!(decode_church_list(&church_list(&items), 100), Some(items));
+    assert_eq!(decode_church_list(&nil(), 100), Some(vec![]));
+}
+
+#[test]
+fn decode_church_list_rejects_non_lists() {
+    assert_eq!(decode_church_list(&abs("x", var("x")), 100), None);
+    assert_eq!(decode_church_list(&church_numeral(2), 100), None);
+}
+
+#[test]
+fn values_convert_to_native_numbers_and_booleans() {
+    use crate::{empty_context, eval};
+
+    let five = eval(app(succ(), church_numeral(4)), empty_context()).unwrap();
+    let yes = eval(church_bool(true), empty_context()).unwrap();
+    // not true
+    let not = abs(
+        "b",
+        app(app(var("b"), church_bool(false)), church_bool(true)),
+    );
+    let no = eval(app(not, church_bool(true)), empty_context()).unwrap();
+
+    assert_eq!(u64::try_from(&five), Ok(5));
+    assert_eq!(bool::try_from(&yes), Ok(true));
+    assert_eq!(bool::try_from(&no), Ok(false));
+    assert_eq!(
+        u64::try_from(&yes),
+        Err(ConversionError {
+            expected: "numeral"
+        })
+    );
+    assert_eq!(
+        bool::try_from(&five),
+        Err(ConversionError {
+            expected: "boolean"
+        })
+    );
+}
+
+#[test]
+fn large_numerals_build_without_recursion() {
+    use crate::analysis::size;
+
+    use crate::drop_iteratively;
+
+    let n = church_numeral(100_000);
+    assert_eq!(size(&n), 2 * 100_000 + 3);
+    drop_iteratively(n);
+}
@@ -0,0 +1,84 @@
+// Counts the heap allocations made on each thread, so tests can check that
+// a change really allocates less. Counters are per thread because tests run
+// in parallel.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct CountingAlloc;
+
+#[global_allocator]
+static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+thread_local! {
+    // `const` and without a destructor, so using them never allocates
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    static BYTES: Cell<usize> = const { Cell::new(0) };
+}
+
+fn record(size: usize) {
+    // Fails only while the thread is being torn down
+    let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+    let _ = BYTES.try_with(|n| n.set(n.get() + size));
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    // A reallocation counts as one allocation of the new size
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+// Totals for the current thread since it started
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AllocStats {
+    pub allocations: usize,
+    pub bytes: usize,
+}
+
+impl AllocStats {
+    // What was allocated between `earlier` and `self`
+    pub fn since(&self, earlier: &AllocStats) -> AllocStats {
+        AllocStats {
+            allocations: self.allocations - earlier.allocations,
+            bytes: self.bytes - earlier.bytes,
+        }
+    }
+}
+
+pub fn alloc_stats() -> AllocStats {
+    AllocStats {
+        allocations: ALLOCATIONS.with(Cell::get),
+        bytes: BYTES.with(Cell::get),
+    }
+}
+
+#[test]
+fn evaluation_allocates_within_budget() {
+    use crate::church::{church_numeral, mul};
+    use crate::{app, eval, Context};
+
+    let term = app(app(mul(), church_numeral(3)), church_numeral(3));
+    let before = alloc_stats();
+    let value = eval(term, Context::new());
+    let used = alloc_stats().since(&before);
+    drop(value);
+
+    // 35 when this was written
+    assert!(used.allocations > 0);
+    assert!(used.allocations < 100, "{:?}", used);
+}
@@ -0,0 +1,159 @@
+// Terms with bound variables as De Bruijn indices, 0 for the nearest
+// enclosing binder, so alpha-equal terms are equal. Free variables keep
+// their names.
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::subst::readable_name;
+use crate::Expr;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeBruijn {
+    Var(usize),
+    Free(String),
+    Lit(i64),
+    Abs(Box<DeBruijn>),
+    App(Box<DeBruijn>, Box<DeBruijn>),
+}
+
+// An index that points past the outermost binder, with the number of
+// binders around it
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnboundIndex {
+    pub index: usize,
+    pub depth: usize,
+}
+
+impl fmt::Display for UnboundIndex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Index {} has no binder at depth {}",
+            self.index, self.depth
+        )
+    }
+}
+
+impl std::error::Error for UnboundIndex {}
+
+pub fn to_debruijn(expr: &Expr) -> DeBruijn {
+    fn go<'a>(expr: &'a Expr, bound: &mut Vec<&'a str>) -> DeBruijn {
+        match expr {
+            Expr::Var(name) => match bound.iter().rev().position(|bound| bound == name) {
+                Some(index) => DeBruijn::Var(index),
+                None => DeBruijn::Free(name.clone()),
+            },
+            Expr::Lit(n) => DeBruijn::Lit(*n),
+            Expr::Abs(param, body) => {
+                bound.push(param);
+                let body = go(body, bound);
+                bound.pop();
+                DeBruijn::Abs(Box::new(body))
+            }
+            Expr::App(f, arg) => DeBruijn::App(Box::new(go(f, bound)), Box::new(go(arg, bound))),
+            Expr::LetRecGroup(..) | Expr::If(..) => go(&expr.expand(), &mut bound.clone()),
+        }
+    }
+    go(expr, &mut Vec::new())
+}
+
+// Whether every index points at a binder
+pub fn is_closed_debruijn(term: &DeBruijn) -> bool {
+    fn go(term: &DeBruijn, depth: usize) -> bool {
+        match term {
+            DeBruijn::Var(index) => *index < depth,
+            DeBruijn::Free(_) | DeBruijn::Lit(_) => true,
+            DeBruijn::Abs(body) => go(body, depth + 1),
+            DeBruijn::App(f, arg) => go(f, depth) && go(arg, depth),
+        }
+    }
+    go(term, 0)
+}
+
+// Names binders `v0`, `v1`, ... by depth, skipping the free names
+pub fn from_debruijn(term: &DeBruijn) -> Result<Expr, UnboundIndex> {
+    fn free_names(term: &DeBruijn, names: &mut HashSet<String>) {
+        match term {
+            DeBruijn::Free(name) => {
+                names.insert(name.clone());
+            }
+            DeBruijn::Var(_) | DeBruijn::Lit(_) => {}
+            DeBruijn::Abs(body) => free_names(body, names),
+            DeBruijn::App(f, arg) => {
+                free_names(f, names);
+                free_names(arg, names);
+            }
+        }
+    }
+
+    fn go(
+        term: &DeBruijn,
+        free: &HashSet<String>,
+        names: &mut Vec<String>,
+    ) -> Result<Expr, UnboundIndex> {
+        match term {
+            DeBruijn::Var(index) => match names.len().checked_sub(index + 1) {
+                Some(position) => Ok(Expr::Var(names[position].clone())),
+                None => Err(UnboundIndex {
+                    index: *index,
+                    depth: names.len(),
+                }),
+            },
+            DeBruijn::Free(name) => Ok(Expr::Var(name.clone())),
+            DeBruijn::Lit(n) => Ok(Expr::Lit(*n)),
+            DeBruijn::Abs(body) => {
+                let mut taken = free.clone();
+                taken.extend(names.iter().cloned());
+                names.push(readable_name("v", &taken));
+                let body = go(body, free, names);
+                let param = names.pop().unwrap();
+                Ok(Expr::Abs(param, Box::new(body?)))
+            }
+            DeBruijn::App(f, arg) => Ok(Expr::App(
+                Box::new(go(f, free, names)?),
+                Box::new(go(arg, free, names)?),
+            )),
+        }
+    }
+
+    let mut free = HashSet::new();
+    free_names(term, &mut free);
+    go(term, &free, &mut Vec::new())
+}
+
+#[test]
+fn indices_round_trip_up_to_alpha() {
+    use crate::church::{add, church_numeral};
+    use crate::diff::alpha_eq;
+    use crate::{abs, app, var};
+
+    let k = abs("x", abs("y", var("x")));
+    assert_eq!(
+        to_debruijn(&k),
+        DeBruijn::Abs(Box::new(DeBruijn::Abs(Box::new(DeBruijn::Var(1)))))
+    );
+    for term in [k, app(add(), church_numeral(2)), abs("v0", var("v1"))] {
+        let back = from_debruijn(&to_debruijn(&term)).unwrap();
+        assert!(alpha_eq(&back, &term), "{} became {}", term, back);
+    }
+}
+
+#[test]
+fn out_of_range_indices_are_rejected() {
+    use crate::{abs, var};
+
+    let open = DeBruijn::Var(0);
+    assert!(!is_closed_debruijn(&open));
+    assert_eq!(
+        from_debruijn(&open),
+        Err(UnboundIndex { index: 0, depth: 0 })
+    );
+
+    let id = DeBruijn::Abs(Box::new(DeBruijn::Var(0)));
+    assert!(is_closed_debruijn(&id));
+    assert_eq!(from_debruijn(&id), Ok(abs("v0", var("v0"))));
+
+    let too_far = DeBruijn::Abs(Box::new(DeBruijn::Var(1)));
+    assert!(!is_closed_debruijn(&too_far));
+    assert!(from_debruijn(&too_far).is_err());
+}
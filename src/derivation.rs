@@ -0,0 +1,101 @@
+// Big-step evaluation that keeps the proof of its result: one node per rule
+// applied, with the derivations of the rule's premises as children
+use std::rc::Rc;
+
+use crate::{Context, EvalError, Expr, Value};
+
+// E-Var, E-Lit, E-Abs, E-App and E-App for neutral heads
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rule {
+    Var,
+    Lit,
+    Abs,
+    // Premises: the function to a closure, the argument, then the body
+    App,
+    // Premises: the function to a neutral term, then the argument
+    AppNeutral,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Derivation {
+    pub rule: Rule,
+    pub expr: Expr,
+    pub value: Value,
+    pub premises: Vec<Derivation>,
+}
+
+// The same rules as `eval_without_trampoline`, so deep evaluations overflow
+// the stack the same way
+pub fn eval_derivation(expr: &Expr, ctx: &Context) -> Result<Derivation, EvalError> {
+    let leaf = |rule, value| Derivation {
+        rule,
+        expr: expr.clone(),
+        value,
+        premises: Vec::new(),
+    };
+    match expr {
+        Expr::Var(name) => match ctx.get(name) {
+            Some(value) => Ok(leaf(Rule::Var, value.clone())),
+            None => Err(EvalError::UnboundVariable(name.clone())),
+        },
+        Expr::Lit(n) => Ok(leaf(Rule::Lit, Value::VInt(*n))),
+        Expr::Abs(param, body) => Ok(leaf(
+            Rule::Abs,
+            Value::VClosure(
+                Rc::new(ctx.clone()),
+                param.clone(),
+                Rc::new(Expr::clone(body)),
+            ),
+        )),
+        Expr::LetRecGroup(..) | Expr::If(..) => eval_derivation(&expr.expand(), ctx),
+        Expr::App(f, arg) => {
+            let func = eval_derivation(f, ctx)?;
+            let arg = eval_derivation(arg, ctx)?;
+            let (rule, value, premises) = match &func.value {
+                Value::VClosure(captured, param, body) => {
+                    let mut new_ctx = Context::clone(captured);
+                    new_ctx.insert(param.clone(), arg.value.clone());
+                    let body = eval_derivation(body, &new_ctx)?;
+                    (Rule::App, body.value.clone(), vec![func, arg, body])
+                }
+                Value::VNeutral(head, args) => {
+                    let mut args = args.clone();
+                    args.push(arg.value.clone());
+                    let value = Value::VNeutral(head.clone(), args);
+                    (Rule::AppNeutral, value, vec![func, arg])
+                }
+                value @ Value::VInt(_) => return Err(EvalError::NotAFunction(value.clone())),
+            };
+            Ok(Derivation {
+                rule,
+                expr: expr.clone(),
+                value,
+                premises,
+            })
+        }
+    }
+}
+
+#[test]
+fn application_derives_from_its_premises() {
+    use std::collections::HashMap;
+
+    use crate::{abs, app, eval, var};
+
+    // (\x. x) y
+    let term = app(abs("x", var("x")), var("y"));
+    let y = eval(abs("z", var("z")), HashMap::new()).unwrap();
+    let ctx = HashMap::from([("y".to_string(), y.clone())]);
+
+    let derivation = eval_derivation(&term, &ctx).unwrap();
+    assert_eq!(derivation.rule, Rule::App);
+    assert_eq!(derivation.value, y);
+    let rules: Vec<_> = derivation.premises.iter().map(|d| d.rule).collect();
+    assert_eq!(rules, [Rule::Abs, Rule::Var, Rule::Var]);
+    assert_eq!(derivation.premises[2].expr, var("x"));
+
+    assert_eq!(
+        eval_derivation(&term, &HashMap::new()),
+        Err(EvalError::UnboundVariable("y".to_string()))
+    );
+}
@@ -0,0 +1,83 @@
+// The core calculus terms that `Expr`'s sugar nodes stand for, for the
+// passes that only know variables, abstractions and applications. The
+// trampoline evaluates the sugar itself.
+use std::collections::HashSet;
+
+use crate::subst::{free_vars, fresh_name};
+use crate::{abs, app, var, Expr};
+
+// `expr` with every sugar node rewritten
+pub fn desugar(expr: &Expr) -> Expr {
+    match expr {
+        Expr::Var(_) | Expr::Lit(_) => expr.clone(),
+        Expr::Abs(param, body) => abs(param, desugar(body)),
+        Expr::App(f, arg) => app(desugar(f), desugar(arg)),
+        Expr::LetRecGroup(..) | Expr::If(..) => desugar(&expr.expand()),
+    }
+}
+
+// (\r. (\f g. body) (fst r) (snd r))
+//     (Z (\r. (\f g. \k. k e1 e2) (fst r) (snd r)))
+// with `fst` and `snd` generalized to any number of definitions. `Z` is the
+// call-by-value fixed-point combinator, so this also works under `eval`
+// as long as each definition is an abstraction.
+pub fn letrec_group(bindings: &[(String, Expr)], body: &Expr) -> Expr {
+    let mut avoid: HashSet<String> = free_vars(body);
+    for (name, expr) in bindings {
+        avoid.insert(name.clone());
+        avoid.extend(free_vars(expr));
+    }
+    let group = fresh_name("rec", &avoid);
+    let k = fresh_name("k", &avoid);
+
+    let names: Vec<_> = bindings.iter().map(|(name, _)| name.clone()).collect();
+    let unpack = |inner: Expr| {
+        let fun = names
+            .iter()
+            .rev()
+            .fold(inner, |inner, name| abs(name, inner));
+        let args = (0..names.len()).map(|i| app(var(&group), selector(i, names.len())));
+        abs(&group, args.fold(fun, app))
+    };
+
+    let tuple = bindings
+        .iter()
+        .fold(var(&k), |tuple, (_, expr)| app(tuple, expr.clone()));
+    let knot = unpack(abs(&k, tuple));
+    app(unpack(body.clone()), app(z_combinator(), knot))
+}
+
+// \s0 ... s(n-1). s(i)
+fn selector(i: usize, n: usize) -> Expr {
+    (0..n).rev().fold(var(&format!("s{}", i)), |body, j| {
+        abs(&format!("s{}", j), body)
+    })
+}
+
+// \h. (\x. h (\v. x x v)) (\x. h (\v. x x v))
+fn z_combinator() -> Expr {
+    let half = abs(
+        "x",
+        app(var("h"), abs("v", app(app(var("x"), var("x")), var("v")))),
+    );
+    abs("h", app(half.clone(), half))
+}
+
+#[test]
+fn desugared_groups_normalize_like_the_group() {
+    use crate::church::{church_numeral, decode_church};
+    use crate::parser::parse;
+
+    let source = "letrec double = \\n. n add2 zero \
+                  and add2 = \\m f x. f (f (m f x)) \
+                  and zero = \\f x. x \
+                  in double (\\f x. f (f (f x)))";
+    let group = parse(source).unwrap();
+    assert!(matches!(group, Expr::LetRecGroup(..)));
+    let core = desugar(&group);
+    assert_eq!(decode_church(&core, 100_000), Some(6));
+    assert_eq!(desugar(&core), core);
+    assert_eq!(desugar(&church_numeral(2)), church_numeral(2));
+    let nested = parse("\\c. if c then x else if c then y else z").unwrap();
+    assert_eq!(desugar(&nested), parse("\\c. c x (c y z)").unwrap());
+}
@@ -0,0 +1,302 @@
+use crate::normalize::{eta_reduce, normalize};
+use crate::subst::subst;
+use crate::{Dir, EvalError, Expr};
+
+// The first place, from the root, where two terms stop being alpha-equal
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diff {
+    pub path: Vec<Dir>,
+    pub left: Expr,
+    pub right: Expr,
+}
+
+pub fn diff(a: &Expr, b: &Expr) -> Option<Diff> {
+    let mut path = Vec::new();
+    first_difference(a, b, &mut Vec::new(), &mut Vec::new(), &mut path).map(|(left, right)| Diff {
+        path,
+        left: left.clone(),
+        right: right.clone(),
+    })
+}
+
+// Bound variables are compared by the distance to their binder, free ones by name
+fn first_difference<'a>(
+    a: &'a Expr,
+    b: &'a Expr,
+    bound_a: &mut Vec<&'a str>,
+    bound_b: &mut Vec<&'a str>,
+    path: &mut Vec<Dir>,
+) -> Option<(&'a Expr, &'a Expr)> {
+    match (a, b) {
+        (Expr::Var(x), Expr::Var(y)) => {
+            let index_x = bound_a.iter().rev().position(|name| name == x);
+            let index_y = bound_b.iter().rev().position(|name| name == y);
+            let same = match (index_x, index_y) {
+                (None, None) => x == y,
+                (index_x, index_y) => index_x == index_y,
+            };
+            if same {
+                None
+            } else {
+                Some((a, b))
+            }
+        }
+        (Expr::Lit(x), Expr::Lit(y)) if x == y => None,
+        (Expr::Abs(x, body_a), Expr::Abs(y, body_b)) => {
+            bound_a.push(x);
+            bound_b.push(y);
+            path.push(Dir::AbsBody);
+            let result = first_difference(body_a, body_b, bound_a, bound_b, path);
+            if result.is_none() {
+                path.pop();
+            }
+            bound_a.pop();
+            bound_b.pop();
+            result
+        }
+        (Expr::App(f_a, arg_a), Expr::App(f_b, arg_b)) => {
+            path.push(Dir::AppFun);
+            if let Some(result) = first_difference(f_a, f_b, bound_a, bound_b, path) {
+                return Some(result);
+            }
+            path.pop();
+            path.push(Dir::AppArg);
+            if let Some(result) = first_difference(arg_a, arg_b, bound_a, bound_b, path) {
+                return Some(result);
+            }
+            path.pop();
+            None
+        }
+        _ => Some((a, b)),
+    }
+}
+
+pub fn alpha_eq(a: &Expr, b: &Expr) -> bool {
+    diff(a, b).is_none()
+}
+
+// Compares the beta-eta normal forms of both terms, `Ok(None)` meaning equal
+pub fn diff_normal_forms(a: &Expr, b: &Expr, max_steps: usize) -> Result<Option<Diff>, EvalError> {
+    let a = eta_reduce(normalize(a.clone(), max_steps)?);
+    let b = eta_reduce(normalize(b.clone(), max_steps)?);
+    Ok(diff(&a, &b))
+}
+
+pub fn beta_eta_eq(a: &Expr, b: &Expr, max_steps: usize) -> Result<bool, EvalError> {
+    diff_normal_forms(a, b, max_steps).map(|diff| diff.is_none())
+}
+
+// A quick necessary check for `beta_eta_eq`: reduces both sides to weak
+// head normal form and compares only the heads. `false` means the terms are
+// certainly not equal, `true` only that the heads agree, so a full
+// comparison is still needed. An abstraction may eta-reduce to a neutral
+// term, so that pair always passes.
+pub fn whnf_eq(a: &Expr, b: &Expr, max: usize) -> Result<bool, EvalError> {
+    let a = whnf_within(a.clone(), max)?;
+    let b = whnf_within(b.clone(), max)?;
+    Ok(match (a, b) {
+        (Head::Abs, _) | (_, Head::Abs) => true,
+        (Head::Lit(x), Head::Lit(y)) => x == y,
+        (Head::Neutral(x, args_x), Head::Neutral(y, args_y)) => x == y && args_x == args_y,
+        _ => false,
+    })
+}
+
+// What a weak head normal form starts with, with the argument count of a
+// neutral term. Literals applied to arguments count as neutral.
+#[derive(Debug, PartialEq)]
+enum Head {
+    Abs,
+    Lit(i64),
+    Neutral(Expr, usize),
+}
+
+fn whnf_within(expr: Expr, max_steps: usize) -> Result<Head, EvalError> {
+    let mut current = expr;
+    let mut args = Vec::new();
+    let mut steps = 0;
+    loop {
+        current = match current {
+            Expr::App(f, arg) => {
+                args.push(*arg);
+                *f
+            }
+            Expr::Abs(param, body) => match args.pop() {
+                Some(_) if steps == max_steps => return Err(EvalError::StepLimit(max_steps)),
+                Some(arg) => {
+                    steps += 1;
+                    subst(*body, &param, arg)
+                }
+                None => return Ok(Head::Abs),
+            },
+            Expr::Lit(n) if args.is_empty() => return Ok(Head::Lit(n)),
+            head => return Ok(Head::Neutral(head, args.len())),
+        };
+    }
+}
+
+// Why `beta_eta_eq` said what it did: both normal forms whenever they
+// differ, and the path to their first difference
+#[derive(Clone, Debug, PartialEq)]
+pub enum Equality {
+    Equal,
+    Differ {
+        lhs_nf: Expr,
+        rhs_nf: Expr,
+        path: Vec<Dir>,
+    },
+}
+
+pub fn beta_eta_eq_witness(a: &Expr, b: &Expr, max: usize) -> Result<Equality, EvalError> {
+    let lhs_nf = eta_reduce(normalize(a.clone(), max)?);
+    let rhs_nf = eta_reduce(normalize(b.clone(), max)?);
+    Ok(match diff(&lhs_nf, &rhs_nf) {
+        None => Equality::Equal,
+        Some(Diff { path, .. }) => Equality::Differ {
+            lhs_nf,
+            rhs_nf,
+            path,
+        },
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DefEqResult {
+    Equal,
+    NotEqual,
+    // Fuel ran out before both sides reached a normal form
+    Unknown,
+}
+
+// Unlike `beta_eta_eq`, running out of fuel is not reported as a difference
+pub fn definitionally_equal(a: &Expr, b: &Expr, fuel: usize) -> DefEqResult {
+    match diff_normal_forms(a, b, fuel) {
+        Ok(None) => DefEqResult::Equal,
+        Ok(Some(_)) => DefEqResult::NotEqual,
+        Err(_) => DefEqResult::Unknown,
+    }
+}
+
+#[test]
+fn diff_points_at_differing_argument() {
+    use crate::{abs, app, var};
+
+    // \x. f x x vs \x. f x y
+    let a = abs("x", app(app(var("f"), var("x")), var("x")));
+    let b = abs("x", app(app(var("f"), var("x")), var("y")));
+
+    assert_eq!(
+        diff_normal_forms(&a, &b, 100),
+        Ok(Some(Diff {
+            path: vec![Dir::AbsBody, Dir::AppArg],
+            left: var("x"),
+            right: var("y"),
+        }))
+    );
+}
+
+#[test]
+fn diff_normal_forms_ignores_binder_names_and_redexes() {
+    use crate::{abs, app, var};
+
+    let a = app(abs("y", abs("z", app(var("y"), var("z")))), var("f"));
+    let b = abs("x", app(var("f"), var("x")));
+
+    assert_eq!(diff_normal_forms(&a, &b, 100), Ok(None));
+    assert!(alpha_eq(&abs("x", var("x")), &abs("y", var("y"))));
+    assert!(!alpha_eq(&abs("x", var("y")), &abs("y", var("y"))));
+}
+
+#[test]
+fn witness_shows_both_normal_forms() {
+    use crate::church::{church_numeral, succ};
+    use crate::{abs, app, var};
+
+    // succ 1 against 3
+    let two = app(succ(), church_numeral(1));
+    let Ok(Equality::Differ {
+        lhs_nf,
+        rhs_nf,
+        path,
+    }) = beta_eta_eq_witness(&two, &church_numeral(3), 100)
+    else {
+        panic!("2 and 3 should differ")
+    };
+    assert_eq!(lhs_nf, church_numeral(2));
+    assert_eq!(rhs_nf, church_numeral(3));
+    // The innermost x against f x
+    assert_eq!(
+        path,
+        vec![Dir::AbsBody, Dir::AbsBody, Dir::AppArg, Dir::AppArg]
+    );
+
+    assert_eq!(
+        beta_eta_eq_witness(&app(abs("x", var("x")), var("y")), &var("y"), 10),
+        Ok(Equality::Equal)
+    );
+}
+
+#[test]
+fn whnf_eq_rejects_different_heads() {
+    use crate::church::{church_numeral, succ};
+    use crate::{abs, app, var};
+
+    // (\y. f) z against g, and f a against f a b
+    let f = app(abs("y", var("f")), var("z"));
+    assert_eq!(whnf_eq(&f, &var("g"), 10), Ok(false));
+    assert_eq!(
+        whnf_eq(
+            &app(var("f"), var("a")),
+            &app(app(var("f"), var("a")), var("b")),
+            10
+        ),
+        Ok(false)
+    );
+
+    // Same head, different arguments: passes, but the terms differ
+    let (lhs, rhs) = (app(f, var("a")), app(var("f"), var("b")));
+    assert_eq!(whnf_eq(&lhs, &rhs, 10), Ok(true));
+    assert_eq!(beta_eta_eq(&lhs, &rhs, 10), Ok(false));
+
+    // Both are abstractions once the head redex fires
+    let two = app(succ(), church_numeral(1));
+    assert_eq!(whnf_eq(&two, &church_numeral(3), 10), Ok(true));
+    assert_eq!(
+        whnf_eq(&abs("x", app(var("f"), var("x"))), &var("f"), 10),
+        Ok(true)
+    );
+
+    let omega = app(
+        abs("x", app(var("x"), var("x"))),
+        abs("x", app(var("x"), var("x"))),
+    );
+    assert_eq!(
+        whnf_eq(&omega, &var("f"), 10),
+        Err(EvalError::StepLimit(10))
+    );
+}
+
+#[test]
+fn definitional_equality_has_three_outcomes() {
+    use crate::church::{church_numeral, mul};
+    use crate::{abs, app, var};
+
+    let nine = app(app(mul(), church_numeral(3)), church_numeral(3));
+
+    assert_eq!(
+        definitionally_equal(&app(abs("x", var("x")), var("y")), &var("y"), 10),
+        DefEqResult::Equal
+    );
+    assert_eq!(
+        definitionally_equal(&var("x"), &var("y"), 10),
+        DefEqResult::NotEqual
+    );
+    assert_eq!(
+        definitionally_equal(&nine, &church_numeral(9), 3),
+        DefEqResult::Unknown
+    );
+    assert_eq!(
+        definitionally_equal(&nine, &church_numeral(9), 1_000),
+        DefEqResult::Equal
+    );
+}
@@ -0,0 +1,217 @@
+// Lambda terms with explicit substitutions. A beta step only records the
+// binding in a `Subst` node and separate rules push it down the tree, so
+// every intermediate substitution state can be observed.
+
+use std::collections::HashSet;
+
+use crate::subst::{free_vars, fresh_name};
+use crate::Expr;
+
+// Bindings of a `Subst` node, applied in parallel
+pub type Env = Vec<(String, ExExpr)>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExExpr {
+    Var(String),
+    Lit(i64),
+    Abs(String, Box<ExExpr>),
+    App(Box<ExExpr>, Box<ExExpr>),
+    Subst(Box<ExExpr>, Env),
+}
+
+pub fn from_expr(expr: &Expr) -> ExExpr {
+    match expr {
+        Expr::Var(name) => ExExpr::Var(name.clone()),
+        Expr::Lit(n) => ExExpr::Lit(*n),
+        Expr::Abs(param, body) => ExExpr::Abs(param.clone(), Box::new(from_expr(body))),
+        Expr::App(f, arg) => ExExpr::App(Box::new(from_expr(f)), Box::new(from_expr(arg))),
+        Expr::LetRecGroup(..) | Expr::If(..) => from_expr(&expr.expand()),
+    }
+}
+
+// Carries out any pending substitutions
+pub fn to_expr(t: ExExpr) -> Expr {
+    match t {
+        ExExpr::Var(name) => Expr::Var(name),
+        ExExpr::Lit(n) => Expr::Lit(n),
+        ExExpr::Abs(param, body) => Expr::Abs(param, Box::new(to_expr(*body))),
+        ExExpr::App(f, arg) => Expr::App(Box::new(to_expr(*f)), Box::new(to_expr(*arg))),
+        ExExpr::Subst(t, env) => {
+            let env: Vec<_> = env
+                .into_iter()
+                .map(|(name, t)| (name, to_expr(t)))
+                .collect();
+            parallel_subst(to_expr(*t), &env)
+        }
+    }
+}
+
+fn parallel_subst(expr: Expr, env: &[(String, Expr)]) -> Expr {
+    match expr {
+        Expr::Var(name) => match env.iter().find(|(bound, _)| *bound == name) {
+            Some((_, value)) => value.clone(),
+            None => Expr::Var(name),
+        },
+        Expr::Lit(_) => expr,
+        sugar @ (Expr::LetRecGroup(..) | Expr::If(..)) => parallel_subst(sugar.expand(), env),
+        Expr::App(f, arg) => Expr::App(
+            Box::new(parallel_subst(*f, env)),
+            Box::new(parallel_subst(*arg, env)),
+        ),
+        Expr::Abs(param, body) => {
+            let mut env: Vec<_> = env
+                .iter()
+                .filter(|(name, _)| *name != param)
+                .cloned()
+                .collect();
+            let mut avoid: HashSet<String> =
+                env.iter().flat_map(|(_, value)| free_vars(value)).collect();
+            if avoid.contains(&param) {
+                avoid.extend(free_vars(&body));
+                avoid.extend(env.iter().map(|(name, _)| name.clone()));
+                let renamed = fresh_name(&param, &avoid);
+                env.push((param, Expr::Var(renamed.clone())));
+                Expr::Abs(renamed, Box::new(parallel_subst(*body, &env)))
+            } else {
+                Expr::Abs(param, Box::new(parallel_subst(*body, &env)))
+            }
+        }
+    }
+}
+
+fn ex_free_vars(t: &ExExpr) -> HashSet<String> {
+    match t {
+        ExExpr::Var(name) => HashSet::from([name.clone()]),
+        ExExpr::Lit(_) => HashSet::new(),
+        ExExpr::Abs(param, body) => {
+            let mut vars = ex_free_vars(body);
+            vars.remove(param);
+            vars
+        }
+        ExExpr::App(f, arg) => {
+            let mut vars = ex_free_vars(f);
+            vars.extend(ex_free_vars(arg));
+            vars
+        }
+        ExExpr::Subst(t, env) => {
+            // The bindings are parallel, so a value's free variables are
+            // never removed by another binding of the same node
+            let mut vars = ex_free_vars(t);
+            for (name, _) in env {
+                vars.remove(name);
+            }
+            for (_, value) in env {
+                vars.extend(ex_free_vars(value));
+            }
+            vars
+        }
+    }
+}
+
+// Pushes a substitution one level into its term
+fn propagate(t: ExExpr, env: Env) -> ExExpr {
+    match t {
+        ExExpr::Var(name) => match env.into_iter().find(|(bound, _)| *bound == name) {
+            Some((_, value)) => value,
+            None => ExExpr::Var(name),
+        },
+        ExExpr::Lit(n) => ExExpr::Lit(n),
+        ExExpr::App(f, arg) => ExExpr::App(
+            Box::new(ExExpr::Subst(f, env.clone())),
+            Box::new(ExExpr::Subst(arg, env)),
+        ),
+        ExExpr::Abs(param, body) => {
+            let mut env: Env = env.into_iter().filter(|(name, _)| *name != param).collect();
+            let mut avoid: HashSet<String> = env
+                .iter()
+                .flat_map(|(_, value)| ex_free_vars(value))
+                .collect();
+            let param = if avoid.contains(&param) {
+                avoid.extend(ex_free_vars(&body));
+                avoid.extend(env.iter().map(|(name, _)| name.clone()));
+                let renamed = fresh_name(&param, &avoid);
+                env.push((param, ExExpr::Var(renamed.clone())));
+                renamed
+            } else {
+                param
+            };
+            if env.is_empty() {
+                ExExpr::Abs(param, body)
+            } else {
+                ExExpr::Abs(param, Box::new(ExExpr::Subst(body, env)))
+            }
+        }
+        // The inner substitution always has a step of its own, take it first
+        ExExpr::Subst(inner, inner_env) => {
+            ExExpr::Subst(Box::new(propagate(*inner, inner_env)), env)
+        }
+    }
+}
+
+// One leftmost-outermost step, or `None` if the term is a normal form. A
+// normal form never contains a `Subst` node.
+pub fn ex_step(t: ExExpr) -> Option<ExExpr> {
+    match t {
+        ExExpr::Var(_) | ExExpr::Lit(_) => None,
+        ExExpr::Subst(t, env) => Some(propagate(*t, env)),
+        ExExpr::Abs(param, body) => ex_step(*body).map(|body| ExExpr::Abs(param, Box::new(body))),
+        ExExpr::App(f, arg) => match *f {
+            ExExpr::Abs(param, body) => Some(ExExpr::Subst(body, vec![(param, *arg)])),
+            f => match ex_step(f.clone()) {
+                Some(f) => Some(ExExpr::App(Box::new(f), arg)),
+                None => ex_step(*arg).map(|arg| ExExpr::App(Box::new(f), Box::new(arg))),
+            },
+        },
+    }
+}
+
+#[test]
+fn beta_step_records_a_substitution() {
+    use crate::{abs, app, var};
+
+    let t = from_expr(&app(abs("x", var("x")), var("y")));
+
+    assert_eq!(
+        ex_step(t),
+        Some(ExExpr::Subst(
+            Box::new(ExExpr::Var("x".to_string())),
+            vec![("x".to_string(), ExExpr::Var("y".to_string()))]
+        ))
+    );
+}
+
+#[test]
+fn full_reduction_matches_normalize() {
+    use crate::app;
+    use crate::church::{add, church_numeral, mul, succ};
+    use crate::diff::alpha_eq;
+    use crate::normalize::normalize;
+
+    let terms = vec![
+        app(succ(), church_numeral(2)),
+        app(app(add(), church_numeral(2)), church_numeral(1)),
+        app(app(mul(), church_numeral(2)), church_numeral(2)),
+    ];
+
+    for expr in terms {
+        let mut t = from_expr(&expr);
+        while let Some(next) = ex_step(t.clone()) {
+            t = next;
+        }
+        assert!(alpha_eq(&to_expr(t), &normalize(expr, 1_000).unwrap()));
+    }
+}
+
+#[test]
+fn free_vars_of_a_substitution_keep_every_value() {
+    let var = |name: &str| ExExpr::Var(name.to_string());
+    // x[x := y, y := y0] is `y`
+    let t = ExExpr::Subst(
+        Box::new(var("x")),
+        vec![("x".to_string(), var("y")), ("y".to_string(), var("y0"))],
+    );
+
+    assert!(ex_free_vars(&t).contains("y"));
+    assert!(!ex_free_vars(&t).contains("x"));
+    assert_eq!(to_expr(t), crate::var("y"));
+}
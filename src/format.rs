@@ -0,0 +1,398 @@
+// Presentation of evaluation results, kept apart from evaluation so each
+// frontend can pick its own
+use std::error::Error;
+
+use serde_json::json;
+
+use crate::pretty::{pretty, pretty_trace, PrettyOpts};
+use crate::{quote, Expr, Value};
+
+pub trait ResultFormatter {
+    fn format_value(&self, v: &Value) -> String;
+    fn format_error(&self, e: &dyn Error) -> String;
+    // Output of REPL commands that report on a term without evaluating it
+    fn format_info(&self, text: &str) -> String;
+    // A reduction sequence, one term per step
+    fn format_trace(&self, trace: &[Expr]) -> String;
+}
+
+pub struct PlainFormatter;
+
+impl ResultFormatter for PlainFormatter {
+    fn format_value(&self, v: &Value) -> String {
+        quote(v).to_string()
+    }
+
+    fn format_error(&self, e: &dyn Error) -> String {
+        format!("Error: {}", e)
+    }
+
+    fn format_info(&self, text: &str) -> String {
+        text.to_string()
+    }
+
+    fn format_trace(&self, trace: &[Expr]) -> String {
+        let lines: Vec<_> = trace.iter().map(Expr::to_string).collect();
+        lines.join("\n")
+    }
+}
+
+// Like `PlainFormatter`, printing terms with `pretty`, so a trace highlights
+// each redex when the options ask for color
+pub struct PrettyFormatter(pub PrettyOpts);
+
+impl ResultFormatter for PrettyFormatter {
+    fn format_value(&self, v: &Value) -> String {
+        pretty(&quote(v), &self.0)
+    }
+
+    fn format_error(&self, e: &dyn Error) -> String {
+        PlainFormatter.format_error(e)
+    }
+
+    fn format_info(&self, text: &str) -> String {
+        PlainFormatter.format_info(text)
+    }
+
+    fn format_trace(&self, trace: &[Expr]) -> String {
+        pretty_trace(trace, &self.0).trim_end().to_string()
+    }
+}
+
+// One JSON object per result, `{"value": ...}`, `{"error": ...}`,
+// `{"info": ...}` or `{"trace": [...]}`
+pub struct JsonFormatter;
+
+impl ResultFormatter for JsonFormatter {
+    fn format_value(&self, v: &Value) -> String {
+        format!("{{\"value\":{}}}", json_string(&quote(v).to_string()))
+    }
+
+    fn format_error(&self, e: &dyn Error) -> String {
+        format!("{{\"error\":{}}}", json_string(&e.to_string()))
+    }
+
+    fn format_info(&self, text: &str) -> String {
+        format!("{{\"info\":{}}}", json_string(text))
+    }
+
+    fn format_trace(&self, trace: &[Expr]) -> String {
+        let steps: Vec<_> = trace
+            .iter()
+            .map(|expr| json_string(&expr.to_string()))
+            .collect();
+        format!("{{\"trace\":[{}]}}", steps.join(","))
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// JSON Schema for the serde representation of `Expr`, one single-key object
+// per node: `{"Var":"x"}`, `{"Lit":3}`, `{"Abs":["x",body]}`, `{"App":[f,arg]}`
+// `{"LetRecGroup":[[["f",def],...],body]}` and `{"If":[cond,then,else]}`
+pub fn json_schema() -> serde_json::Value {
+    let tagged = |tag: &str, schema: serde_json::Value| {
+        json!({
+            "type": "object",
+            "properties": { tag: schema },
+            "required": [tag],
+            "additionalProperties": false,
+        })
+    };
+    let tuple = |items: Vec<serde_json::Value>| {
+        json!({
+            "type": "array",
+            "minItems": items.len(),
+            "maxItems": items.len(),
+            "prefixItems": items,
+        })
+    };
+    let expr = json!({ "$ref": "#/$defs/expr" });
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$ref": "#/$defs/expr",
+        "$defs": {
+            "expr": {
+                "oneOf": [
+                    tagged("Var", json!({ "type": "string" })),
+                    tagged("Lit", json!({ "type": "integer" })),
+                    tagged("Abs", tuple(vec![json!({ "type": "string" }), expr.clone()])),
+                    tagged("App", tuple(vec![expr.clone(), expr.clone()])),
+                    tagged(
+                        "LetRecGroup",
+                        tuple(vec![
+                            json!({
+                                "type": "array",
+                                "items": tuple(vec![json!({ "type": "string" }), expr.clone()]),
+                            }),
+                            expr.clone(),
+                        ]),
+                    ),
+                    tagged("If", tuple(vec![expr.clone(), expr.clone(), expr])),
+                ]
+            }
+        }
+    })
+}
+
+// A standalone HTML page showing a term as nested lists, one `<li>` per node
+// labelled with its kind and name or literal. Clicking a label folds or
+// unfolds the node's children.
+pub fn to_html_tree(expr: &Expr) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<style>\n\
+         .tree span { cursor: pointer; font-family: monospace; }\n\
+         .tree .folded > ul { display: none; }\n\
+         </style>\n</head>\n<body>\n<ul class=\"tree\">\n",
+    );
+    html_node(expr, &mut out);
+    out.push_str(
+        "</ul>\n<script>\n\
+         for (const label of document.querySelectorAll(\".tree span\")) {\n\
+         \x20 label.onclick = () => label.parentElement.classList.toggle(\"folded\");\n\
+         }\n</script>\n</body>\n</html>\n",
+    );
+    out
+}
+
+fn html_node(expr: &Expr, out: &mut String) {
+    let (label, children): (String, Vec<&Expr>) = match expr {
+        Expr::Var(name) => (format!("Var {}", html_escape(name)), vec![]),
+        Expr::Lit(n) => (format!("Lit {}", n), vec![]),
+        Expr::Abs(param, body) => (format!("Abs {}", html_escape(param)), vec![body]),
+        Expr::App(f, arg) => ("App".to_string(), vec![f, arg]),
+        Expr::LetRecGroup(bindings, body) => {
+            let names: Vec<_> = bindings.iter().map(|(name, _)| html_escape(name)).collect();
+            let mut children: Vec<_> = bindings.iter().map(|(_, expr)| expr).collect();
+            children.push(body);
+            (format!("LetRecGroup {}", names.join(" ")), children)
+        }
+        Expr::If(cond, then, otherwise) => ("If".to_string(), vec![cond, then, otherwise]),
+    };
+    out.push_str(&format!("<li><span>{}</span>", label));
+    if !children.is_empty() {
+        out.push_str("<ul>\n");
+        for child in children {
+            html_node(child, out);
+        }
+        out.push_str("</ul>");
+    }
+    out.push_str("</li>\n");
+}
+
+fn html_escape(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+fn identity_value() -> Value {
+    use std::collections::HashMap;
+
+    use crate::{abs, eval_with_trampoline, var};
+
+    eval_with_trampoline(abs("x", var("x")), HashMap::new()).run()
+}
+
+#[test]
+fn plain_formatter_output() {
+    use crate::EvalError;
+
+    let error = EvalError::UnboundVariable("y".to_string());
+
+    assert_eq!(PlainFormatter.format_value(&identity_value()), "\\x. x");
+    assert_eq!(
+        PlainFormatter.format_error(&error),
+        "Error: Variable y not found"
+    );
+}
+
+#[test]
+fn json_formatter_output() {
+    use crate::EvalError;
+
+    let error = EvalError::UnboundVariable("y".to_string());
+
+    assert_eq!(
+        JsonFormatter.format_value(&identity_value()),
+        "{\"value\":\"\\\\x. x\"}"
+    );
+    assert_eq!(
+        JsonFormatter.format_error(&error),
+        "{\"error\":\"Variable y not found\"}"
+    );
+}
+
+#[test]
+fn pretty_formatter_without_color_prints_plainly() {
+    use crate::church::{church_numeral, succ};
+    use crate::normalize::reduce_steps;
+    use crate::subst::subst;
+    use crate::{abs, app, var};
+
+    let trace = reduce_steps(app(succ(), church_numeral(1)), 100);
+    let pretty = PrettyFormatter(PrettyOpts::default());
+    assert_eq!(
+        pretty.format_trace(&trace),
+        PlainFormatter.format_trace(&trace)
+    );
+    assert_eq!(
+        pretty.format_value(&identity_value()),
+        PlainFormatter.format_value(&identity_value())
+    );
+
+    // Stdout isn't a terminal under the test harness, so no colors, but
+    // generated binders are renamed
+    let renamed = subst(abs("x", app(var("y"), var("x"))), "y", var("x"));
+    let stdout = PrettyFormatter(PrettyOpts::for_stdout());
+    assert_eq!(stdout.format_trace(&[renamed]), "\\x0. x x0");
+    assert_eq!(
+        JsonFormatter.format_trace(&trace[..2]),
+        format!(
+            "{{\"trace\":[{},{}]}}",
+            json_string(&trace[0].to_string()),
+            json_string(&trace[1].to_string())
+        )
+    );
+}
+
+// Whether `value` satisfies `schema`, for the keywords `json_schema` uses.
+// Other keywords, such as `$schema` and `$defs`, are ignored.
+#[cfg(test)]
+fn satisfies(
+    value: &serde_json::Value,
+    schema: &serde_json::Value,
+    root: &serde_json::Value,
+) -> bool {
+    use serde_json::Value as Json;
+
+    let Some(keywords) = schema.as_object() else {
+        return false;
+    };
+    keywords
+        .iter()
+        .all(|(keyword, arg)| match (keyword.as_str(), arg) {
+            ("$ref", Json::String(path)) => path
+                .strip_prefix('#')
+                .and_then(|pointer| root.pointer(pointer))
+                .is_some_and(|target| satisfies(value, target, root)),
+            ("oneOf", Json::Array(options)) => {
+                options
+                    .iter()
+                    .filter(|option| satisfies(value, option, root))
+                    .count()
+                    == 1
+            }
+            ("type", Json::String(kind)) => match kind.as_str() {
+                "object" => value.is_object(),
+                "array" => value.is_array(),
+                "string" => value.is_string(),
+                "integer" => value.is_i64() || value.is_u64(),
+                _ => false,
+            },
+            ("properties", Json::Object(properties)) => properties.iter().all(|(key, schema)| {
+                value
+                    .get(key)
+                    .is_none_or(|field| satisfies(field, schema, root))
+            }),
+            ("required", Json::Array(keys)) => keys
+                .iter()
+                .all(|key| key.as_str().is_some_and(|key| value.get(key).is_some())),
+            ("additionalProperties", Json::Bool(false)) => value.as_object().is_none_or(|fields| {
+                fields
+                    .keys()
+                    .all(|key| schema["properties"].get(key).is_some())
+            }),
+            ("items", schema) => value
+                .as_array()
+                .is_none_or(|items| items.iter().all(|item| satisfies(item, schema, root))),
+            ("prefixItems", Json::Array(schemas)) => value.as_array().is_none_or(|items| {
+                items
+                    .iter()
+                    .zip(schemas)
+                    .all(|(item, schema)| satisfies(item, schema, root))
+            }),
+            ("minItems", n) => value
+                .as_array()
+                .is_none_or(|items| n.as_u64().is_some_and(|n| items.len() as u64 >= n)),
+            ("maxItems", n) => value
+                .as_array()
+                .is_none_or(|items| n.as_u64().is_some_and(|n| items.len() as u64 <= n)),
+            _ => true,
+        })
+}
+
+#[test]
+fn json_encoding_follows_the_schema() {
+    use crate::{abs, app, var};
+
+    let term = app(abs("x", var("x")), Expr::Lit(-2));
+    assert_eq!(
+        serde_json::to_string(&term).unwrap(),
+        "{\"App\":[{\"Abs\":[\"x\",{\"Var\":\"x\"}]},{\"Lit\":-2}]}"
+    );
+
+    let schema = json_schema();
+    for expr in [
+        term,
+        abs("f", app(var("f"), var("a\"b\\"))),
+        crate::church::church_numeral(2),
+        crate::parser::parse("letrec f = \\x. g x and g = \\y. f y in f").unwrap(),
+        crate::parser::parse("if c then \\x. x else y").unwrap(),
+    ] {
+        let value = serde_json::to_value(&expr).unwrap();
+        assert!(satisfies(&value, &schema, &schema), "{}", value);
+    }
+    for invalid in [
+        json!({ "Abs": ["x"] }),
+        json!({ "Abs": [1, { "Var": "x" }] }),
+        json!({ "App": [{ "Var": "f" }, { "Var": "x" }, { "Var": "y" }] }),
+        json!({ "App": [{ "Var": "f" }, { "Lam": "x" }] }),
+        json!({ "Lit": 1.5 }),
+        json!({ "LetRecGroup": [[["f"]], { "Var": "f" }] }),
+        json!({ "If": [{ "Var": "c" }, { "Var": "t" }] }),
+        json!({ "Var": "x", "Lit": 1 }),
+        json!(["Var", "x"]),
+    ] {
+        assert!(!satisfies(&invalid, &schema, &schema), "{}", invalid);
+        // serde rejects the same values
+        assert!(serde_json::from_value::<Expr>(invalid).is_err());
+    }
+}
+
+#[test]
+fn html_tree_has_one_item_per_node() {
+    use crate::{abs, app, var};
+
+    let html = to_html_tree(&abs("x", app(var("x"), var("x"))));
+    assert_eq!(html.matches("<li>").count(), 4);
+    assert_eq!(html.matches("</li>").count(), 4);
+    assert!(html.contains("<li><span>Abs x</span><ul>\n<li><span>App</span><ul>\n"));
+    assert_eq!(html.matches("<li><span>Var x</span></li>").count(), 2);
+
+    let quoted = to_html_tree(&var("x'"));
+    assert!(quoted.contains("<span>Var x&#39;</span>"));
+}
@@ -0,0 +1,113 @@
+// Deterministic random terms for benchmarks. Every binder is used at most
+// once, so each beta step shrinks the term and every generated term has a
+// normal form.
+use crate::Expr;
+
+// `redex_density` is the chance that an application is built with an
+// abstraction in function position. The same arguments always give the same
+// term.
+pub fn gen_term(seed: u64, depth: usize, redex_density: f64) -> Expr {
+    let mut gen = TermGen {
+        state: seed,
+        names: 0,
+        redex_density,
+    };
+    gen.term(depth, &mut Vec::new())
+}
+
+struct TermGen {
+    state: u64,
+    names: usize,
+    redex_density: f64,
+}
+
+impl TermGen {
+    fn next(&mut self) -> u64 {
+        // Knuth's MMIX linear congruential generator
+        self.state = self
+            .state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.state >> 33
+    }
+
+    fn chance(&mut self, p: f64) -> bool {
+        (self.next() as f64) < p * (1u64 << 31) as f64
+    }
+
+    fn fresh(&mut self) -> String {
+        self.names += 1;
+        format!("v{}", self.names - 1)
+    }
+
+    // `unused` holds the binders in scope that have not been used yet
+    fn term(&mut self, depth: usize, unused: &mut Vec<String>) -> Expr {
+        if depth == 0 {
+            return self.leaf(unused);
+        }
+        match self.next() % 3 {
+            0 => self.leaf(unused),
+            1 => self.abs(depth, unused),
+            _ => {
+                let f = if self.chance(self.redex_density) {
+                    self.abs(depth - 1, unused)
+                } else {
+                    self.head(depth - 1, unused)
+                };
+                let arg = self.term(depth - 1, unused);
+                Expr::App(Box::new(f), Box::new(arg))
+            }
+        }
+    }
+
+    fn abs(&mut self, depth: usize, unused: &mut Vec<String>) -> Expr {
+        let param = self.fresh();
+        unused.push(param.clone());
+        let body = self.term(depth.saturating_sub(1), unused);
+        unused.retain(|name| *name != param);
+        Expr::Abs(param, Box::new(body))
+    }
+
+    // An application or a variable, so the application around it is not a
+    // redex unless no variable is left to use
+    fn head(&mut self, depth: usize, unused: &mut Vec<String>) -> Expr {
+        if depth > 0 && self.chance(0.5) {
+            let f = self.head(depth - 1, unused);
+            let arg = self.term(depth - 1, unused);
+            return Expr::App(Box::new(f), Box::new(arg));
+        }
+        self.leaf(unused)
+    }
+
+    fn leaf(&mut self, unused: &mut Vec<String>) -> Expr {
+        if unused.is_empty() {
+            let param = self.fresh();
+            return Expr::Abs(param.clone(), Box::new(Expr::Var(param)));
+        }
+        let index = self.next() as usize % unused.len();
+        Expr::Var(unused.remove(index))
+    }
+}
+
+#[test]
+fn generated_terms_are_stable_closed_and_normalizing() {
+    use crate::analysis::{count_redexes, size};
+    use crate::normalize::normalize;
+    use crate::subst::free_vars;
+
+    assert_eq!(gen_term(7, 8, 0.5), gen_term(7, 8, 0.5));
+    assert_ne!(gen_term(7, 8, 0.5), gen_term(8, 8, 0.5));
+
+    for seed in 0..20 {
+        let term = gen_term(seed, 8, 0.5);
+        assert!(free_vars(&term).is_empty());
+        assert!(normalize(term.clone(), size(&term)).is_ok());
+    }
+
+    let redexes = |density| -> usize {
+        (0..20)
+            .map(|seed| count_redexes(&gen_term(seed, 8, density)).beta)
+            .sum()
+    };
+    assert!(redexes(0.9) > redexes(0.1));
+}
@@ -0,0 +1,214 @@
+// Every way a term can reduce, not just the leftmost-outermost one
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+use crate::diff::alpha_eq;
+use crate::subst::subst;
+use crate::{Dir, Expr};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    // Where the fired redex sits in the `from` term
+    pub redex: Vec<Dir>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Graph {
+    pub nodes: Vec<Expr>,
+    pub edges: Vec<Edge>,
+}
+
+// All one-step reducts of `expr`, paired with the path to the redex fired
+pub fn reducts(expr: &Expr) -> Vec<(Vec<Dir>, Expr)> {
+    let mut found = Vec::new();
+    collect_reducts(expr, &mut Vec::new(), &mut found);
+    found
+}
+
+fn collect_reducts(expr: &Expr, path: &mut Vec<Dir>, found: &mut Vec<(Vec<Dir>, Expr)>) {
+    match expr {
+        Expr::Var(_) | Expr::Lit(_) => {}
+        // Unfolding the group is the only step there
+        Expr::LetRecGroup(..) | Expr::If(..) => found.push((path.clone(), expr.expand())),
+        Expr::Abs(param, body) => {
+            collect_below(Dir::AbsBody, body, path, found, |body| {
+                Expr::Abs(param.clone(), Box::new(body))
+            });
+        }
+        Expr::App(f, arg) => {
+            if let Expr::Abs(param, body) = f.as_ref() {
+                found.push((path.clone(), subst(*body.clone(), param, *arg.clone())));
+            }
+            collect_below(Dir::AppFun, f, path, found, |f| {
+                Expr::App(Box::new(f), arg.clone())
+            });
+            collect_below(Dir::AppArg, arg, path, found, |arg| {
+                Expr::App(f.clone(), Box::new(arg))
+            });
+        }
+    }
+}
+
+fn collect_below(
+    dir: Dir,
+    child: &Expr,
+    path: &mut Vec<Dir>,
+    found: &mut Vec<(Vec<Dir>, Expr)>,
+    rebuild: impl Fn(Expr) -> Expr,
+) {
+    let mut inner = Vec::new();
+    path.push(dir);
+    collect_reducts(child, path, &mut inner);
+    path.pop();
+    found.extend(
+        inner
+            .into_iter()
+            .map(|(path, reduct)| (path, rebuild(reduct))),
+    );
+}
+
+// Explores reducts breadth-first, identifying alpha-equal terms, until
+// `max_nodes` terms have been found
+pub fn reduction_graph(expr: &Expr, max_nodes: usize) -> Graph {
+    let mut graph = Graph::default();
+    if max_nodes == 0 {
+        return graph;
+    }
+    graph.nodes.push(expr.clone());
+
+    let mut queue = VecDeque::from([0]);
+    while let Some(from) = queue.pop_front() {
+        for (redex, reduct) in reducts(&graph.nodes[from]) {
+            let to = match graph.nodes.iter().position(|node| alpha_eq(node, &reduct)) {
+                Some(to) => to,
+                None if graph.nodes.len() < max_nodes => {
+                    graph.nodes.push(reduct);
+                    queue.push_back(graph.nodes.len() - 1);
+                    graph.nodes.len() - 1
+                }
+                None => continue,
+            };
+            graph.edges.push(Edge { from, to, redex });
+        }
+    }
+    graph
+}
+
+// Every term at most `n` steps from `expr` under any choice of redex, up to
+// alpha-equivalence, in the order a breadth-first search meets them
+pub fn reachable_within(expr: &Expr, n: usize) -> Vec<Expr> {
+    let mut seen = vec![expr.clone()];
+    let mut frontier = vec![expr.clone()];
+    for _ in 0..n {
+        let mut next = Vec::new();
+        for term in &frontier {
+            for (_, reduct) in reducts(term) {
+                if !seen.iter().any(|node| alpha_eq(node, &reduct)) {
+                    seen.push(reduct.clone());
+                    next.push(reduct);
+                }
+            }
+        }
+        if next.is_empty() {
+            break;
+        }
+        frontier = next;
+    }
+    seen
+}
+
+impl Graph {
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph reductions {\n");
+        for (id, node) in self.nodes.iter().enumerate() {
+            let label = node.to_string().replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(dot, "  {} [label=\"{}\"];", id, label).unwrap();
+        }
+        for edge in &self.edges {
+            let redex = if edge.redex.is_empty() {
+                "root".to_string()
+            } else {
+                let dirs: Vec<_> = edge.redex.iter().map(|dir| format!("{:?}", dir)).collect();
+                dirs.join("/")
+            };
+            writeln!(dot, "  {} -> {} [label=\"{}\"];", edge.from, edge.to, redex).unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[test]
+fn independent_redexes_form_a_diamond() {
+    use crate::{abs, app, var};
+
+    // (\x. x w) ((\y. y) z)
+    let term = app(
+        abs("x", app(var("x"), var("w"))),
+        app(abs("y", var("y")), var("z")),
+    );
+    let graph = reduction_graph(&term, 10);
+
+    assert_eq!(
+        graph.nodes,
+        vec![
+            term,
+            app(app(abs("y", var("y")), var("z")), var("w")),
+            app(abs("x", app(var("x"), var("w"))), var("z")),
+            app(var("z"), var("w")),
+        ]
+    );
+
+    let edges: Vec<_> = graph
+        .edges
+        .iter()
+        .map(|edge| (edge.from, edge.to, edge.redex.clone()))
+        .collect();
+    assert_eq!(
+        edges,
+        vec![
+            (0, 1, vec![]),
+            (0, 2, vec![Dir::AppArg]),
+            (1, 3, vec![Dir::AppFun]),
+            (2, 3, vec![]),
+        ]
+    );
+}
+
+#[test]
+fn reachable_terms_grow_with_the_step_bound() {
+    use crate::{abs, app, var};
+
+    // (\x. x x) ((\y. y) z)
+    let id_z = app(abs("y", var("y")), var("z"));
+    let term = app(abs("x", app(var("x"), var("x"))), id_z.clone());
+
+    assert_eq!(reachable_within(&term, 0), vec![term.clone()]);
+    assert_eq!(
+        reachable_within(&term, 2),
+        vec![
+            term.clone(),
+            app(id_z.clone(), id_z.clone()),
+            app(abs("x", app(var("x"), var("x"))), var("z")),
+            app(var("z"), id_z.clone()),
+            app(id_z, var("z")),
+            app(var("z"), var("z")),
+        ]
+    );
+    // Everything is found by then
+    assert_eq!(reachable_within(&term, 10).len(), 6);
+}
+
+#[test]
+fn dot_output_lists_nodes_and_edges() {
+    use crate::{abs, app, var};
+
+    let graph = reduction_graph(&app(abs("x", var("x")), var("y")), 10);
+
+    assert_eq!(
+        graph.to_dot(),
+        "digraph reductions {\n  0 [label=\"(\\\\x. x) y\"];\n  1 [label=\"y\"];\n  0 -> 1 [label=\"root\"];\n}\n"
+    );
+}
@@ -0,0 +1,287 @@
+// Maximal sharing: every interned node is unique up to alpha-equivalence, so a
+// term with repeated subterms becomes a DAG whose shared parts are the same
+// `Rc`.
+
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::subst::{free_vars, readable_name};
+use crate::Expr;
+
+// A node whose children have already been interned
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExprNode {
+    Var(String),
+    Lit(i64),
+    Abs(String, Rc<ExprNode>),
+    App(Rc<ExprNode>, Rc<ExprNode>),
+}
+
+#[derive(Default)]
+pub struct HashConsTable {
+    nodes: HashMap<String, Rc<ExprNode>>,
+}
+
+impl HashConsTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn mk(&mut self, node: ExprNode) -> Rc<ExprNode> {
+        let mut key = String::new();
+        canonical_key(&node, &mut Vec::new(), &mut key);
+        self.nodes
+            .entry(key)
+            .or_insert_with(|| Rc::new(node))
+            .clone()
+    }
+}
+
+// De Bruijn indices for bound variables and length-prefixed names for free
+// ones, so alpha-equal nodes get the same key
+fn canonical_key<'a>(node: &'a ExprNode, bound: &mut Vec<&'a str>, key: &mut String) {
+    match node {
+        ExprNode::Var(name) => match bound.iter().rev().position(|bound| bound == name) {
+            Some(index) => key.push_str(&format!("#{}", index)),
+            None => key.push_str(&format!("${}:{}", name.len(), name)),
+        },
+        ExprNode::Lit(n) => key.push_str(&format!("%{}", n)),
+        ExprNode::Abs(param, body) => {
+            bound.push(param);
+            key.push('\\');
+            canonical_key(body, bound, key);
+            bound.pop();
+        }
+        ExprNode::App(f, arg) => {
+            key.push('(');
+            canonical_key(f, bound, key);
+            key.push(' ');
+            canonical_key(arg, bound, key);
+            key.push(')');
+        }
+    }
+}
+
+pub fn hashcons(expr: &Expr, table: &mut HashConsTable) -> Rc<ExprNode> {
+    let node = match expr {
+        Expr::Var(name) => ExprNode::Var(name.clone()),
+        Expr::Lit(n) => ExprNode::Lit(*n),
+        Expr::Abs(param, body) => ExprNode::Abs(param.clone(), hashcons(body, table)),
+        Expr::App(f, arg) => ExprNode::App(hashcons(f, table), hashcons(arg, table)),
+        Expr::LetRecGroup(..) | Expr::If(..) => return hashcons(&expr.expand(), table),
+    };
+    table.mk(node)
+}
+
+// How many subterms of `expr` are different up to alpha-equivalence.
+// Compared with `size`, this shows how much a DAG would save.
+pub fn count_distinct_subterms(expr: &Expr) -> usize {
+    let mut table = HashConsTable::new();
+    hashcons(expr, &mut table);
+    table.len()
+}
+
+pub fn to_expr(node: &ExprNode) -> Expr {
+    match node {
+        ExprNode::Var(name) => Expr::Var(name.clone()),
+        ExprNode::Lit(n) => Expr::Lit(*n),
+        ExprNode::Abs(param, body) => Expr::Abs(param.clone(), Box::new(to_expr(body))),
+        ExprNode::App(f, arg) => Expr::App(Box::new(to_expr(f)), Box::new(to_expr(arg))),
+    }
+}
+
+// Prints the DAG with every closed subterm that is referenced more than once
+// bound by a `let`, e.g. `let t0 = \x. x x in t0 t0`. Open subterms stay
+// inline, since a `let` outside their binder would change what they mean.
+pub fn pretty_shared(root: &Rc<ExprNode>) -> String {
+    let mut parents = HashMap::new();
+    count_parents(root, &mut parents);
+
+    let mut shared = Vec::new();
+    collect_shared(root, &parents, &mut HashSet::new(), &mut shared);
+
+    let mut avoid = names_in(&to_expr(root));
+    let mut names = HashMap::new();
+    let mut out = String::new();
+    for node in shared {
+        let name = readable_name("t", &avoid);
+        avoid.insert(name.clone());
+        let definition = with_names(node, &names);
+        out.push_str(&format!("let {} = {} in ", name, definition));
+        names.insert(Rc::as_ptr(node), name);
+    }
+    out.push_str(&with_names(root, &names).to_string());
+    out
+}
+
+fn count_parents(node: &Rc<ExprNode>, parents: &mut HashMap<*const ExprNode, usize>) {
+    let count = parents.entry(Rc::as_ptr(node)).or_insert(0);
+    *count += 1;
+    if *count > 1 {
+        return;
+    }
+    match node.as_ref() {
+        ExprNode::Var(_) | ExprNode::Lit(_) => {}
+        ExprNode::Abs(_, body) => count_parents(body, parents),
+        ExprNode::App(f, arg) => {
+            count_parents(f, parents);
+            count_parents(arg, parents);
+        }
+    }
+}
+
+// Post-order, so a shared node is named before anything that refers to it
+fn collect_shared<'a>(
+    node: &'a Rc<ExprNode>,
+    parents: &HashMap<*const ExprNode, usize>,
+    seen: &mut HashSet<*const ExprNode>,
+    shared: &mut Vec<&'a Rc<ExprNode>>,
+) {
+    if !seen.insert(Rc::as_ptr(node)) {
+        return;
+    }
+    match node.as_ref() {
+        ExprNode::Var(_) | ExprNode::Lit(_) => return,
+        ExprNode::Abs(_, body) => collect_shared(body, parents, seen, shared),
+        ExprNode::App(f, arg) => {
+            collect_shared(f, parents, seen, shared);
+            collect_shared(arg, parents, seen, shared);
+        }
+    }
+    if parents[&Rc::as_ptr(node)] > 1 && free_vars(&to_expr(node)).is_empty() {
+        shared.push(node);
+    }
+}
+
+fn with_names(node: &Rc<ExprNode>, names: &HashMap<*const ExprNode, String>) -> Expr {
+    if let Some(name) = names.get(&Rc::as_ptr(node)) {
+        return Expr::Var(name.clone());
+    }
+    match node.as_ref() {
+        ExprNode::Var(name) => Expr::Var(name.clone()),
+        ExprNode::Lit(n) => Expr::Lit(*n),
+        ExprNode::Abs(param, body) => Expr::Abs(param.clone(), Box::new(with_names(body, names))),
+        ExprNode::App(f, arg) => Expr::App(
+            Box::new(with_names(f, names)),
+            Box::new(with_names(arg, names)),
+        ),
+    }
+}
+
+// Bound and free, so the `let` names cannot clash with either
+fn names_in(expr: &Expr) -> HashSet<String> {
+    match expr {
+        Expr::Var(name) => HashSet::from([name.clone()]),
+        Expr::Lit(_) => HashSet::new(),
+        Expr::Abs(param, body) => {
+            let mut names = names_in(body);
+            names.insert(param.clone());
+            names
+        }
+        Expr::App(f, arg) => {
+            let mut names = names_in(f);
+            names.extend(names_in(arg));
+            names
+        }
+        Expr::LetRecGroup(bindings, body) => {
+            let mut names = names_in(body);
+            for (name, expr) in bindings {
+                names.insert(name.clone());
+                names.extend(names_in(expr));
+            }
+            names
+        }
+        Expr::If(cond, then, otherwise) => {
+            let mut names = names_in(cond);
+            names.extend(names_in(then));
+            names.extend(names_in(otherwise));
+            names
+        }
+    }
+}
+
+#[test]
+fn identical_subtrees_share_one_node() {
+    use crate::{abs, app, var};
+
+    // (\x. x x) (\y. y y)
+    let term = app(
+        abs("x", app(var("x"), var("x"))),
+        abs("y", app(var("y"), var("y"))),
+    );
+    let mut table = HashConsTable::new();
+    let root = hashcons(&term, &mut table);
+
+    match root.as_ref() {
+        ExprNode::App(f, arg) => assert!(Rc::ptr_eq(f, arg)),
+        _ => panic!("expected an application"),
+    }
+    // x, x x, y, y y, the shared abstraction and the root
+    assert_eq!(table.len(), 6);
+    assert_eq!(
+        to_expr(&root),
+        app(
+            abs("x", app(var("x"), var("x"))),
+            abs("x", app(var("x"), var("x")))
+        )
+    );
+}
+
+#[test]
+fn free_variables_are_not_shared_across_names() {
+    use crate::{app, var};
+
+    let mut table = HashConsTable::new();
+    let root = hashcons(&app(var("x"), var("y")), &mut table);
+
+    match root.as_ref() {
+        ExprNode::App(f, arg) => assert!(!Rc::ptr_eq(f, arg)),
+        _ => panic!("expected an application"),
+    }
+}
+
+#[test]
+fn shared_subterms_print_as_lets() {
+    use crate::{abs, app, var};
+
+    let term = app(
+        abs("x", app(var("x"), var("x"))),
+        abs("y", app(var("y"), var("y"))),
+    );
+    let mut table = HashConsTable::new();
+    let printed = pretty_shared(&hashcons(&term, &mut table));
+
+    assert_eq!(printed.matches("let ").count(), 1);
+    assert_eq!(printed, "let t0 = \\x. x x in t0 t0");
+
+    // The shared `x x` is open, so it is left where it is
+    let open = abs("x", app(app(var("x"), var("x")), app(var("x"), var("x"))));
+    let printed = pretty_shared(&hashcons(&open, &mut table));
+    assert_eq!(printed, "\\x. x x (x x)");
+}
+
+#[test]
+fn repeated_subtrees_count_once() {
+    use crate::analysis::size;
+    use crate::church::church_numeral;
+    use crate::{abs, app, var};
+
+    // \f. \x. f (f (f x)) has f three times
+    let three = church_numeral(3);
+    assert_eq!(count_distinct_subterms(&three), 7);
+    assert_eq!(size(&three), 9);
+
+    // (\x. x) (\y. y) is the same abstraction twice, over the distinct
+    // variables x and y
+    let term = app(abs("x", var("x")), abs("y", var("y")));
+    assert_eq!(count_distinct_subterms(&term), 4);
+    assert!(count_distinct_subterms(&term) < size(&term));
+}
@@ -0,0 +1,182 @@
+// Big-step evaluation with a step index: every beta step uses one step, and
+// running out gives `Timeout` instead of a value. Environments are persistent
+// linked lists shared between closures, so binding a variable allocates one
+// node instead of copying the whole context.
+use std::rc::Rc;
+
+use crate::{Context, Expr, Value};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Indexed {
+    Value(Value),
+    Timeout,
+    Unbound(String),
+    // Literals have no closure to run, so they stop evaluation here
+    Unsupported(Expr),
+}
+
+#[derive(Clone)]
+pub struct Closure<'a> {
+    param: &'a str,
+    body: &'a Expr,
+    env: Env<'a>,
+}
+
+#[derive(Clone, Default)]
+pub struct Env<'a>(Option<Rc<Binding<'a>>>);
+
+struct Binding<'a> {
+    name: &'a str,
+    closure: Closure<'a>,
+    next: Env<'a>,
+}
+
+impl<'a> Env<'a> {
+    // Borrows the closures of `context`, converting their captured contexts
+    // the same way
+    pub fn from_context(context: &'a Context) -> Env<'a> {
+        context
+            .iter()
+            .filter_map(|(name, value)| match value {
+                Value::VClosure(ctx, param, body) => Some((name, ctx, param, body)),
+                Value::VNeutral(..) | Value::VInt(_) => None,
+            })
+            .fold(Env::default(), |env, (name, ctx, param, body)| {
+                let closure = Closure {
+                    param,
+                    body,
+                    env: Env::from_context(ctx),
+                };
+                env.bind(name, closure)
+            })
+    }
+
+    fn bind(&self, name: &'a str, closure: Closure<'a>) -> Env<'a> {
+        Env(Some(Rc::new(Binding {
+            name,
+            closure,
+            next: self.clone(),
+        })))
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Closure<'a>> {
+        let mut current = &self.0;
+        while let Some(binding) = current {
+            if binding.name == name {
+                return Some(&binding.closure);
+            }
+            current = &binding.next.0;
+        }
+        None
+    }
+
+    // The innermost binding of each name, as the trampoline's contexts hold
+    fn to_context(&self) -> Context {
+        let mut context = Context::new();
+        let mut current = &self.0;
+        while let Some(binding) = current {
+            context
+                .entry(binding.name.to_string())
+                .or_insert_with(|| binding.closure.to_value());
+            current = &binding.next.0;
+        }
+        context
+    }
+}
+
+impl Closure<'_> {
+    fn to_value(&self) -> Value {
+        Value::VClosure(
+            Rc::new(self.env.to_context()),
+            self.param.to_string(),
+            Rc::new(self.body.clone()),
+        )
+    }
+}
+
+pub fn eval_indexed(expr: &Expr, env: &Env, steps: usize) -> Indexed {
+    let mut steps = steps;
+    match eval_closure(expr, env, &mut steps) {
+        Ok(closure) => Indexed::Value(closure.to_value()),
+        Err(stuck) => stuck,
+    }
+}
+
+fn eval_closure<'a>(
+    expr: &'a Expr,
+    env: &Env<'a>,
+    steps: &mut usize,
+) -> Result<Closure<'a>, Indexed> {
+    match expr {
+        Expr::Var(name) => env
+            .lookup(name)
+            .cloned()
+            .ok_or_else(|| Indexed::Unbound(name.clone())),
+        Expr::Lit(_) | Expr::LetRecGroup(..) | Expr::If(..) => {
+            Err(Indexed::Unsupported(expr.clone()))
+        }
+        Expr::Abs(param, body) => Ok(Closure {
+            param,
+            body,
+            env: env.clone(),
+        }),
+        Expr::App(f, arg) => {
+            let func = eval_closure(f, env, steps)?;
+            let arg = eval_closure(arg, env, steps)?;
+            *steps = steps.checked_sub(1).ok_or(Indexed::Timeout)?;
+            eval_closure(func.body, &func.env.bind(func.param, arg), steps)
+        }
+    }
+}
+
+#[test]
+fn indexed_matches_the_trampoline() {
+    use crate::church::{add, church_numeral};
+    use crate::vm::church_examples;
+    use crate::{app, eval_with_trampoline, quote};
+    use std::collections::HashMap;
+
+    let mut terms = church_examples();
+    terms.push(app(app(add(), church_numeral(2)), church_numeral(3)));
+    for term in terms {
+        let expected = eval_with_trampoline(term.clone(), HashMap::new()).run();
+        match eval_indexed(&term, &Env::default(), 10_000) {
+            Indexed::Value(value) => assert_eq!(quote(&value), quote(&expected)),
+            other => panic!("{} gave {:?}", term, other),
+        }
+    }
+}
+
+#[test]
+fn indexed_times_out_on_omega() {
+    use crate::{abs, app, var};
+
+    let omega = app(
+        abs("x", app(var("x"), var("x"))),
+        abs("x", app(var("x"), var("x"))),
+    );
+    assert_eq!(
+        eval_indexed(&omega, &Env::default(), 1_000),
+        Indexed::Timeout
+    );
+    assert_eq!(
+        eval_indexed(&app(var("f"), omega), &Env::default(), 1_000),
+        Indexed::Unbound("f".to_string())
+    );
+}
+
+#[test]
+fn indexed_reads_bindings_from_a_context() {
+    use crate::session::Session;
+    use crate::{app, quote, var};
+
+    let mut session = Session::new();
+    session.eval_line("id = \\x. x; k = \\x y. x").unwrap();
+    let id = session.context()["id"].clone();
+    let env = Env::from_context(session.context());
+
+    match eval_indexed(&app(app(var("k"), var("id")), var("k")), &env, 10) {
+        Indexed::Value(value) => assert_eq!(quote(&value), quote(&id)),
+        other => panic!("expected a value, got {:?}", other),
+    }
+}
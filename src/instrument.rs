@@ -0,0 +1,67 @@
+// Evaluation that measures where the time goes
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::{app, quote, Context, EvalError, Expr, Value};
+
+// Evaluates like `eval_without_trampoline` and records every beta step as
+// the redex, read back as a term, with the time from contracting it to
+// having the value of its body. Steps are listed in the order they fire.
+pub fn eval_timed(expr: Expr, ctx: Context) -> (Value, Vec<(Expr, Duration)>) {
+    let mut steps = Vec::new();
+    let value = eval_recording(expr, ctx, &mut steps);
+    (value, steps)
+}
+
+fn eval_recording(expr: Expr, context: Context, steps: &mut Vec<(Expr, Duration)>) -> Value {
+    match expr {
+        Expr::Var(name) => match context.get(&name) {
+            Some(value) => value.clone(),
+            None => panic!("Variable {} not found", name),
+        },
+        Expr::Lit(n) => Value::VInt(n),
+        Expr::Abs(param, body) => Value::VClosure(Rc::new(context), param, Rc::from(body)),
+        sugar @ (Expr::LetRecGroup(..) | Expr::If(..)) => {
+            eval_recording(sugar.expand(), context, steps)
+        }
+        Expr::App(f, arg) => {
+            let func = eval_recording(*f, context.clone(), steps);
+            let arg_value = eval_recording(*arg, context, steps);
+            let redex = app(quote(&func), quote(&arg_value));
+            match func {
+                Value::VClosure(ctx, param, body) => {
+                    let index = steps.len();
+                    steps.push((redex, Duration::ZERO));
+
+                    let start = Instant::now();
+                    let mut new_ctx = Rc::unwrap_or_clone(ctx);
+                    new_ctx.insert(param, arg_value);
+                    let value = eval_recording(Rc::unwrap_or_clone(body), new_ctx, steps);
+                    steps[index].1 = start.elapsed();
+                    value
+                }
+                Value::VNeutral(head, mut args) => {
+                    args.push(arg_value);
+                    Value::VNeutral(head, args)
+                }
+                value @ Value::VInt(_) => panic!("{}", EvalError::NotAFunction(value)),
+            }
+        }
+    }
+}
+
+#[test]
+fn records_one_entry_per_beta_step() {
+    use crate::{abs, var};
+    use std::collections::HashMap;
+
+    // (\x. x) ((\y. y) (\z. z))
+    let id = abs("z", var("z"));
+    let inner = app(abs("y", var("y")), id.clone());
+    let term = app(abs("x", var("x")), inner.clone());
+    let (value, steps) = eval_timed(term, HashMap::new());
+
+    assert_eq!(quote(&value), id);
+    let redexes: Vec<_> = steps.into_iter().map(|(redex, _)| redex).collect();
+    assert_eq!(redexes, vec![inner, app(abs("x", var("x")), id)]);
+}
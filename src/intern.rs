@@ -0,0 +1,130 @@
+// Closures that capture the same bindings can share one context. The
+// interner hands out a single `Rc` per structurally identical context, so a
+// program that builds many such closures keeps one copy of each environment.
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::{eval_in, Context, EvalError, Evaluator, Expr, Value};
+
+#[derive(Default)]
+pub struct ContextInterner {
+    contexts: HashMap<ContextKey, Rc<Context>>,
+}
+
+// A context's bindings in name order. A closure is keyed on the address of
+// its own context rather than on its contents: nested closures built while
+// interning have interned contexts, so equal ones already are the same `Rc`.
+// The table keeps every context it keys on alive, so an address can't be
+// reused for a different one.
+type ContextKey = Vec<(String, ValueKey)>;
+
+#[derive(PartialEq, Eq, Hash)]
+enum ValueKey {
+    Closure(*const Context, String, Expr),
+    Neutral(String, Vec<ValueKey>),
+    Int(i64),
+}
+
+impl ContextInterner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.contexts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contexts.is_empty()
+    }
+
+    pub fn intern(&mut self, context: Context) -> Rc<Context> {
+        let mut key: ContextKey = context
+            .iter()
+            .map(|(name, value)| (name.clone(), value_key(value)))
+            .collect();
+        key.sort_by(|a, b| a.0.cmp(&b.0));
+        self.contexts
+            .entry(key)
+            .or_insert_with(|| Rc::new(context))
+            .clone()
+    }
+}
+
+fn value_key(value: &Value) -> ValueKey {
+    match value {
+        Value::VClosure(ctx, param, body) => {
+            ValueKey::Closure(Rc::as_ptr(ctx), param.clone(), Expr::clone(body))
+        }
+        Value::VNeutral(head, args) => {
+            ValueKey::Neutral(head.clone(), args.iter().map(value_key).collect())
+        }
+        Value::VInt(n) => ValueKey::Int(*n),
+    }
+}
+
+// Evaluates like `eval_with_trampoline`, interning the context of every closure built on the
+// way into `interner`, which keeps them for later calls
+pub fn eval_interned(
+    expr: Expr,
+    context: Context,
+    interner: &mut ContextInterner,
+) -> Result<Value, EvalError> {
+    let evaluator = Rc::new(Evaluator {
+        interner: Some(std::mem::take(interner).into()),
+        ..Evaluator::default()
+    });
+    let result = eval_in(expr, context, evaluator.clone()).try_run();
+    if let Some(used) = &evaluator.interner {
+        *interner = used.take();
+    }
+    result
+}
+
+#[test]
+fn identical_captures_share_one_context() {
+    use crate::{abs, app, eval_without_trampoline, var};
+
+    let mut interner = ContextInterner::new();
+    let id = abs("z", var("z"));
+    // (\a. \x. a) (\z. z) and (\a. \y. y a) (\z. z) both capture a = \z. z
+    let k = app(abs("a", abs("x", var("a"))), id.clone());
+    let t = app(abs("a", abs("y", app(var("y"), var("a")))), id);
+
+    let first = eval_interned(k.clone(), Context::new(), &mut interner).unwrap();
+    let second = eval_interned(t, Context::new(), &mut interner).unwrap();
+    match (&first, &second) {
+        (Value::VClosure(a, ..), Value::VClosure(b, ..)) => assert!(Rc::ptr_eq(a, b)),
+        _ => panic!("expected closures"),
+    }
+    // The empty context of `\z. z` and the one binding `a`
+    assert_eq!(interner.len(), 2);
+    assert_eq!(first, eval_without_trampoline(k, Context::new()));
+
+    // Captured closures are keyed on their already interned contexts, so
+    // contexts nested two deep are shared too
+    let nested = app(
+        app(var("pair"), abs("p", var("p"))),
+        app(var("pair"), abs("q", var("q"))),
+    );
+    let pair = abs(
+        "l",
+        abs("r", abs("s", app(app(var("s"), var("l")), var("r")))),
+    );
+    let term = app(abs("pair", nested), pair);
+    let Value::VClosure(outer, ..) =
+        eval_interned(term.clone(), Context::new(), &mut interner).unwrap()
+    else {
+        panic!("expected a closure")
+    };
+    let Value::VClosure(again, ..) = eval_interned(term, Context::new(), &mut interner).unwrap()
+    else {
+        panic!("expected a closure")
+    };
+    assert!(Rc::ptr_eq(&outer, &again));
+    let (Value::VClosure(inner, ..), Value::VClosure(inner_again, ..)) = (&outer["r"], &again["r"])
+    else {
+        panic!("expected closures")
+    };
+    assert!(Rc::ptr_eq(inner, inner_again));
+}
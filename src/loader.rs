@@ -0,0 +1,125 @@
+// Program files: one definition per line, `import "other.lc"` to pull in the
+// definitions of another file (relative to the importing one) and `#` for
+// comments
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::session::Session;
+use crate::{Context, EvalError};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoadError {
+    Io(PathBuf, String),
+    // The chain of imports that leads back to its first file
+    Cycle(Vec<PathBuf>),
+    Eval(PathBuf, usize, EvalError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(path, message) => write!(f, "{}: {}", path.display(), message),
+            LoadError::Cycle(chain) => {
+                let chain: Vec<_> = chain
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect();
+                write!(f, "Import cycle: {}", chain.join(" -> "))
+            }
+            LoadError::Eval(path, line, err) => write!(f, "{}:{}: {}", path.display(), line, err),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+pub fn load_program(path: &Path) -> Result<Context, LoadError> {
+    let mut session = Session::new();
+    load_into(path, &mut session, &mut Vec::new(), &mut HashSet::new())?;
+    Ok(session.context().clone())
+}
+
+fn load_into(
+    path: &Path,
+    session: &mut Session,
+    importing: &mut Vec<PathBuf>,
+    loaded: &mut HashSet<PathBuf>,
+) -> Result<(), LoadError> {
+    let io_error = |err: std::io::Error| LoadError::Io(path.to_path_buf(), err.to_string());
+    let path = fs::canonicalize(path).map_err(io_error)?;
+    if let Some(start) = importing.iter().position(|seen| *seen == path) {
+        let mut chain = importing[start..].to_vec();
+        chain.push(path);
+        return Err(LoadError::Cycle(chain));
+    }
+    if !loaded.insert(path.clone()) {
+        return Ok(());
+    }
+    let source = fs::read_to_string(&path).map_err(io_error)?;
+
+    importing.push(path.clone());
+    for (index, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.strip_prefix("import ") {
+            Some(target) => {
+                let target = target.trim().trim_matches('"');
+                let dir = path.parent().unwrap_or(Path::new("."));
+                load_into(&dir.join(target), session, importing, loaded)?;
+            }
+            None => {
+                session
+                    .eval_line(line)
+                    .map_err(|err| LoadError::Eval(path.clone(), index + 1, err))?;
+            }
+        }
+    }
+    importing.pop();
+    Ok(())
+}
+
+#[cfg(test)]
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("trampoline-lc-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn imports_are_resolved_relative_to_the_file() {
+    let dir = temp_dir("imports");
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::write(dir.join("lib/prelude.lc"), "# combinators\nid = \\x. x\n").unwrap();
+    fs::write(
+        dir.join("main.lc"),
+        "import \"lib/prelude.lc\"\n\nidid = id id\n",
+    )
+    .unwrap();
+
+    let context = load_program(&dir.join("main.lc")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(context.len(), 2);
+    assert_eq!(context["idid"], context["id"]);
+}
+
+#[test]
+fn import_cycles_and_missing_files_are_errors() {
+    let dir = temp_dir("cycle");
+    fs::write(dir.join("a.lc"), "import \"b.lc\"\n").unwrap();
+    fs::write(dir.join("b.lc"), "import \"a.lc\"\n").unwrap();
+    fs::write(dir.join("c.lc"), "import \"missing.lc\"\n").unwrap();
+
+    let cycle = load_program(&dir.join("a.lc"));
+    let missing = load_program(&dir.join("c.lc"));
+    let a = fs::canonicalize(dir.join("a.lc")).unwrap();
+    let b = fs::canonicalize(dir.join("b.lc")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(cycle, Err(LoadError::Cycle(vec![a.clone(), b, a])));
+    assert!(matches!(missing, Err(LoadError::Io(path, _)) if path.ends_with("missing.lc")));
+}
@@ -1,37 +1,595 @@
-use std::collections::HashMap;
+// The evaluators are exercised by the tests rather than by `main`
+#![allow(dead_code)]
 
-#[derive(Clone, Debug, PartialEq)]
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, BufRead};
+use std::ops::ControlFlow;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use format::{JsonFormatter, PrettyFormatter, ResultFormatter};
+use parser::ParseError;
+use session::Session;
+use subst::free_vars;
+
+mod analysis;
+mod binary;
+mod blc;
+mod church;
+#[cfg(feature = "count-alloc")]
+mod count_alloc;
+mod debruijn;
+mod derivation;
+mod desugar;
+mod diff;
+mod explicit;
+mod format;
+mod generate;
+mod graph;
+mod hashcons;
+mod indexed;
+#[cfg(feature = "instrument")]
+mod instrument;
+mod intern;
+mod loader;
+#[cfg(feature = "memoize")]
+mod memo;
+mod need;
+mod normalize;
+mod parser;
+mod pipeline;
+mod prelude;
+mod pretty;
+mod primitive;
+mod repl;
+mod rpn;
+mod session;
+mod simplify;
+mod stats;
+mod subst;
+mod transpile;
+mod types;
+mod vm;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 enum Expr {
     Var(String),
     Abs(String, Box<Expr>),
     App(Box<Expr>, Box<Expr>),
+    // An integer constant. The parser has no syntax for it, so it is only
+    // built directly.
+    Lit(i64),
+    // `letrec f = e1 and g = e2 in body`: every name is bound in all of the
+    // definitions and in the body
+    LetRecGroup(Vec<(String, Expr)>, Box<Expr>),
+    // `if c then t else e`, with `c` a Church boolean
+    If(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn as_var(&self) -> Option<&str> {
+        match self {
+            Expr::Var(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn as_abs(&self) -> Option<(&str, &Expr)> {
+        match self {
+            Expr::Abs(param, body) => Some((param, body)),
+            _ => None,
+        }
+    }
+
+    fn as_app(&self) -> Option<(&Expr, &Expr)> {
+        match self {
+            Expr::App(f, arg) => Some((f, arg)),
+            _ => None,
+        }
+    }
+
+    // Abstractions and literals are the only terms that evaluate to
+    // themselves
+    fn is_value(&self) -> bool {
+        matches!(self, Expr::Abs(..) | Expr::Lit(_))
+    }
+
+    // The term in the core calculus that a sugar node stands for, with its
+    // children left as they are. Other nodes come back unchanged.
+    fn expand(&self) -> Expr {
+        match self {
+            Expr::LetRecGroup(bindings, body) => desugar::letrec_group(bindings, body),
+            Expr::If(cond, then, otherwise) => {
+                app(app(*cond.clone(), *then.clone()), *otherwise.clone())
+            }
+            _ => self.clone(),
+        }
+    }
+}
+
+// A step from a node to one of its children
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Dir {
+    AbsBody,
+    AppFun,
+    AppArg,
+}
+
+fn var(name: &str) -> Expr {
+    Expr::Var(name.to_string())
+}
+
+fn abs(param: &str, body: Expr) -> Expr {
+    Expr::Abs(param.to_string(), Box::new(body))
+}
+
+fn app(f: Expr, arg: Expr) -> Expr {
+    Expr::App(Box::new(f), Box::new(arg))
+}
+
+// Like `abs`, but panics right away on a parameter the parser couldn't read
+// back, such as `""` or `"x y"`
+fn abs_checked(param: &str, body: Expr) -> Expr {
+    assert!(parser::is_name(param), "{:?} is not a variable name", param);
+    abs(param.trim(), body)
+}
+
+// Hands back a term built in Rust if it is closed, and its free variables in
+// sorted order otherwise
+fn finish_closed(expr: Expr) -> Result<Expr, Vec<String>> {
+    let mut free: Vec<_> = free_vars(&expr).into_iter().collect();
+    if free.is_empty() {
+        return Ok(expr);
+    }
+    free.sort();
+    Err(free)
+}
+
+// Frees a term one node at a time, for terms so deep that the default drop
+// glue, which recurses once per node, would overflow the stack
+fn drop_iteratively(expr: Expr) {
+    let mut pending = vec![expr];
+    while let Some(expr) = pending.pop() {
+        match expr {
+            Expr::Var(_) | Expr::Lit(_) => {}
+            Expr::Abs(_, body) => pending.push(*body),
+            Expr::App(f, arg) => pending.extend([*f, *arg]),
+            Expr::LetRecGroup(bindings, body) => {
+                pending.extend(bindings.into_iter().map(|(_, expr)| expr));
+                pending.push(*body);
+            }
+            Expr::If(cond, then, otherwise) => pending.extend([*cond, *then, *otherwise]),
+        }
+    }
+}
+
+// The subterm reached by following `path` from `expr`, `None` if the path
+// leads past a leaf or into the wrong kind of node
+fn get_at<'a>(expr: &'a Expr, path: &[Dir]) -> Option<&'a Expr> {
+    path.iter().try_fold(expr, |expr, dir| match (dir, expr) {
+        (Dir::AbsBody, Expr::Abs(_, body)) => Some(body.as_ref()),
+        (Dir::AppFun, Expr::App(f, _)) => Some(f.as_ref()),
+        (Dir::AppArg, Expr::App(_, arg)) => Some(arg.as_ref()),
+        _ => None,
+    })
+}
+
+// `expr` with the subterm at `path` swapped for `new`. Binders are left as
+// they are, so free variables of `new` can be captured.
+fn replace_at(expr: Expr, path: &[Dir], new: Expr) -> Option<Expr> {
+    let Some((dir, rest)) = path.split_first() else {
+        return Some(new);
+    };
+    match (dir, expr) {
+        (Dir::AbsBody, Expr::Abs(param, body)) => {
+            Some(Expr::Abs(param, Box::new(replace_at(*body, rest, new)?)))
+        }
+        (Dir::AppFun, Expr::App(f, arg)) => {
+            Some(Expr::App(Box::new(replace_at(*f, rest, new)?), arg))
+        }
+        (Dir::AppArg, Expr::App(f, arg)) => {
+            Some(Expr::App(f, Box::new(replace_at(*arg, rest, new)?)))
+        }
+        _ => None,
+    }
+}
+
+// Prints the syntax accepted by `parser::parse`, except that a literal prints
+// as its digits, which parse back as a variable of that name, and a binder
+// renamed by `subst::fresh_name` doesn't parse at all
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Var(name) => write!(f, "{}", name),
+            Expr::Lit(n) => write!(f, "{}", n),
+            Expr::Abs(param, body) => write!(f, "\\{}. {}", param, body),
+            Expr::App(func, arg) => {
+                match func.as_ref() {
+                    Expr::Abs(..) | Expr::LetRecGroup(..) | Expr::If(..) => {
+                        write!(f, "({})", func)?
+                    }
+                    _ => write!(f, "{}", func)?,
+                }
+                match arg.as_ref() {
+                    Expr::Var(_) | Expr::Lit(_) => write!(f, " {}", arg),
+                    _ => write!(f, " ({})", arg),
+                }
+            }
+            Expr::LetRecGroup(bindings, body) => {
+                write!(f, "letrec ")?;
+                for (i, (name, expr)) in bindings.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " and ")?;
+                    }
+                    write!(f, "{} = {}", name, expr)?;
+                }
+                write!(f, " in {}", body)
+            }
+            Expr::If(cond, then, otherwise) => {
+                write!(f, "if {} then {} else {}", cond, then, otherwise)
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 enum Value {
-    VClosure(Context, String, Box<Expr>),
+    VClosure(Rc<Context>, String, Rc<Expr>),
+    // A free variable applied to the values of its arguments
+    VNeutral(String, Vec<Value>),
+    VInt(i64),
 }
 
 type Context = HashMap<String, Value>;
 
+fn empty_context() -> Context {
+    Context::new()
+}
+
+// Reads a closure back as a term by substituting its captured bindings
+fn quote(value: &Value) -> Expr {
+    let (ctx, param, body) = match value {
+        Value::VClosure(ctx, param, body) => (ctx, param, body),
+        Value::VNeutral(head, args) => {
+            return args
+                .iter()
+                .fold(Expr::Var(head.clone()), |f, arg| app(f, quote(arg)));
+        }
+        Value::VInt(n) => return Expr::Lit(*n),
+    };
+    let mut captured: Vec<_> = free_vars(body)
+        .into_iter()
+        .filter(|name| name != param && ctx.contains_key(name))
+        .collect();
+    captured.sort();
+    let body = captured.into_iter().fold(Expr::clone(body), |body, name| {
+        let value = quote(&ctx[&name]);
+        subst::subst(body, &name, value)
+    });
+    Expr::Abs(param.clone(), Box::new(body))
+}
+
+// A string that is equal for values whose read-back terms are alpha-eta
+// equal, for deduplicating values in hashed collections
+fn value_key(v: &Value) -> String {
+    rename_canonical(&normalize::eta_reduce(quote(v))).to_string()
+}
+
+// The representative of `expr`'s alpha-equivalence class, so `==` on the
+// results is alpha-equivalence
+fn rename_canonical(expr: &Expr) -> Expr {
+    let free = free_vars(expr);
+    canonical_binders(expr, &free, &mut 0, &mut Vec::new())
+}
+
+// Renames binders to `v0`, `v1`, ... in the order they are introduced,
+// skipping names that occur free
+fn canonical_binders(
+    expr: &Expr,
+    free: &HashSet<String>,
+    next: &mut usize,
+    renamed: &mut Vec<(String, String)>,
+) -> Expr {
+    match expr {
+        Expr::Var(name) => match renamed.iter().rev().find(|(old, _)| old == name) {
+            Some((_, new)) => Expr::Var(new.clone()),
+            None => expr.clone(),
+        },
+        Expr::Lit(_) => expr.clone(),
+        Expr::Abs(param, body) => {
+            let new = loop {
+                let candidate = format!("v{}", next);
+                *next += 1;
+                if !free.contains(&candidate) {
+                    break candidate;
+                }
+            };
+            renamed.push((param.clone(), new.clone()));
+            let body = canonical_binders(body, free, next, renamed);
+            renamed.pop();
+            Expr::Abs(new, Box::new(body))
+        }
+        Expr::App(f, arg) => Expr::App(
+            Box::new(canonical_binders(f, free, next, renamed)),
+            Box::new(canonical_binders(arg, free, next, renamed)),
+        ),
+        Expr::LetRecGroup(bindings, body) => {
+            let depth = renamed.len();
+            let mut names = Vec::new();
+            for (name, _) in bindings {
+                let new = loop {
+                    let candidate = format!("v{}", next);
+                    *next += 1;
+                    if !free.contains(&candidate) {
+                        break candidate;
+                    }
+                };
+                renamed.push((name.clone(), new.clone()));
+                names.push(new);
+            }
+            let bindings = names
+                .into_iter()
+                .zip(bindings)
+                .map(|(new, (_, expr))| (new, canonical_binders(expr, free, next, renamed)))
+                .collect();
+            let body = canonical_binders(body, free, next, renamed);
+            renamed.truncate(depth);
+            Expr::LetRecGroup(bindings, Box::new(body))
+        }
+        Expr::If(cond, then, otherwise) => Expr::If(
+            Box::new(canonical_binders(cond, free, next, renamed)),
+            Box::new(canonical_binders(then, free, next, renamed)),
+            Box::new(canonical_binders(otherwise, free, next, renamed)),
+        ),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum EvalError {
+    UnboundVariable(String),
+    MalformedCode,
+    StepLimit(usize),
+    RecursionLimit(usize),
+    Parse(ParseError),
+    SubstBudgetExceeded,
+    NameConflict(String),
+    // The function position of an application evaluated to something that
+    // cannot be applied
+    NotAFunction(Value),
+    // Which measure of the term broke a `SandboxLimits` bound, and the bound
+    SandboxLimit(&'static str, usize),
+    // A monitor asked the evaluation to stop
+    Cancelled,
+    // The normal form of a term expected to be a Church numeral
+    NotANumeral(Expr),
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::UnboundVariable(name) => write!(f, "Variable {} not found", name),
+            EvalError::MalformedCode => write!(f, "Malformed code"),
+            EvalError::StepLimit(steps) => write!(f, "No normal form within {} steps", steps),
+            EvalError::RecursionLimit(depth) => write!(f, "Recursion deeper than {}", depth),
+            EvalError::Parse(err) => write!(f, "{}", err),
+            EvalError::SubstBudgetExceeded => write!(f, "Substitution grew too large"),
+            EvalError::NameConflict(name) => write!(f, "{} is defined twice", name),
+            EvalError::NotAFunction(value) => write!(f, "{} is not a function", quote(value)),
+            EvalError::SandboxLimit(measure, limit) => {
+                write!(f, "Term {} exceeds the sandbox limit of {}", measure, limit)
+            }
+            EvalError::Cancelled => write!(f, "Evaluation cancelled"),
+            EvalError::NotANumeral(expr) => write!(f, "{} is not a Church numeral", expr),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+thread_local! {
+    // Bounces `eval_with_trampoline` may still take inline before the
+    // innermost `run_batched` loop bounces again
+    static INLINE_LEFT: Cell<usize> = const { Cell::new(0) };
+}
+
+fn take_inline() -> bool {
+    INLINE_LEFT.with(|left| match left.get() {
+        0 => false,
+        n => {
+            left.set(n - 1);
+            true
+        }
+    })
+}
+
 enum Trampoline {
     Continue(Box<dyn FnOnce() -> Trampoline>),
     Complete(Value),
+    Failed(EvalError),
 }
 
 impl Trampoline {
     fn run(self) -> Value {
+        match self.try_run() {
+            Ok(value) => value,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    fn try_run(self) -> Result<Value, EvalError> {
+        let mut current_trampoline = self;
+        loop {
+            match current_trampoline {
+                Trampoline::Complete(value) => return Ok(value),
+                Trampoline::Continue(func) => current_trampoline = func(),
+                Trampoline::Failed(err) => return Err(err),
+            }
+        }
+    }
+
+    // Like `run`, but lets `eval_with_trampoline` take up to `batch`
+    // applications inline between bounces instead of boxing each one. The
+    // stack grows with `batch`, so keep it to a few hundred.
+    fn run_batched(self, batch: usize) -> Value {
+        let saved = INLINE_LEFT.replace(batch);
+        let mut current_trampoline = self;
+        let result = loop {
+            match current_trampoline {
+                Trampoline::Complete(value) => break Ok(value),
+                Trampoline::Continue(func) => {
+                    INLINE_LEFT.set(batch);
+                    current_trampoline = func();
+                }
+                Trampoline::Failed(err) => break Err(err),
+            }
+        };
+        INLINE_LEFT.set(saved);
+        match result {
+            Ok(value) => value,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    // Counts bounces into `counter` and gives up once `cancel` is set, both
+    // checked from other threads. Bounces inside nested `run`s aren't seen.
+    fn run_monitored(self, counter: &AtomicUsize, cancel: &AtomicBool) -> Result<Value, EvalError> {
+        let mut current_trampoline = self;
+        loop {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(EvalError::Cancelled);
+            }
+            match current_trampoline {
+                Trampoline::Complete(value) => return Ok(value),
+                Trampoline::Continue(func) => current_trampoline = func(),
+                Trampoline::Failed(err) => return Err(err),
+            }
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // Calls `on_step` with the bounce count every `every` bounces. Returning
+    // `Break` from it abandons the evaluation and yields `None`.
+    fn run_with_progress(
+        self,
+        every: usize,
+        on_step: &mut dyn FnMut(usize) -> ControlFlow<()>,
+    ) -> Option<Value> {
         let mut current_trampoline = self;
+        let mut bounces = 0;
         loop {
             match current_trampoline {
-                Trampoline::Complete(value) => return value,
+                Trampoline::Complete(value) => return Some(value),
                 Trampoline::Continue(func) => current_trampoline = func(),
+                Trampoline::Failed(err) => panic!("{}", err),
+            }
+            bounces += 1;
+            if bounces % every.max(1) == 0 && on_step(bounces).is_break() {
+                return None;
             }
         }
     }
 }
 
+// Bounces `a` and `b` in turns of `budget_per_round` bounces and returns the
+// value of whichever completes first, so one of them may diverge
+fn race(a: Trampoline, b: Trampoline, budget_per_round: usize) -> Value {
+    race_many(vec![a, b], budget_per_round).0
+}
+
+// `race` over any number of trampolines, also returning the position of the
+// one that completed
+fn race_many(mut trampolines: Vec<Trampoline>, budget_per_round: usize) -> (Value, usize) {
+    assert!(!trampolines.is_empty(), "nothing to race");
+    fn bounce(mut trampoline: Trampoline, times: usize) -> Result<Value, Trampoline> {
+        for _ in 0..times {
+            match trampoline {
+                Trampoline::Complete(value) => return Ok(value),
+                Trampoline::Continue(func) => trampoline = func(),
+                Trampoline::Failed(err) => panic!("{}", err),
+            }
+        }
+        match trampoline {
+            Trampoline::Complete(value) => Ok(value),
+            pending => Err(pending),
+        }
+    }
+
+    loop {
+        let mut pending = Vec::with_capacity(trampolines.len());
+        for (i, trampoline) in trampolines.into_iter().enumerate() {
+            match bounce(trampoline, budget_per_round.max(1)) {
+                Ok(value) => return (value, i),
+                Err(trampoline) => pending.push(trampoline),
+            }
+        }
+        trampolines = pending;
+    }
+}
+
+// Optional machinery `eval_in` applies along the way. The trampoline's thunks
+// can't borrow, so it is shared through an `Rc` and changed through cells.
+#[derive(Default)]
+struct Evaluator {
+    // Closures share one `Rc` per structurally identical context
+    interner: Option<RefCell<intern::ContextInterner>>,
+    // Applications in argument position are evaluated once per term and
+    // bindings of its free variables
+    #[cfg(feature = "memoize")]
+    memo: Option<RefCell<memo::EvalMemo>>,
+    // Consulted whenever a neutral spine gets another argument
+    primitives: primitive::Primitives,
+    // Tallies what the evaluation uses, and may prune the contexts
+    meter: Option<RefCell<stats::Meter>>,
+}
+
+impl Evaluator {
+    // The context for a closure to capture
+    fn capture(&self, context: Context) -> Rc<Context> {
+        match &self.interner {
+            Some(interner) => interner.borrow_mut().intern(context),
+            None => Rc::new(context),
+        }
+    }
+
+    // The context to evaluate `expr` in
+    fn enter(&self, expr: &Expr, context: Context) -> Context {
+        match &self.meter {
+            Some(meter) => meter.borrow_mut().enter(expr, context),
+            None => context,
+        }
+    }
+
+    fn allocated(&self, values: usize) {
+        if let Some(meter) = &self.meter {
+            meter.borrow_mut().stats.total_values_allocated += values;
+        }
+    }
+
+    // Runs the nested evaluations of an application's operands
+    fn nested<T>(&self, run: impl FnOnce() -> T) -> T {
+        if let Some(meter) = &self.meter {
+            meter.borrow_mut().push();
+        }
+        let result = run();
+        if let Some(meter) = &self.meter {
+            meter.borrow_mut().pop();
+        }
+        result
+    }
+}
+
 fn eval_with_trampoline(expr: Expr, context: Context) -> Trampoline {
+    eval_in(expr, context, Rc::new(Evaluator::default()))
+}
+
+fn eval_in(expr: Expr, context: Context, evaluator: Rc<Evaluator>) -> Trampoline {
+    let context = evaluator.enter(&expr, context);
     match expr {
         Expr::Var(name) => Trampoline::Complete(
             context
@@ -39,19 +597,253 @@ fn eval_with_trampoline(expr: Expr, context: Context) -> Trampoline {
                 .cloned()
                 .unwrap_or_else(|| panic!("Variable {} not found", name)),
         ),
-        Expr::Abs(param, body) => Trampoline::Complete(Value::VClosure(context, param, body)),
-        Expr::App(f, arg) => Trampoline::Continue(Box::new(move || {
-            let func_value_tramp = eval_with_trampoline(*f.clone(), context.clone());
-            let arg_value_tramp = eval_with_trampoline(*arg.clone(), context.clone());
-            match func_value_tramp.run() {
-                Value::VClosure(ctx, param, body) => {
-                    let arg_value = arg_value_tramp.run();
-                    let mut new_ctx = ctx;
-                    new_ctx.insert(param, arg_value);
-                    eval_with_trampoline(*body, new_ctx)
+        Expr::Abs(param, body) => {
+            evaluator.allocated(1);
+            Trampoline::Complete(Value::VClosure(
+                evaluator.capture(context),
+                param,
+                Rc::from(body),
+            ))
+        }
+        Expr::Lit(n) => Trampoline::Complete(Value::VInt(n)),
+        Expr::LetRecGroup(bindings, body) => {
+            let knots = letrec_knots(&bindings, context.clone(), &evaluator);
+            evaluator.allocated(knots.len());
+            let mut context = context;
+            context.extend(knots);
+            eval_in(*body, context, evaluator)
+        }
+        // Only the branch the condition picks is evaluated, as for `c t e`
+        sugar @ Expr::If(..) => eval_in(sugar.expand(), context, evaluator),
+        Expr::App(f, arg) => {
+            // Errors from the nested runs are passed on rather than panicking
+            let step = move || {
+                // The argument is only evaluated once the function is known
+                // to be one
+                let operands = evaluator.nested(|| {
+                    let func_value = eval_in(*f, context.clone(), evaluator.clone()).try_run()?;
+                    if let Value::VInt(_) = func_value {
+                        return Err(EvalError::NotAFunction(func_value));
+                    }
+                    Ok((func_value, eval_argument(*arg, context, &evaluator)?))
+                });
+                let (func_value, arg_value) = match operands {
+                    Ok(operands) => operands,
+                    Err(err) => return Trampoline::Failed(err),
+                };
+                match func_value {
+                    Value::VClosure(ctx, param, body) => {
+                        let mut new_ctx = Rc::unwrap_or_clone(ctx);
+                        new_ctx.insert(param, arg_value);
+                        eval_in(Rc::unwrap_or_clone(body), new_ctx, evaluator)
+                    }
+                    Value::VNeutral(head, mut args) => {
+                        evaluator.allocated(1);
+                        args.push(arg_value);
+                        match evaluator.primitives.saturate(head, args) {
+                            Ok(value) => Trampoline::Complete(value),
+                            Err(err) => Trampoline::Failed(err),
+                        }
+                    }
+                    Value::VInt(_) => unreachable!(),
                 }
+            };
+            if take_inline() {
+                step()
+            } else {
+                Trampoline::Continue(Box::new(step))
+            }
+        }
+    }
+}
+
+// Binds each name of a `letrec` group to `\v. (letrec ... in e) v`, `e`
+// being its definition, closed over the context the group is evaluated in.
+// The knots share that one context, and applying one evaluates the
+// definition with the group bound afresh, so no value refers back to
+// itself. As with the encoding in `desugar`, the definitions should be
+// abstractions: anything else is evaluated again on every use.
+fn letrec_knots(
+    bindings: &[(String, Expr)],
+    context: Context,
+    evaluator: &Evaluator,
+) -> Vec<(String, Value)> {
+    let mut avoid = HashSet::new();
+    for (name, expr) in bindings {
+        avoid.insert(name.clone());
+        avoid.extend(free_vars(expr));
+    }
+    let param = subst::fresh_name("v", &avoid);
+    let shared = evaluator.capture(context);
+    bindings
+        .iter()
+        .map(|(name, expr)| {
+            let group = Expr::LetRecGroup(bindings.to_vec(), Box::new(expr.clone()));
+            let knot = app(group, var(&param));
+            let value = Value::VClosure(shared.clone(), param.clone(), Rc::new(knot));
+            (name.clone(), value)
+        })
+        .collect()
+}
+
+// The value of an application's argument. With `memoize`, an argument
+// syntactically equal to one already evaluated under the same bindings, as
+// the second `a` in `f a a`, reuses that value. Arguments are still
+// evaluated before the body, so call-by-value is kept for terminating terms.
+fn eval_argument(
+    arg: Expr,
+    context: Context,
+    evaluator: &Rc<Evaluator>,
+) -> Result<Value, EvalError> {
+    #[cfg(feature = "memoize")]
+    if let (Some(memo), Expr::App(..)) = (&evaluator.memo, &arg) {
+        return memo::eval_argument(arg, context, evaluator.clone(), memo);
+    }
+    eval_in(arg, context, evaluator.clone()).try_run()
+}
+
+// Evaluates with the trampoline, reporting unbound variables instead of
+// panicking. Every closure captures the context it was built in, so checking
+// the free variables up front is enough.
+fn eval(expr: Expr, context: Context) -> Result<Value, EvalError> {
+    eval_with_policy(expr, context, OpenVarPolicy::Error)
+}
+
+// Like `eval`, for evaluations watched from another thread: `counter` shows
+// progress and setting `cancel` stops with `Cancelled`. Values aren't `Send`,
+// so the evaluation itself stays on the calling thread.
+fn eval_with_monitor(
+    expr: Expr,
+    ctx: Context,
+    counter: Arc<AtomicUsize>,
+    cancel: Arc<AtomicBool>,
+) -> Result<Value, EvalError> {
+    let mut unbound: Vec<_> = free_vars(&expr)
+        .into_iter()
+        .filter(|name| !ctx.contains_key(name))
+        .collect();
+    unbound.sort();
+    if let Some(name) = unbound.into_iter().next() {
+        return Err(EvalError::UnboundVariable(name));
+    }
+    eval_with_trampoline(expr, ctx).run_monitored(&counter, &cancel)
+}
+
+// Applies one evaluated value to another without quoting either back into a
+// term. Neutral heads take the argument onto their spine, as in `eval`, and
+// no primitives are applied.
+fn apply_value(func: Value, arg: Value) -> Result<Value, EvalError> {
+    match func {
+        Value::VClosure(ctx, param, body) => {
+            let mut new_ctx = Rc::unwrap_or_clone(ctx);
+            new_ctx.insert(param, arg);
+            eval_with_trampoline(Rc::unwrap_or_clone(body), new_ctx).try_run()
+        }
+        Value::VNeutral(head, mut args) => {
+            args.push(arg);
+            Ok(Value::VNeutral(head, args))
+        }
+        value @ Value::VInt(_) => Err(EvalError::NotAFunction(value)),
+    }
+}
+
+// Wraps `value` as a Rust function that applies it with `apply_value` on
+// every call, so a non-function fails when called rather than here
+fn as_rust_fn(value: Value) -> Box<dyn Fn(Value) -> Result<Value, EvalError>> {
+    Box::new(move |arg| apply_value(value.clone(), arg))
+}
+
+// What `eval_with_policy` does with a variable that is not in the context
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum OpenVarPolicy {
+    Error,
+    // The variable is an uninterpreted constant, and applying it builds a
+    // `VNeutral` spine
+    Constant,
+}
+
+fn eval_with_policy(
+    expr: Expr,
+    mut context: Context,
+    policy: OpenVarPolicy,
+) -> Result<Value, EvalError> {
+    let mut unbound: Vec<_> = free_vars(&expr)
+        .into_iter()
+        .filter(|name| !context.contains_key(name))
+        .collect();
+    unbound.sort();
+    match policy {
+        OpenVarPolicy::Error => {
+            if let Some(name) = unbound.into_iter().next() {
+                return Err(EvalError::UnboundVariable(name));
+            }
+        }
+        OpenVarPolicy::Constant => {
+            for name in unbound {
+                context.insert(name.clone(), Value::VNeutral(name, Vec::new()));
             }
-        })),
+        }
+    }
+    eval_with_trampoline(expr, context).try_run()
+}
+
+// The normal form of `expr`, with variables missing from `ctx` left as
+// constants. Closures are read back by applying them to a fresh constant, so
+// this diverges on terms without a normal form, and arguments are evaluated
+// even when they are never used.
+fn partial_eval(expr: Expr, ctx: Context) -> Result<Expr, EvalError> {
+    let value = eval_with_policy(expr, ctx, OpenVarPolicy::Constant)?;
+    read_back(&value)
+}
+
+// `\x1 ... xn. h a1 ... am` with each `ai` in the same form and `h` a bound
+// variable, a constant left from a variable missing in `ctx`, or a literal
+// when `m` is 0. That is what `partial_eval` reads back, so this names the
+// shape for callers that rely on it.
+fn spine_normal_form(expr: Expr, ctx: Context) -> Result<Expr, EvalError> {
+    partial_eval(expr, ctx)
+}
+
+// Binders keep their source names unless the closure mentions a constant of
+// the same name, which the binder would capture
+fn read_back(value: &Value) -> Result<Expr, EvalError> {
+    match value {
+        Value::VNeutral(head, args) => args.iter().try_fold(Expr::Var(head.clone()), |f, arg| {
+            Ok(app(f, read_back(arg)?))
+        }),
+        Value::VInt(n) => Ok(Expr::Lit(*n)),
+        Value::VClosure(ctx, param, body) => {
+            let constants = constants_in(value);
+            let name = if constants.contains(param) {
+                subst::fresh_name(param, &constants)
+            } else {
+                param.clone()
+            };
+            let mut ctx = Context::clone(ctx);
+            ctx.insert(param.clone(), Value::VNeutral(name.clone(), Vec::new()));
+            let body = eval_with_policy(Expr::clone(body), ctx, OpenVarPolicy::Constant)?;
+            Ok(abs(&name, read_back(&body)?))
+        }
+    }
+}
+
+// The constants a value's read-back form can refer to
+fn constants_in(value: &Value) -> HashSet<String> {
+    match value {
+        Value::VNeutral(head, args) => {
+            let mut names = HashSet::from([head.clone()]);
+            names.extend(args.iter().flat_map(constants_in));
+            names
+        }
+        Value::VInt(_) => HashSet::new(),
+        Value::VClosure(ctx, param, body) => free_vars(body)
+            .into_iter()
+            .filter(|name| name != param)
+            .flat_map(|name| match ctx.get(&name) {
+                Some(value) => constants_in(value),
+                None => HashSet::from([name]),
+            })
+            .collect(),
     }
 }
 
@@ -61,22 +853,294 @@ fn eval_without_trampoline(expr: Expr, context: HashMap<String, Value>) -> Value
             Some(value) => value.clone(),
             None => panic!("Variable {} not found", name),
         },
-        Expr::Abs(param, body) => Value::VClosure(context, param, body),
+        Expr::Abs(param, body) => Value::VClosure(Rc::new(context), param, Rc::from(body)),
+        Expr::Lit(n) => Value::VInt(n),
+        sugar @ (Expr::LetRecGroup(..) | Expr::If(..)) => {
+            eval_without_trampoline(sugar.expand(), context)
+        }
         Expr::App(f, arg) => {
-            let Value::VClosure(ctx, param, body) = eval_without_trampoline(*f, context.clone());
+            let (ctx, param, body) = match eval_without_trampoline(*f, context.clone()) {
+                Value::VClosure(ctx, param, body) => (ctx, param, body),
+                Value::VNeutral(head, mut args) => {
+                    args.push(eval_without_trampoline(*arg, context));
+                    return Value::VNeutral(head, args);
+                }
+                value @ Value::VInt(_) => panic!("{}", EvalError::NotAFunction(value)),
+            };
             let arg_value = eval_without_trampoline(*arg, context.clone());
 
-            let mut new_ctx = ctx;
+            let mut new_ctx = Rc::unwrap_or_clone(ctx);
 
             new_ctx.insert(param, arg_value);
 
-            eval_without_trampoline(*body, new_ctx)
+            eval_without_trampoline(Rc::unwrap_or_clone(body), new_ctx)
         }
     }
 }
 
+// Like `eval_without_trampoline`, calling `hook` with every expression and
+// the context it is evaluated in before evaluating it. The trampoline's
+// thunks can't borrow the hook, so this one recurses.
+fn eval_with_hook(expr: Expr, ctx: Context, hook: &mut dyn FnMut(&Expr, &Context)) -> Value {
+    hook(&expr, &ctx);
+    match expr {
+        Expr::Var(name) => match ctx.get(&name) {
+            Some(value) => value.clone(),
+            None => panic!("Variable {} not found", name),
+        },
+        Expr::Abs(param, body) => Value::VClosure(Rc::new(ctx), param, Rc::from(body)),
+        Expr::Lit(n) => Value::VInt(n),
+        sugar @ (Expr::LetRecGroup(..) | Expr::If(..)) => eval_with_hook(sugar.expand(), ctx, hook),
+        Expr::App(f, arg) => {
+            let (captured, param, body) = match eval_with_hook(*f, ctx.clone(), hook) {
+                Value::VClosure(captured, param, body) => (captured, param, body),
+                Value::VNeutral(head, mut args) => {
+                    args.push(eval_with_hook(*arg, ctx, hook));
+                    return Value::VNeutral(head, args);
+                }
+                value @ Value::VInt(_) => panic!("{}", EvalError::NotAFunction(value)),
+            };
+            let arg_value = eval_with_hook(*arg, ctx, hook);
+            let mut new_ctx = Rc::unwrap_or_clone(captured);
+            new_ctx.insert(param, arg_value);
+            eval_with_hook(Rc::unwrap_or_clone(body), new_ctx, hook)
+        }
+    }
+}
+
+// A value paired with what `eval_tracking_reads` needs for it: each binding
+// in a closure's context carries the reads that produced the bound value.
+// Values from the caller's context stay `Plain` until they are applied.
+#[derive(Clone)]
+enum Tracked {
+    Plain(Value),
+    Closure(Rc<TrackedContext>, String, Rc<Expr>),
+    Neutral(String, Vec<Tracked>),
+}
+
+type TrackedContext = HashMap<String, (Tracked, HashSet<String>)>;
+
+impl Tracked {
+    fn open(self) -> Tracked {
+        match self {
+            Tracked::Plain(Value::VClosure(captured, param, body)) => {
+                let captured = Rc::unwrap_or_clone(captured)
+                    .into_iter()
+                    .map(|(name, value)| (name, (Tracked::Plain(value), HashSet::new())))
+                    .collect();
+                Tracked::Closure(Rc::new(captured), param, body)
+            }
+            Tracked::Plain(Value::VNeutral(head, args)) => {
+                Tracked::Neutral(head, args.into_iter().map(Tracked::Plain).collect())
+            }
+            tracked => tracked,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        match self {
+            Tracked::Plain(value) => value,
+            Tracked::Closure(captured, param, body) => {
+                let captured = Rc::unwrap_or_clone(captured)
+                    .into_iter()
+                    .map(|(name, (value, _))| (name, value.into_value()))
+                    .collect();
+                Value::VClosure(Rc::new(captured), param, body)
+            }
+            Tracked::Neutral(head, args) => {
+                Value::VNeutral(head, args.into_iter().map(Tracked::into_value).collect())
+            }
+        }
+    }
+}
+
+// Evaluates like `eval_without_trampoline` and returns the names whose
+// lookups reach the result, whether they came from `ctx` or from a closure's
+// parameter. Arguments are still evaluated first, but the lookups made for
+// one only count once its parameter is looked up, so a discarded argument
+// adds nothing. Neither do the bindings a closure in the result has captured
+// but not yet used.
+fn eval_tracking_reads(expr: Expr, ctx: Context) -> (Value, HashSet<String>) {
+    let ctx = ctx
+        .into_iter()
+        .map(|(name, value)| (name, (Tracked::Plain(value), HashSet::new())))
+        .collect();
+    let (value, reads) = eval_reads(expr, Rc::new(ctx));
+    (value.into_value(), reads)
+}
+
+fn eval_reads(expr: Expr, ctx: Rc<TrackedContext>) -> (Tracked, HashSet<String>) {
+    match expr {
+        Expr::Var(name) => match ctx.get(&name) {
+            Some((value, reads)) => {
+                let mut reads = reads.clone();
+                reads.insert(name);
+                (value.clone(), reads)
+            }
+            None => panic!("Variable {} not found", name),
+        },
+        Expr::Abs(param, body) => (Tracked::Closure(ctx, param, Rc::from(body)), HashSet::new()),
+        Expr::Lit(n) => (Tracked::Plain(Value::VInt(n)), HashSet::new()),
+        sugar @ (Expr::LetRecGroup(..) | Expr::If(..)) => eval_reads(sugar.expand(), ctx),
+        Expr::App(f, arg) => {
+            let (f, mut reads) = eval_reads(*f, ctx.clone());
+            match f.open() {
+                Tracked::Closure(captured, param, body) => {
+                    let arg = eval_reads(*arg, ctx);
+                    let mut new_ctx = Rc::unwrap_or_clone(captured);
+                    new_ctx.insert(param, arg);
+                    let (value, body_reads) =
+                        eval_reads(Rc::unwrap_or_clone(body), Rc::new(new_ctx));
+                    reads.extend(body_reads);
+                    (value, reads)
+                }
+                Tracked::Neutral(head, mut args) => {
+                    let (arg, arg_reads) = eval_reads(*arg, ctx);
+                    args.push(arg);
+                    reads.extend(arg_reads);
+                    (Tracked::Neutral(head, args), reads)
+                }
+                Tracked::Plain(value) => panic!("{}", EvalError::NotAFunction(value)),
+            }
+        }
+    }
+}
+
+// Evaluates like `eval_with_hook` and records each closure as it is built:
+// its parameter and the names in the context it captures, sorted. Closures
+// capture the whole context, not only the names their body uses.
+fn eval_capturing(expr: Expr, ctx: Context) -> (Value, Vec<(String, Vec<String>)>) {
+    let mut captures = Vec::new();
+    let value = eval_with_hook(expr, ctx, &mut |expr, ctx| {
+        if let Expr::Abs(param, _) = expr {
+            let mut names: Vec<_> = ctx.keys().cloned().collect();
+            names.sort();
+            captures.push((param.clone(), names));
+        }
+    });
+    (value, captures)
+}
+
+// Like `eval_without_trampoline`, but fails with `RecursionLimit` instead of
+// overflowing the native stack. A nesting level takes roughly 1-2 KiB of
+// stack in debug builds and a few hundred bytes in release builds, so keep
+// `max_depth` around 500 on the default 2 MiB stack of a spawned thread and
+// around 2_000 on the 8 MiB main thread stack.
+fn eval_recursive_bounded(expr: Expr, ctx: Context, max_depth: usize) -> Result<Value, EvalError> {
+    let limits = EvalLimits {
+        max_depth,
+        max_steps: usize::MAX,
+    };
+    eval_limited(expr, ctx, &limits)
+}
+
+// Bounds for `eval_limited`: how deep it may recurse, as for
+// `eval_recursive_bounded`, and how many beta steps it may take
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct EvalLimits {
+    max_depth: usize,
+    max_steps: usize,
+}
+
+fn eval_limited(expr: Expr, ctx: Context, limits: &EvalLimits) -> Result<Value, EvalError> {
+    fn go(
+        expr: Expr,
+        context: Context,
+        depth: usize,
+        steps: &mut usize,
+        limits: &EvalLimits,
+    ) -> Result<Value, EvalError> {
+        if depth > limits.max_depth {
+            return Err(EvalError::RecursionLimit(limits.max_depth));
+        }
+        match expr {
+            Expr::Var(name) => match context.get(&name) {
+                Some(value) => Ok(value.clone()),
+                None => Err(EvalError::UnboundVariable(name)),
+            },
+            Expr::Abs(param, body) => Ok(Value::VClosure(Rc::new(context), param, Rc::from(body))),
+            Expr::Lit(n) => Ok(Value::VInt(n)),
+            sugar @ (Expr::LetRecGroup(..) | Expr::If(..)) => {
+                go(sugar.expand(), context, depth, steps, limits)
+            }
+            Expr::App(f, arg) => {
+                let (ctx, param, body) = match go(*f, context.clone(), depth + 1, steps, limits)? {
+                    Value::VClosure(ctx, param, body) => (ctx, param, body),
+                    Value::VNeutral(head, mut args) => {
+                        args.push(go(*arg, context, depth + 1, steps, limits)?);
+                        return Ok(Value::VNeutral(head, args));
+                    }
+                    value @ Value::VInt(_) => return Err(EvalError::NotAFunction(value)),
+                };
+                let arg_value = go(*arg, context, depth + 1, steps, limits)?;
+
+                if *steps == limits.max_steps {
+                    return Err(EvalError::StepLimit(limits.max_steps));
+                }
+                *steps += 1;
+
+                let mut new_ctx = Rc::unwrap_or_clone(ctx);
+
+                new_ctx.insert(param, arg_value);
+
+                go(Rc::unwrap_or_clone(body), new_ctx, depth + 1, steps, limits)
+            }
+        }
+    }
+
+    go(expr, ctx, 0, &mut 0, limits)
+}
+
+// Evaluates every term on its own copy of `base`, so one failing term
+// doesn't stop the rest of the batch
+fn eval_many<I: IntoIterator<Item = Expr>>(
+    terms: I,
+    base: &Context,
+    limits: &EvalLimits,
+) -> Vec<Result<Value, EvalError>> {
+    terms
+        .into_iter()
+        .map(|term| eval_limited(term, base.clone(), limits))
+        .collect()
+}
+
 fn main() {
-    println!("Trampoline");
+    // `--dump-ast <term>` prints the parsed term as JSON instead of starting
+    // the REPL
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(i) = args.iter().position(|arg| arg == "--dump-ast") {
+        let Some(src) = args.get(i + 1) else {
+            eprintln!("Error: --dump-ast needs a term");
+            std::process::exit(2);
+        };
+        match parser::parse(src) {
+            Ok(expr) => println!(
+                "{}",
+                serde_json::to_string(&expr).expect("terms always serialize")
+            ),
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let json = args.iter().any(|arg| arg == "--json");
+    let formatter: Box<dyn ResultFormatter> = if json {
+        Box::new(JsonFormatter)
+    } else {
+        println!("Trampoline");
+        Box::new(PrettyFormatter(pretty::PrettyOpts::for_stdout()))
+    };
+
+    let mut session = Session::new();
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        if let Some(output) = repl::respond(&mut session, &line, formatter.as_ref()) {
+            println!("{}", output);
+        }
+    }
 }
 
 #[test]
@@ -130,6 +1194,110 @@ pub fn check_results() {
     );
 }
 
+#[test]
+fn bounded_recursion_reports_limit() {
+    // id (id (... (id id)))
+    let id = abs("x", var("x"));
+    let deep = (0..200).fold(id.clone(), |inner, _| app(id.clone(), inner));
+
+    assert_eq!(
+        eval_recursive_bounded(deep.clone(), HashMap::new(), 50),
+        Err(EvalError::RecursionLimit(50))
+    );
+    assert_eq!(
+        eval_recursive_bounded(deep.clone(), HashMap::new(), 1_000),
+        Ok(eval_without_trampoline(deep, HashMap::new()))
+    );
+}
+
+#[test]
+fn quote_substitutes_captured_bindings() {
+    let k = abs("x", abs("y", var("x")));
+    let id = abs("z", var("z"));
+    let value = eval_with_trampoline(app(k, id.clone()), HashMap::new()).run();
+
+    assert_eq!(quote(&value), abs("y", id));
+    assert_eq!(quote(&value).to_string(), "\\y. \\z. z");
+}
+
+#[test]
+fn value_key_identifies_equal_closures() {
+    let id = abs("z", var("z"));
+    // \x. (\z. z) x once its captured `g` is read back
+    let wrapped = eval_with_trampoline(
+        app(abs("g", abs("x", app(var("g"), var("x")))), id.clone()),
+        HashMap::new(),
+    )
+    .run();
+    let plain = eval_with_trampoline(abs("a", var("a")), HashMap::new()).run();
+    let k = eval_with_trampoline(abs("a", abs("b", var("a"))), HashMap::new()).run();
+    let ki = eval_with_trampoline(app(abs("x", abs("y", var("x"))), id), HashMap::new()).run();
+
+    assert_eq!(value_key(&wrapped), value_key(&plain));
+    assert_eq!(value_key(&plain), "\\v0. v0");
+    assert_ne!(value_key(&k), value_key(&ki));
+
+    let keys: HashSet<_> = [&wrapped, &plain, &k, &ki]
+        .into_iter()
+        .map(value_key)
+        .collect();
+    assert_eq!(keys.len(), 3);
+}
+
+#[test]
+fn display_parenthesizes_only_where_needed() {
+    let term = app(
+        app(abs("x", var("x")), var("y")),
+        app(var("f"), abs("z", var("z"))),
+    );
+
+    assert_eq!(term.to_string(), "(\\x. x) y (f (\\z. z))");
+    assert_eq!(parser::parse(&term.to_string()), Ok(term));
+}
+
+#[test]
+fn accessors_match_only_their_variant() {
+    let x = var("x");
+    let id = abs("x", var("x"));
+    let applied = app(id.clone(), var("y"));
+
+    assert_eq!(x.as_var(), Some("x"));
+    assert_eq!(id.as_var(), None);
+    assert_eq!(id.as_abs(), Some(("x", &x)));
+    assert_eq!(applied.as_abs(), None);
+    assert_eq!(applied.as_app(), Some((&id, &var("y"))));
+    assert_eq!(x.as_app(), None);
+    assert!(id.is_value());
+    assert!(!x.is_value());
+    assert!(!applied.is_value());
+}
+
+#[test]
+fn progress_callback_can_cancel() {
+    let omega = app(
+        abs("x", app(var("x"), var("x"))),
+        abs("x", app(var("x"), var("x"))),
+    );
+    let mut seen = Vec::new();
+    let result =
+        eval_with_trampoline(omega, HashMap::new()).run_with_progress(10, &mut |bounces| {
+            seen.push(bounces);
+            if seen.len() == 3 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+
+    assert_eq!(result, None);
+    assert_eq!(seen, vec![10, 20, 30]);
+
+    let id = abs("x", var("x"));
+    let result = eval_with_trampoline(app(id.clone(), id.clone()), HashMap::new())
+        .run_with_progress(1, &mut |_| ControlFlow::Continue(()));
+    assert_eq!(result, Some(eval_with_trampoline(id, HashMap::new()).run()));
+}
+
 // Uncomment this if you want to see that without trampoline stack overflow will happen
 // #[test]
 // fn stack_overflow() {
@@ -175,3 +1343,460 @@ fn not_stack_overflow() {
 
     eval_with_trampoline(looping_expr, HashMap::new()).run();
 }
+
+#[test]
+fn partial_eval_keeps_unbound_heads() {
+    // (\x. f x) a
+    let term = app(abs("x", app(var("f"), var("x"))), var("a"));
+    assert_eq!(
+        partial_eval(term.clone(), HashMap::new()),
+        Ok(app(var("f"), var("a")))
+    );
+    assert_eq!(
+        eval(term, HashMap::new()),
+        Err(EvalError::UnboundVariable("a".to_string()))
+    );
+
+    // (\g. \a. (\y. y) g a) a, where the binder has to be renamed away from
+    // the free `a` substituted under it
+    let term = app(
+        abs(
+            "g",
+            abs("a", app(app(abs("y", var("y")), var("g")), var("a"))),
+        ),
+        var("a"),
+    );
+    assert_eq!(
+        partial_eval(term, HashMap::new()),
+        Ok(abs("#a0", app(var("a"), var("#a0"))))
+    );
+}
+
+#[test]
+fn partial_eval_keeps_binder_names_without_capture() {
+    // (\f. \x. f x) g
+    let term = app(abs("f", abs("x", app(var("f"), var("x")))), var("g"));
+    assert_eq!(
+        partial_eval(term, HashMap::new()),
+        Ok(abs("x", app(var("g"), var("x"))))
+    );
+
+    // (\y. \x. x) x, where the free `x` is discarded before it could clash
+    let term = app(abs("y", abs("x", var("x"))), var("x"));
+    assert_eq!(partial_eval(term, HashMap::new()), Ok(abs("x", var("x"))));
+}
+
+#[test]
+fn race_returns_the_strategy_that_terminates() {
+    use crate::normalize::{trampoline_ao, trampoline_no};
+
+    // (\x. \y. y) ((\x. x x) (\x. x x))
+    let omega = app(
+        abs("x", app(var("x"), var("x"))),
+        abs("x", app(var("x"), var("x"))),
+    );
+    let term = app(abs("x", abs("y", var("y"))), omega);
+
+    let value = race(trampoline_ao(term.clone()), trampoline_no(term.clone()), 10);
+    assert_eq!(quote(&value), abs("y", var("y")));
+    let value = race(trampoline_no(term.clone()), trampoline_ao(term), 10);
+    assert_eq!(quote(&value), abs("y", var("y")));
+}
+
+#[test]
+fn applying_a_literal_is_not_a_function() {
+    let term = app(Expr::Lit(3), abs("x", var("x")));
+
+    assert_eq!(
+        eval(term.clone(), HashMap::new()),
+        Err(EvalError::NotAFunction(Value::VInt(3)))
+    );
+    assert_eq!(
+        eval_recursive_bounded(term, HashMap::new(), 100),
+        Err(EvalError::NotAFunction(Value::VInt(3)))
+    );
+    assert_eq!(
+        EvalError::NotAFunction(Value::VInt(3)).to_string(),
+        "3 is not a function"
+    );
+    assert_eq!(
+        eval(app(abs("n", var("n")), Expr::Lit(3)), HashMap::new()),
+        Ok(Value::VInt(3))
+    );
+    // Inside an argument, so found by a nested run
+    let nested = app(abs("y", var("y")), app(Expr::Lit(3), abs("y", var("y"))));
+    assert_eq!(
+        eval(nested, HashMap::new()),
+        Err(EvalError::NotAFunction(Value::VInt(3)))
+    );
+}
+
+#[test]
+fn eval_many_reports_each_term_separately() {
+    use crate::prelude::prelude_context;
+
+    let omega = app(
+        abs("x", app(var("x"), var("x"))),
+        abs("x", app(var("x"), var("x"))),
+    );
+    let limits = EvalLimits {
+        max_depth: 500,
+        max_steps: 100,
+    };
+    let terms = vec![
+        app(var("I"), var("K")),
+        var("missing"),
+        omega,
+        app(app(var("K"), var("I")), var("S")),
+    ];
+
+    let base = prelude_context();
+    let results = eval_many(terms, &base, &limits);
+    assert_eq!(results.len(), 4);
+    assert_eq!(results[0], Ok(base["K"].clone()));
+    assert_eq!(
+        results[1],
+        Err(EvalError::UnboundVariable("missing".to_string()))
+    );
+    assert_eq!(results[2], Err(EvalError::StepLimit(100)));
+    assert_eq!(results[3], Ok(base["I"].clone()));
+}
+
+#[test]
+fn paths_reach_and_replace_subterms() {
+    // \f. f ((\x. x) y)
+    let term = abs("f", app(var("f"), app(abs("x", var("x")), var("y"))));
+    let path = [Dir::AbsBody, Dir::AppArg];
+
+    assert_eq!(
+        get_at(&term, &path),
+        Some(&app(abs("x", var("x")), var("y")))
+    );
+    assert_eq!(get_at(&term, &[Dir::AppFun]), None);
+    assert_eq!(get_at(&term, &[]), Some(&term));
+
+    assert_eq!(
+        replace_at(term.clone(), &path, var("y")),
+        Some(abs("f", app(var("f"), var("y"))))
+    );
+    assert_eq!(
+        replace_at(term, &[Dir::AbsBody, Dir::AbsBody], var("y")),
+        None
+    );
+}
+
+#[test]
+fn hook_sees_every_evaluated_expression() {
+    // (\x. x) y
+    let term = app(abs("x", var("x")), var("y"));
+    let y = eval(abs("z", var("z")), HashMap::new()).unwrap();
+    let ctx = HashMap::from([("y".to_string(), y.clone())]);
+
+    let mut seen = Vec::new();
+    let value = eval_with_hook(term.clone(), ctx.clone(), &mut |expr, ctx| {
+        seen.push((expr.to_string(), ctx.contains_key("x")))
+    });
+
+    assert_eq!(value, eval(term, ctx).unwrap());
+    assert_eq!(
+        seen,
+        vec![
+            ("(\\x. x) y".to_string(), false),
+            ("\\x. x".to_string(), false),
+            ("y".to_string(), false),
+            ("x".to_string(), true),
+        ]
+    );
+}
+
+#[test]
+fn apply_value_continues_from_evaluated_values() {
+    use crate::church::church_numeral;
+
+    let id = eval(abs("x", var("x")), HashMap::new()).unwrap();
+    let two = eval(church_numeral(2), HashMap::new()).unwrap();
+    assert_eq!(apply_value(id.clone(), two.clone()), Ok(two.clone()));
+
+    // K applied to two values in turn keeps the first
+    let k = eval(abs("a", abs("b", var("a"))), HashMap::new()).unwrap();
+    let partial = apply_value(k, two.clone()).unwrap();
+    assert_eq!(apply_value(partial, id.clone()), Ok(two));
+
+    assert_eq!(
+        apply_value(Value::VInt(1), id),
+        Err(EvalError::NotAFunction(Value::VInt(1)))
+    );
+}
+
+#[test]
+fn lambda_values_can_be_called_from_rust() {
+    use crate::church::{church_numeral, succ};
+
+    let succ = as_rust_fn(eval(succ(), HashMap::new()).unwrap());
+    let two = eval(church_numeral(2), HashMap::new()).unwrap();
+    let three = succ(two).unwrap();
+    assert_eq!(u64::try_from(&three), Ok(3));
+    assert_eq!(u64::try_from(&succ(three).unwrap()), Ok(4));
+
+    let int = as_rust_fn(Value::VInt(5));
+    assert_eq!(
+        int(Value::VInt(1)),
+        Err(EvalError::NotAFunction(Value::VInt(5)))
+    );
+}
+
+#[test]
+fn canonical_renaming_identifies_alpha_equal_terms() {
+    // \a. \b. a b and \x. \y. x y
+    let ab = abs("a", abs("b", app(var("a"), var("b"))));
+    let xy = abs("x", abs("y", app(var("x"), var("y"))));
+    assert_ne!(ab, xy);
+    assert_eq!(rename_canonical(&ab), rename_canonical(&xy));
+    assert_eq!(
+        rename_canonical(&ab),
+        abs("v0", abs("v1", app(var("v0"), var("v1"))))
+    );
+
+    // A free v0 keeps its name and the binder skips it
+    let term = abs("x", app(var("x"), var("v0")));
+    assert_eq!(
+        rename_canonical(&term),
+        abs("v1", app(var("v1"), var("v0")))
+    );
+}
+
+#[test]
+fn cloning_a_closure_shares_its_context_and_body() {
+    use crate::prelude::prelude_context;
+
+    // K applied once captures `x` along with the whole prelude
+    let ctx = prelude_context();
+    let value = eval(app(var("K"), var("I")), ctx.clone()).unwrap();
+    let copy = value.clone();
+
+    let (Value::VClosure(ctx_a, _, body_a), Value::VClosure(ctx_b, _, body_b)) = (&value, &copy)
+    else {
+        panic!("expected closures")
+    };
+    assert!(Rc::ptr_eq(ctx_a, ctx_b));
+    assert!(Rc::ptr_eq(body_a, body_b));
+    assert_eq!(Rc::strong_count(ctx_a), 2);
+    assert_eq!(quote(&copy), quote(&value));
+    assert_eq!(apply_value(copy, ctx["S"].clone()), Ok(ctx["I"].clone()));
+}
+
+#[test]
+fn batched_run_matches_run() {
+    use crate::church::church_numeral;
+    use crate::vm::church_examples;
+
+    // 500 applications of the identity, nested to the right
+    let id = abs("z", var("z"));
+    let deep = app(app(church_numeral(500), abs("y", var("y"))), id);
+
+    for expr in church_examples().into_iter().chain([deep]) {
+        let expected = eval_with_trampoline(expr.clone(), HashMap::new()).run();
+        for batch in [0, 1, 16, 256] {
+            let value = eval_with_trampoline(expr.clone(), HashMap::new()).run_batched(batch);
+            assert_eq!(value, expected, "batch {} of {}", batch, expr);
+        }
+    }
+    // Nothing leaks into later unbatched runs
+    assert!(!take_inline());
+}
+
+// Run with `cargo test --release -- --ignored --nocapture`
+#[test]
+#[ignore]
+fn bench_run_against_run_batched() {
+    use std::time::Instant;
+
+    use crate::vm::church_examples;
+
+    const ROUNDS: usize = 1_000;
+
+    let programs = church_examples();
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for expr in &programs {
+            eval_with_trampoline(expr.clone(), HashMap::new()).run();
+        }
+    }
+    let unbatched = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for expr in &programs {
+            eval_with_trampoline(expr.clone(), HashMap::new()).run_batched(64);
+        }
+    }
+    let batched = start.elapsed();
+
+    println!("run: {:?}, run_batched(64): {:?}", unbatched, batched);
+}
+
+#[test]
+fn tracking_reads_skips_the_discarded_binding() {
+    let mut ctx = HashMap::new();
+    ctx.insert(
+        "const".to_string(),
+        eval(abs("x", abs("y", var("x"))), HashMap::new()).unwrap(),
+    );
+    ctx.insert(
+        "a".to_string(),
+        eval(abs("p", var("p")), HashMap::new()).unwrap(),
+    );
+    ctx.insert(
+        "b".to_string(),
+        eval(abs("q", var("q")), HashMap::new()).unwrap(),
+    );
+    ctx.insert(
+        "unused".to_string(),
+        eval(abs("u", var("u")), HashMap::new()).unwrap(),
+    );
+
+    let term = app(app(var("const"), var("a")), var("b"));
+    let (value, reads) = eval_tracking_reads(term, ctx.clone());
+
+    assert_eq!(value, ctx["a"]);
+    // `b` is evaluated too, but only `x` is used from the body
+    let expected = ["const", "a", "x"].map(String::from);
+    assert_eq!(reads, HashSet::from(expected));
+
+    // The same with the argument discarded inside the term itself
+    let term = app(app(abs("x", abs("y", var("y"))), var("a")), var("b"));
+    let (value, reads) = eval_tracking_reads(term, ctx.clone());
+    assert_eq!(value, ctx["b"]);
+    assert_eq!(reads, HashSet::from(["b", "y"].map(String::from)));
+
+    // Arguments of a free variable are part of the result
+    ctx.insert(
+        "f".to_string(),
+        Value::VNeutral("f".to_string(), Vec::new()),
+    );
+    let (_, reads) = eval_tracking_reads(app(var("f"), var("unused")), ctx);
+    assert_eq!(reads, HashSet::from(["f", "unused"].map(String::from)));
+}
+
+#[test]
+fn capturing_lists_each_closure_with_its_context() {
+    // (\x. \y. x) (\z. z) builds \x and \z in the empty context, then \y
+    // with `x` bound
+    let term = app(abs("x", abs("y", var("x"))), abs("z", var("z")));
+    let (value, captures) = eval_capturing(term, HashMap::new());
+
+    let Value::VClosure(captured, param, _) = &value else {
+        panic!("expected a closure")
+    };
+    assert_eq!(param, "y");
+    assert_eq!(captured.len(), 1);
+    assert_eq!(
+        captures,
+        vec![
+            ("x".to_string(), vec![]),
+            ("z".to_string(), vec![]),
+            ("y".to_string(), vec!["x".to_string()]),
+        ]
+    );
+}
+
+#[test]
+fn closed_builder_reports_free_variables() {
+    let k = abs_checked("x", abs_checked("y", var("x")));
+    assert_eq!(finish_closed(k.clone()), Ok(k));
+    assert_eq!(
+        finish_closed(abs_checked("x", var("y"))),
+        Err(vec!["y".to_string()])
+    );
+    assert_eq!(
+        finish_closed(app(var("g"), abs_checked("x", app(var("f"), var("x"))))),
+        Err(vec!["f".to_string(), "g".to_string()])
+    );
+    assert!(std::panic::catch_unwind(|| abs_checked("x y", var("x"))).is_err());
+}
+
+#[test]
+fn spine_normal_form_layers_binders_over_a_head() {
+    // \y. (\x. x) (g y)
+    let term = abs("y", app(abs("x", var("x")), app(var("g"), var("y"))));
+    assert_eq!(
+        spine_normal_form(term, HashMap::new()),
+        Ok(abs("y", app(var("g"), var("y"))))
+    );
+
+    // \f. (\a. \b. a b) f ((\z. z) c), with a bound head and an argument
+    // that is normalized in turn
+    let term = abs(
+        "f",
+        app(
+            app(abs("a", abs("b", app(var("a"), var("b")))), var("f")),
+            app(abs("z", var("z")), var("c")),
+        ),
+    );
+    assert_eq!(
+        spine_normal_form(term, HashMap::new()),
+        Ok(abs("f", app(var("f"), var("c"))))
+    );
+}
+
+#[test]
+fn monitor_can_cancel_omega_from_another_thread() {
+    let half = abs("x", app(var("x"), var("x")));
+    let omega = app(half.clone(), half);
+    let counter = Arc::new(AtomicUsize::new(0));
+    let cancel = Arc::new(AtomicBool::new(false));
+
+    let watcher = {
+        let (counter, cancel) = (counter.clone(), cancel.clone());
+        std::thread::spawn(move || {
+            while counter.load(Ordering::Relaxed) < 10_000 {
+                std::thread::yield_now();
+            }
+            cancel.store(true, Ordering::Relaxed);
+        })
+    };
+    let result = eval_with_monitor(omega, HashMap::new(), counter.clone(), cancel);
+    watcher.join().unwrap();
+
+    assert_eq!(result, Err(EvalError::Cancelled));
+    assert!(counter.load(Ordering::Relaxed) >= 10_000);
+
+    let id = abs("x", var("x"));
+    let idle = Arc::new(AtomicBool::new(false));
+    let value = eval_with_monitor(app(id.clone(), id.clone()), HashMap::new(), counter, idle);
+    assert_eq!(value, eval(id, HashMap::new()));
+}
+
+#[test]
+fn letrec_groups_evaluate_with_shared_knots() {
+    use crate::church::{church_numeral, decode_bool};
+    use crate::parser::parse;
+
+    let is_zero = "(\\n. n (\\x t f. f) (\\t f. t))";
+    let pred = "(\\n f x. n (\\g h. h (g f)) (\\u. x) (\\u. u))";
+    let source = format!(
+        "letrec even = \\n. {z} n (\\t f. t) (\\u. odd ({p} n) u) \
+         and odd = \\n. {z} n (\\t f. f) (\\u. even ({p} n) u) \
+         in \\k. k even odd",
+        z = is_zero,
+        p = pred
+    );
+    let group = parse(&source).unwrap();
+    let pair = eval(group, empty_context()).unwrap();
+    let Value::VClosure(ctx, _, _) = &pair else {
+        panic!("expected a closure")
+    };
+    let (Value::VClosure(even_ctx, ..), Value::VClosure(odd_ctx, ..)) = (&ctx["even"], &ctx["odd"])
+    else {
+        panic!("expected closures")
+    };
+    assert!(Rc::ptr_eq(even_ctx, odd_ctx));
+
+    let even = ctx["even"].clone();
+    let is_even = |n| {
+        let value = apply_value(even.clone(), eval(church_numeral(n), empty_context())?)?;
+        Ok::<_, EvalError>(decode_bool(&quote(&value), 1_000))
+    };
+    assert_eq!(is_even(3), Ok(Some(false)));
+    assert_eq!(is_even(4), Ok(Some(true)));
+}
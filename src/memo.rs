@@ -0,0 +1,198 @@
+// Shared evaluation of repeated arguments. With an `EvalMemo` the trampoline
+// looks up every application in argument position by the term and the
+// bindings of its free variables before evaluating it, so an argument
+// written twice, as in `f a a`, is evaluated once. Evaluation has no effects,
+// so a hit is always the value evaluating again would give.
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use crate::subst::free_vars;
+use crate::{eval_in, Context, EvalError, Evaluator, Expr, Value};
+
+#[derive(Default)]
+pub struct EvalMemo {
+    table: HashMap<MemoKey, Value>,
+    // Arguments evaluated and ones answered from the table
+    pub evaluations: usize,
+    pub hits: usize,
+}
+
+impl EvalMemo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+// The hash is taken once, when the key is built. Closures are compared by
+// the addresses of their context and body, which the key keeps alive, so
+// neither is walked again for a lookup.
+struct MemoKey {
+    hash: u64,
+    expr: Expr,
+    bindings: Vec<(String, Value)>,
+}
+
+impl MemoKey {
+    fn new(expr: &Expr, context: &Context) -> Self {
+        let mut bindings: Vec<_> = free_vars(expr)
+            .into_iter()
+            .filter_map(|name| Some((name.clone(), context.get(&name)?.clone())))
+            .collect();
+        bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut hasher = DefaultHasher::new();
+        expr.hash(&mut hasher);
+        for (name, value) in &bindings {
+            name.hash(&mut hasher);
+            hash_value(value, &mut hasher);
+        }
+        MemoKey {
+            hash: hasher.finish(),
+            expr: expr.clone(),
+            bindings,
+        }
+    }
+}
+
+impl Hash for MemoKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash);
+    }
+}
+
+impl PartialEq for MemoKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.hash == other.hash
+            && self.expr == other.expr
+            && self.bindings.len() == other.bindings.len()
+            && self
+                .bindings
+                .iter()
+                .zip(&other.bindings)
+                .all(|((a, x), (b, y))| a == b && same_value(x, y))
+    }
+}
+
+impl Eq for MemoKey {}
+
+fn hash_value(value: &Value, state: &mut impl Hasher) {
+    match value {
+        Value::VClosure(ctx, param, body) => {
+            Rc::as_ptr(ctx).hash(state);
+            param.hash(state);
+            Rc::as_ptr(body).hash(state);
+        }
+        Value::VNeutral(head, args) => {
+            head.hash(state);
+            for arg in args {
+                hash_value(arg, state);
+            }
+        }
+        Value::VInt(n) => n.hash(state),
+    }
+}
+
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::VClosure(c1, p1, b1), Value::VClosure(c2, p2, b2)) => {
+            Rc::ptr_eq(c1, c2) && p1 == p2 && Rc::ptr_eq(b1, b2)
+        }
+        (Value::VNeutral(h1, a1), Value::VNeutral(h2, a2)) => {
+            h1 == h2 && a1.len() == a2.len() && a1.iter().zip(a2).all(|(x, y)| same_value(x, y))
+        }
+        (Value::VInt(m), Value::VInt(n)) => m == n,
+        _ => false,
+    }
+}
+
+// The value of the argument `arg`, from `memo` if it was evaluated before.
+// A miss is run to completion here so its value can be stored.
+pub(crate) fn eval_argument(
+    arg: Expr,
+    context: Context,
+    evaluator: Rc<Evaluator>,
+    memo: &RefCell<EvalMemo>,
+) -> Result<Value, EvalError> {
+    let key = MemoKey::new(&arg, &context);
+    let hit = memo.borrow().table.get(&key).cloned();
+    if let Some(value) = hit {
+        memo.borrow_mut().hits += 1;
+        return Ok(value);
+    }
+    memo.borrow_mut().evaluations += 1;
+    let value = eval_in(arg, context, evaluator).try_run()?;
+    memo.borrow_mut().table.insert(key, value.clone());
+    Ok(value)
+}
+
+// Evaluates like `eval_with_trampoline`, sharing repeated arguments through
+// `memo`, which keeps its table and counts for later calls
+pub fn eval_memoized(
+    expr: Expr,
+    context: Context,
+    memo: &mut EvalMemo,
+) -> Result<Value, EvalError> {
+    let evaluator = Rc::new(Evaluator {
+        memo: Some(std::mem::take(memo).into()),
+        ..Evaluator::default()
+    });
+    let result = eval_in(expr, context, evaluator.clone()).try_run();
+    if let Some(used) = &evaluator.memo {
+        *memo = used.take();
+    }
+    result
+}
+
+#[test]
+fn duplicated_arguments_are_evaluated_once() {
+    use crate::church::{church_numeral, mul};
+    use crate::{abs, app, eval_with_trampoline, rename_canonical, var};
+
+    // (\p. \q. p q) (mul 2 2) (mul 2 2)
+    let shared = app(app(mul(), church_numeral(2)), church_numeral(2));
+    let term = app(
+        app(abs("p", abs("q", app(var("p"), var("q")))), shared.clone()),
+        shared.clone(),
+    );
+    let expected = eval_with_trampoline(term.clone(), Context::new()).run();
+
+    let mut memo = EvalMemo::new();
+    assert_eq!(eval_memoized(term, Context::new(), &mut memo), Ok(expected));
+    // The same with binders renamed in the second `mul 2 2`, so nothing in it
+    // matches the first. Sharing skips just that one argument.
+    let unshared = app(
+        app(abs("p", abs("q", app(var("p"), var("q")))), shared.clone()),
+        rename_canonical(&shared),
+    );
+    let mut apart = EvalMemo::new();
+    eval_memoized(unshared, Context::new(), &mut apart).unwrap();
+    assert_eq!(memo.evaluations + 1, apart.evaluations);
+    assert_eq!(memo.hits, apart.hits + 1);
+}
+
+#[test]
+fn a_repeated_argument_is_shared() {
+    use crate::church::{church_numeral, mul};
+    use crate::{abs, app, eval_with_trampoline, var};
+
+    // f a a with a = mul 2 3 and f = \x. \y. x
+    let a = app(app(mul(), church_numeral(2)), church_numeral(3));
+    let mut ctx = Context::new();
+    ctx.insert(
+        "f".to_string(),
+        eval_with_trampoline(abs("x", abs("y", var("x"))), Context::new()).run(),
+    );
+    let term = app(app(var("f"), a.clone()), a.clone());
+    let expected = eval_with_trampoline(term.clone(), ctx.clone()).run();
+
+    let mut memo = EvalMemo::new();
+    assert_eq!(eval_memoized(term, ctx.clone(), &mut memo), Ok(expected));
+    // Everything `a` evaluates on its own, plus `a` itself as the first
+    // argument. The second `a` is a hit.
+    let mut alone = EvalMemo::new();
+    eval_memoized(a, ctx, &mut alone).unwrap();
+    assert_eq!(memo.evaluations, alone.evaluations + 1);
+    assert_eq!(memo.hits, alone.hits + 1);
+}
@@ -0,0 +1,209 @@
+// Call-by-need normalization. An argument is bound as a thunk that is
+// evaluated the first time its variable is needed and then shared, so a
+// duplicated argument is reduced once where normal order reduces every copy.
+// The normal form is read back by applying each closure to a constant.
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::subst::{free_vars, fresh_name};
+use crate::{app, eval_with_policy, Context, EvalError, Expr, OpenVarPolicy, Trampoline, Value};
+
+type Env = Rc<HashMap<String, Rc<RefCell<Thunk>>>>;
+
+enum Thunk {
+    Delayed(Rc<Expr>, Env),
+    Forced(Lazy),
+}
+
+#[derive(Clone)]
+enum Lazy {
+    Closure(Env, String, Rc<Expr>),
+    // A free variable or read-back constant and its unevaluated arguments
+    Neutral(String, Vec<Rc<RefCell<Thunk>>>),
+    Int(i64),
+}
+
+struct Machine {
+    steps: usize,
+    max_steps: usize,
+    free: HashSet<String>,
+    // Names of the binders read back so far around the current subterm
+    scope: Vec<String>,
+}
+
+impl Machine {
+    // Counts a beta step
+    fn tick(&mut self) -> Result<(), EvalError> {
+        if self.steps == self.max_steps {
+            return Err(EvalError::StepLimit(self.max_steps));
+        }
+        self.steps += 1;
+        Ok(())
+    }
+
+    fn eval(&mut self, expr: &Expr, env: &Env) -> Result<Lazy, EvalError> {
+        match expr {
+            Expr::Var(name) => match env.get(name) {
+                Some(thunk) => self.force(thunk),
+                None => Ok(Lazy::Neutral(name.clone(), Vec::new())),
+            },
+            Expr::Lit(n) => Ok(Lazy::Int(*n)),
+            Expr::Abs(param, body) => Ok(Lazy::Closure(
+                env.clone(),
+                param.clone(),
+                Rc::new(Expr::clone(body)),
+            )),
+            Expr::LetRecGroup(..) | Expr::If(..) => self.eval(&expr.expand(), env),
+            Expr::App(f, arg) => {
+                let f = self.eval(f, env)?;
+                let arg = Thunk::Delayed(Rc::new(Expr::clone(arg)), env.clone());
+                self.apply(f, Rc::new(RefCell::new(arg)))
+            }
+        }
+    }
+
+    fn apply(&mut self, f: Lazy, arg: Rc<RefCell<Thunk>>) -> Result<Lazy, EvalError> {
+        match f {
+            Lazy::Closure(env, param, body) => {
+                self.tick()?;
+                let mut env = Rc::unwrap_or_clone(env);
+                env.insert(param, arg);
+                self.eval(&body, &Rc::new(env))
+            }
+            Lazy::Neutral(head, mut args) => {
+                args.push(arg);
+                Ok(Lazy::Neutral(head, args))
+            }
+            Lazy::Int(n) => Err(EvalError::NotAFunction(Value::VInt(n))),
+        }
+    }
+
+    fn force(&mut self, thunk: &Rc<RefCell<Thunk>>) -> Result<Lazy, EvalError> {
+        let (expr, env) = match &*thunk.borrow() {
+            Thunk::Forced(value) => return Ok(value.clone()),
+            Thunk::Delayed(expr, env) => (expr.clone(), env.clone()),
+        };
+        let value = self.eval(&expr, &env)?;
+        *thunk.borrow_mut() = Thunk::Forced(value.clone());
+        Ok(value)
+    }
+
+    // Binders keep their names unless that would capture a free variable
+    // or shadow a binder around them
+    fn read_back(&mut self, value: Lazy) -> Result<Expr, EvalError> {
+        match value {
+            Lazy::Int(n) => Ok(Expr::Lit(n)),
+            Lazy::Neutral(head, args) => args.iter().try_fold(Expr::Var(head), |f, arg| {
+                let arg = self.force(arg)?;
+                Ok(app(f, self.read_back(arg)?))
+            }),
+            Lazy::Closure(env, param, body) => {
+                let name = if self.free.contains(&param) || self.scope.contains(&param) {
+                    let mut avoid = self.free.clone();
+                    avoid.extend(self.scope.iter().cloned());
+                    fresh_name(&param, &avoid)
+                } else {
+                    param.clone()
+                };
+                let constant = Thunk::Forced(Lazy::Neutral(name.clone(), Vec::new()));
+                let mut env = Rc::unwrap_or_clone(env);
+                env.insert(param, Rc::new(RefCell::new(constant)));
+                let body = self.eval(&body, &Rc::new(env))?;
+                self.scope.push(name.clone());
+                let body = self.read_back(body);
+                self.scope.pop();
+                Ok(Expr::Abs(name, Box::new(body?)))
+            }
+        }
+    }
+}
+
+// The normal form and the number of beta steps taken to reach it, like
+// `normalize_no`. Recurses on the term, so very deep terms can overflow
+// the stack.
+pub fn normalize_need(expr: Expr, max_steps: usize) -> Result<(Expr, usize), EvalError> {
+    let mut machine = Machine {
+        steps: 0,
+        max_steps,
+        free: free_vars(&expr),
+        scope: Vec::new(),
+    };
+    let value = machine.eval(&expr, &Env::default())?;
+    let normal = machine.read_back(value)?;
+    Ok((normal, machine.steps))
+}
+
+// Call-by-need doesn't take one step at a time, so each bounce normalizes
+// from scratch with twice the step limit of the one before, starting at one
+// step
+pub fn trampoline_need(expr: Expr) -> Trampoline {
+    retrying(expr, 1)
+}
+
+fn retrying(expr: Expr, max_steps: usize) -> Trampoline {
+    Trampoline::Continue(Box::new(move || {
+        match normalize_need(expr.clone(), max_steps) {
+            Ok((normal, _)) => {
+                let value = eval_with_policy(normal, Context::new(), OpenVarPolicy::Constant);
+                Trampoline::Complete(value.expect("free variables are constants"))
+            }
+            Err(EvalError::StepLimit(_)) => retrying(expr, max_steps.saturating_mul(2)),
+            Err(err) => Trampoline::Failed(err),
+        }
+    }))
+}
+
+#[test]
+fn shared_arguments_are_reduced_once() {
+    use crate::normalize::normalize_no;
+    use crate::{abs, var};
+
+    // (\x. x x) ((\y. y) (\z. z))
+    let term = app(
+        abs("x", app(var("x"), var("x"))),
+        app(abs("y", var("y")), abs("z", var("z"))),
+    );
+    assert_eq!(
+        normalize_need(term.clone(), 100),
+        Ok((abs("z", var("z")), 3))
+    );
+    assert_eq!(normalize_no(term, 100), Ok((abs("z", var("z")), 4)));
+
+    // An argument that is never needed is never evaluated
+    let omega = app(
+        abs("x", app(var("x"), var("x"))),
+        abs("x", app(var("x"), var("x"))),
+    );
+    let discard = app(abs("x", abs("y", var("y"))), omega.clone());
+    assert_eq!(normalize_need(discard, 10), Ok((abs("y", var("y")), 1)));
+    assert_eq!(normalize_need(omega, 10), Err(EvalError::StepLimit(10)));
+}
+
+#[test]
+fn need_matches_normal_order_under_binders() {
+    use crate::church::{add, church_numeral, mul};
+    use crate::diff::alpha_eq;
+    use crate::normalize::normalize;
+    use crate::{abs, var};
+
+    let terms = [
+        app(app(add(), church_numeral(2)), church_numeral(3)),
+        app(app(mul(), church_numeral(2)), church_numeral(3)),
+        // \y. (\x. \y. x y) y must not capture the outer `y`
+        abs(
+            "y",
+            app(abs("x", abs("y", app(var("x"), var("y")))), var("y")),
+        ),
+        app(abs("x", abs("f", app(var("f"), var("x")))), var("f")),
+    ];
+    for term in terms {
+        let (normal, _) = normalize_need(term.clone(), 1_000).unwrap();
+        assert!(
+            alpha_eq(&normal, &normalize(term.clone(), 1_000).unwrap()),
+            "{} for {}",
+            normal,
+            term
+        );
+    }
+}
@@ -0,0 +1,906 @@
+use std::borrow::Cow;
+use std::time::{Duration, Instant};
+
+use crate::need::{normalize_need, trampoline_need};
+use crate::subst::{free_vars, subst};
+use crate::{
+    eval_with_policy, get_at, quote, race_many, Context, Dir, EvalError, Expr, OpenVarPolicy,
+    Trampoline, Value,
+};
+
+// One leftmost-outermost beta step, or `None` if the term is in normal form.
+// Unfolding a `letrec` group into its encoding counts as a step.
+fn step_no(expr: &Expr) -> Option<Expr> {
+    match expr {
+        Expr::Var(_) | Expr::Lit(_) => None,
+        Expr::LetRecGroup(..) | Expr::If(..) => Some(expr.expand()),
+        Expr::Abs(param, body) => {
+            step_no(body).map(|body| Expr::Abs(param.clone(), Box::new(body)))
+        }
+        Expr::App(f, arg) => match f.as_ref() {
+            Expr::Abs(param, body) => Some(subst(*body.clone(), param, *arg.clone())),
+            _ => match step_no(f) {
+                Some(f) => Some(Expr::App(Box::new(f), arg.clone())),
+                None => step_no(arg).map(|arg| Expr::App(f.clone(), Box::new(arg))),
+            },
+        },
+    }
+}
+
+// One leftmost-innermost beta step: both sides of an application are
+// normalized before it is contracted
+fn step_ao(expr: &Expr) -> Option<Expr> {
+    match expr {
+        Expr::Var(_) | Expr::Lit(_) => None,
+        Expr::LetRecGroup(..) | Expr::If(..) => Some(expr.expand()),
+        Expr::Abs(param, body) => {
+            step_ao(body).map(|body| Expr::Abs(param.clone(), Box::new(body)))
+        }
+        Expr::App(f, arg) => {
+            if let Some(f) = step_ao(f) {
+                return Some(Expr::App(Box::new(f), arg.clone()));
+            }
+            if let Some(arg) = step_ao(arg) {
+                return Some(Expr::App(f.clone(), Box::new(arg)));
+            }
+            match f.as_ref() {
+                Expr::Abs(param, body) => Some(subst(*body.clone(), param, *arg.clone())),
+                _ => None,
+            }
+        }
+    }
+}
+
+// Returns the normal form and the number of steps taken to reach it
+fn normalize_with(
+    expr: Expr,
+    max_steps: usize,
+    step: fn(&Expr) -> Option<Expr>,
+) -> Result<(Expr, usize), EvalError> {
+    let mut current = expr;
+    for steps in 0..max_steps {
+        match step(&current) {
+            Some(next) => current = next,
+            None => return Ok((current, steps)),
+        }
+    }
+    match step(&current) {
+        Some(_) => Err(EvalError::StepLimit(max_steps)),
+        None => Ok((current, max_steps)),
+    }
+}
+
+// Normal order finds the normal form whenever one exists
+pub fn normalize_no(expr: Expr, max_steps: usize) -> Result<(Expr, usize), EvalError> {
+    normalize_with(expr, max_steps, step_no)
+}
+
+// Applicative order can diverge on arguments that normal order never evaluates
+pub fn normalize_ao(expr: Expr, max_steps: usize) -> Result<(Expr, usize), EvalError> {
+    normalize_with(expr, max_steps, step_ao)
+}
+
+// Where `reduce_once` would fire, `None` if `expr` is normal
+pub fn next_redex(expr: &Expr) -> Option<Vec<Dir>> {
+    match expr {
+        Expr::Var(_) | Expr::Lit(_) => None,
+        Expr::LetRecGroup(..) | Expr::If(..) => Some(Vec::new()),
+        Expr::Abs(_, body) => next_redex(body).map(|path| below(Dir::AbsBody, path)),
+        Expr::App(f, arg) => match f.as_ref() {
+            Expr::Abs(..) => Some(Vec::new()),
+            _ => next_redex(f)
+                .map(|path| below(Dir::AppFun, path))
+                .or_else(|| next_redex(arg).map(|path| below(Dir::AppArg, path))),
+        },
+    }
+}
+
+fn below(dir: Dir, mut path: Vec<Dir>) -> Vec<Dir> {
+    path.insert(0, dir);
+    path
+}
+
+// Normal-order and applicative-order reduction as trampolines that take one
+// beta step per bounce, for running alongside other evaluations. Open normal
+// forms complete as neutral values.
+pub fn trampoline_no(expr: Expr) -> Trampoline {
+    stepping(expr, step_no)
+}
+
+pub fn trampoline_ao(expr: Expr) -> Trampoline {
+    stepping(expr, step_ao)
+}
+
+// The reduction orders `race_all` can pit against each other
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum Strategy {
+    // Normal order, as `trampoline_no`
+    CallByName,
+    // Applicative order, as `trampoline_ao`
+    CallByValue,
+    // Normal order with shared arguments, as `need::trampoline_need`
+    CallByNeed,
+}
+
+impl Strategy {
+    pub fn trampoline(self, expr: Expr) -> Trampoline {
+        match self {
+            Strategy::CallByName => trampoline_no(expr),
+            Strategy::CallByValue => trampoline_ao(expr),
+            Strategy::CallByNeed => trampoline_need(expr),
+        }
+    }
+
+    // The normal form and the beta steps taken to reach it
+    pub fn normalize(self, expr: Expr, max_steps: usize) -> Result<(Expr, usize), EvalError> {
+        match self {
+            Strategy::CallByName => normalize_no(expr, max_steps),
+            Strategy::CallByValue => normalize_ao(expr, max_steps),
+            Strategy::CallByNeed => normalize_need(expr, max_steps),
+        }
+    }
+}
+
+// `expr` with the variables bound in `ctx` substituted by their quoted values
+fn close_over(expr: Expr, ctx: &Context) -> Expr {
+    free_vars(&expr)
+        .into_iter()
+        .filter_map(|name| Some((quote(ctx.get(&name)?), name)))
+        .fold(expr, |expr, (value, name)| subst(expr, &name, value))
+}
+
+// Runs every strategy on `expr`, with the variables bound in `ctx`
+// substituted by their quoted values, in turns of `budget` steps. Returns
+// the first value found and the strategy that found it, earlier strategies
+// winning ties.
+pub fn race_all(
+    strategies: &[Strategy],
+    expr: Expr,
+    ctx: &Context,
+    budget: usize,
+) -> (Value, Strategy) {
+    let closed = close_over(expr, ctx);
+    let trampolines = strategies
+        .iter()
+        .map(|strategy| strategy.trampoline(closed.clone()))
+        .collect();
+    let (value, winner) = race_many(trampolines, budget);
+    (value, strategies[winner])
+}
+
+// How many beta steps each strategy takes to normalize `expr`, closed over
+// `ctx` as in `race_all`, or `None` if it needs more than `max_steps`
+pub fn strategy_cost(
+    expr: &Expr,
+    ctx: &Context,
+    strategies: &[Strategy],
+    max_steps: usize,
+) -> Vec<(Strategy, Option<usize>)> {
+    let closed = close_over(expr.clone(), ctx);
+    strategies
+        .iter()
+        .map(|&strategy| {
+            let steps = strategy.normalize(closed.clone(), max_steps).ok();
+            (strategy, steps.map(|(_, steps)| steps))
+        })
+        .collect()
+}
+
+fn stepping(expr: Expr, step: fn(&Expr) -> Option<Expr>) -> Trampoline {
+    Trampoline::Continue(Box::new(move || match step(&expr) {
+        Some(next) => stepping(next, step),
+        None => {
+            let value = eval_with_policy(expr, Context::new(), OpenVarPolicy::Constant);
+            Trampoline::Complete(value.expect("free variables are constants"))
+        }
+    }))
+}
+
+// Which redex a single step contracts. Both orders search the tree from the
+// root, going into an abstraction's body and, at an application, into the
+// function before the argument, so "leftmost" is the first redex met in that
+// search.
+//
+// `LeftmostOutermost` contracts the first redex met, which is never inside
+// another redex. An application whose function is an abstraction is
+// contracted before anything inside it.
+//
+// `LeftmostInnermost` contracts the first redex that contains no other redex.
+// At an application, the function is searched first, then the argument, and
+// the application itself is contracted only if neither contains a redex.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReductionOrder {
+    #[default]
+    LeftmostOutermost,
+    LeftmostInnermost,
+}
+
+impl ReductionOrder {
+    fn step(self) -> fn(&Expr) -> Option<Expr> {
+        match self {
+            ReductionOrder::LeftmostOutermost => step_no,
+            ReductionOrder::LeftmostInnermost => step_ao,
+        }
+    }
+}
+
+// Performs the leftmost-outermost beta step, `None` if `expr` is normal
+pub fn reduce_once(expr: Expr) -> Option<Expr> {
+    reduce_once_in(expr, ReductionOrder::LeftmostOutermost)
+}
+
+pub fn reduce_once_in(expr: Expr, order: ReductionOrder) -> Option<Expr> {
+    order.step()(&expr)
+}
+
+// The terms visited by up to `max_steps` calls to `reduce_once`, starting
+// with `expr` itself
+pub fn reduce_steps(expr: Expr, max_steps: usize) -> Vec<Expr> {
+    reduce_steps_in(expr, max_steps, ReductionOrder::LeftmostOutermost)
+}
+
+pub fn reduce_steps_in(expr: Expr, max_steps: usize, order: ReductionOrder) -> Vec<Expr> {
+    let mut trace = vec![expr];
+    while trace.len() <= max_steps {
+        match reduce_once_in(trace.last().unwrap().clone(), order) {
+            Some(next) => trace.push(next),
+            None => break,
+        }
+    }
+    trace
+}
+
+// One sentence per step of `reduce_steps`, naming the redex it contracts
+// and ending with the term it produces
+pub fn explain(expr: Expr, max: usize) -> Vec<String> {
+    let trace = reduce_steps(expr, max);
+    trace
+        .windows(2)
+        .map(|pair| {
+            let path = next_redex(&pair[0]).unwrap();
+            let Some(Expr::App(f, arg)) = get_at(&pair[0], &path) else {
+                unreachable!("a redex is an application")
+            };
+            let Expr::Abs(param, _) = f.as_ref() else {
+                unreachable!("a redex applies an abstraction")
+            };
+            format!(
+                "Applied the function `{}` to `{}`, substituting `{}` for `{}`, giving `{}`.",
+                f, arg, arg, param, pair[1]
+            )
+        })
+        .collect()
+}
+
+// Reduces like `reduce_once` until `expr` is normal or `budget` runs out,
+// and tells which happened. The clock is read once every `CLOCK_EVERY`
+// steps, so the budget can be overrun by that many steps.
+pub fn reduce_for(expr: Expr, budget: Duration) -> (Expr, bool) {
+    const CLOCK_EVERY: usize = 64;
+
+    let start = Instant::now();
+    let mut expr = expr;
+    let mut steps = 0;
+    loop {
+        match step_no(&expr) {
+            Some(next) => expr = next,
+            None => return (expr, true),
+        }
+        steps += 1;
+        if steps % CLOCK_EVERY == 0 && start.elapsed() >= budget {
+            return (expr, false);
+        }
+    }
+}
+
+// Beta-normalizes by substitution in normal order, giving up after `max_steps`
+pub fn normalize(expr: Expr, max_steps: usize) -> Result<Expr, EvalError> {
+    normalize_no(expr, max_steps).map(|(normal, _)| normal)
+}
+
+// Like `normalize`, borrowing `expr` instead of copying it when it has no
+// beta redex
+pub fn normalize_cow(expr: &Expr, max_steps: usize) -> Result<Cow<'_, Expr>, EvalError> {
+    if next_redex(expr).is_none() {
+        return Ok(Cow::Borrowed(expr));
+    }
+    normalize(expr.clone(), max_steps).map(Cow::Owned)
+}
+
+// Whether `expr` has neither a beta nor an eta redex anywhere. `normalize`
+// only removes beta redexes, so its results can still fail this.
+pub fn is_normal_form(expr: &Expr) -> bool {
+    match expr {
+        Expr::Var(_) | Expr::Lit(_) => true,
+        Expr::LetRecGroup(..) | Expr::If(..) => false,
+        Expr::Abs(param, body) => {
+            let eta_redex = matches!(body.as_ref(), Expr::App(f, arg)
+                if **arg == Expr::Var(param.clone()) && !free_vars(f).contains(param));
+            !eta_redex && is_normal_form(body)
+        }
+        Expr::App(f, arg) => {
+            !matches!(f.as_ref(), Expr::Abs(..)) && is_normal_form(f) && is_normal_form(arg)
+        }
+    }
+}
+
+// Rewrites every `\x. f x` with `x` not free in `f` to `f`
+pub fn eta_reduce(expr: Expr) -> Expr {
+    match expr {
+        Expr::Var(_) | Expr::Lit(_) => expr,
+        Expr::App(f, arg) => Expr::App(Box::new(eta_reduce(*f)), Box::new(eta_reduce(*arg))),
+        sugar @ (Expr::LetRecGroup(..) | Expr::If(..)) => eta_reduce(sugar.expand()),
+        Expr::Abs(param, body) => match eta_reduce(*body) {
+            Expr::App(f, arg)
+                if *arg == Expr::Var(param.clone()) && !free_vars(&f).contains(&param) =>
+            {
+                *f
+            }
+            body => Expr::Abs(param, Box::new(body)),
+        },
+    }
+}
+
+// Contracts head redexes until `expr` is an abstraction, a literal or an
+// application headed by a variable, leaving the arguments unreduced. A head
+// variable bound in `ctx` is replaced by its quoted value; an unbound one is
+// an error under `Error` and stays as the head of a neutral term under
+// `Constant`. Diverges if there is no weak head normal form.
+pub fn whnf_expr(expr: Expr, ctx: Context, policy: OpenVarPolicy) -> Result<Expr, EvalError> {
+    let mut current = expr;
+    let mut args = Vec::new();
+    let head = loop {
+        current = match current {
+            Expr::App(f, arg) => {
+                args.push(*arg);
+                *f
+            }
+            Expr::Abs(param, body) => match args.pop() {
+                Some(arg) => subst(*body, &param, arg),
+                None => return Ok(Expr::Abs(param, body)),
+            },
+            Expr::Lit(n) if !args.is_empty() => {
+                return Err(EvalError::NotAFunction(Value::VInt(n)));
+            }
+            Expr::Var(ref name) => match ctx.get(name) {
+                Some(value @ (Value::VClosure(..) | Value::VInt(_))) => quote(value),
+                Some(Value::VNeutral(..)) => break quote(&ctx[name]),
+                None if policy == OpenVarPolicy::Error => {
+                    return Err(EvalError::UnboundVariable(name.clone()));
+                }
+                None => break current,
+            },
+            head => break head,
+        };
+    };
+    Ok(args
+        .into_iter()
+        .rev()
+        .fold(head, |f, arg| Expr::App(Box::new(f), Box::new(arg))))
+}
+
+// The steps from `expr` to its normal form when it is only forced as far as
+// needed: first its weak head normal form, then one entry for each subterm
+// brought to weak head normal form in turn, leftmost first, with the last
+// entry the normal form. Free variables are constants as in `whnf_expr`.
+pub fn force_nf_staged(expr: Expr, ctx: Context) -> Result<Vec<Expr>, EvalError> {
+    let mut stages = vec![whnf_expr(expr, ctx.clone(), OpenVarPolicy::Constant)?];
+    while let Some(next) = force_first(stages.last().unwrap(), &ctx)? {
+        stages.push(next);
+    }
+    Ok(stages)
+}
+
+// Forces the leftmost subterm of a weak head normal form that is not one
+// itself, `None` if there is none and `expr` is normal
+fn force_first(expr: &Expr, ctx: &Context) -> Result<Option<Expr>, EvalError> {
+    let force = |expr: &Expr, ctx: &Context| -> Result<Option<Expr>, EvalError> {
+        let forced = whnf_expr(expr.clone(), ctx.clone(), OpenVarPolicy::Constant)?;
+        if forced != *expr {
+            return Ok(Some(forced));
+        }
+        force_first(expr, ctx)
+    };
+    match expr {
+        Expr::Var(_) | Expr::Lit(_) => Ok(None),
+        Expr::LetRecGroup(..) | Expr::If(..) => Ok(Some(expr.expand())),
+        Expr::Abs(param, body) => {
+            let mut ctx = ctx.clone();
+            ctx.remove(param);
+            let body = force(body, &ctx)?;
+            Ok(body.map(|body| Expr::Abs(param.clone(), Box::new(body))))
+        }
+        Expr::App(f, arg) => {
+            if let Some(f) = force_first(f, ctx)? {
+                return Ok(Some(Expr::App(Box::new(f), arg.clone())));
+            }
+            let arg = force(arg, ctx)?;
+            Ok(arg.map(|arg| Expr::App(f.clone(), Box::new(arg))))
+        }
+    }
+}
+
+// Normalizes `expr` in normal order, in applicative order and by evaluating
+// to a closure and normalizing its quote, and checks that every path that
+// terminates agrees up to beta-eta. The closure evaluator has no step limit,
+// so it only runs on closed terms that applicative order already normalized.
+#[cfg(test)]
+pub fn assert_confluent(expr: &Expr, max_steps: usize) {
+    use crate::diff::beta_eta_eq;
+    use crate::{eval, quote};
+
+    let mut results = Vec::new();
+    results.extend(normalize_no(expr.clone(), max_steps).map(|(normal, _)| normal));
+    if let Ok((normal, _)) = normalize_ao(expr.clone(), max_steps) {
+        results.push(normal);
+        if free_vars(expr).is_empty() {
+            let value = eval(expr.clone(), Default::default()).unwrap();
+            results.push(normalize(quote(&value), max_steps).unwrap());
+        }
+    }
+
+    for pair in results.windows(2) {
+        assert_eq!(
+            beta_eta_eq(&pair[0], &pair[1], max_steps),
+            Ok(true),
+            "{} and {} disagree for {}",
+            pair[0],
+            pair[1],
+            expr
+        );
+    }
+}
+
+// Runs the substitution normalizer, the trampoline and the VM on the closed
+// term `expr` and checks that they reach beta-eta equal normal forms, or that
+// all of them run out of steps. The trampoline and the VM stop at a value,
+// whose quote is then normalized within the same limit.
+#[cfg(test)]
+pub fn cross_check(expr: &Expr, max_steps: usize) {
+    use std::ops::ControlFlow;
+
+    use crate::diff::beta_eta_eq;
+    use crate::eval_with_trampoline;
+    use crate::vm::{compile, run_vm_within};
+
+    assert!(free_vars(expr).is_empty(), "{} is not closed", expr);
+    let from_value = |value: Result<Value, EvalError>| normalize(quote(&value?), max_steps);
+    let trampoline = eval_with_trampoline(expr.clone(), Context::new())
+        .run_with_progress(max_steps, &mut |_| ControlFlow::Break(()))
+        .ok_or(EvalError::StepLimit(max_steps));
+
+    let results = [
+        ("substitution", normalize(expr.clone(), max_steps)),
+        ("trampoline", from_value(trampoline)),
+        ("vm", from_value(run_vm_within(&compile(expr), max_steps))),
+    ];
+    if results.iter().all(|(_, result)| result.is_err()) {
+        for (engine, result) in &results {
+            assert_eq!(
+                result,
+                &Err(EvalError::StepLimit(max_steps)),
+                "{} failed on {}",
+                engine,
+                expr
+            );
+        }
+        return;
+    }
+    let normals: Vec<_> = results
+        .iter()
+        .map(|(engine, result)| match result {
+            Ok(normal) => (engine, normal),
+            Err(err) => panic!("{} gave {} on {}, the others finished", engine, err, expr),
+        })
+        .collect();
+    let (first, expected) = normals[0];
+    for (engine, normal) in &normals[1..] {
+        assert_eq!(
+            beta_eta_eq(expected, normal, max_steps),
+            Ok(true),
+            "{} gave {} and {} gave {} for {}",
+            first,
+            expected,
+            engine,
+            normal,
+            expr
+        );
+    }
+}
+
+#[test]
+fn reduce_once_takes_a_single_step() {
+    use crate::{abs, app, var};
+
+    assert_eq!(
+        reduce_once(app(abs("x", var("x")), var("y"))),
+        Some(var("y"))
+    );
+    assert_eq!(reduce_once(abs("x", var("x"))), None);
+}
+
+#[test]
+fn next_redex_points_at_the_leftmost_outermost_redex() {
+    use crate::{abs, app, var};
+
+    // \z. z ((\x. x) z)
+    let term = abs("z", app(var("z"), app(abs("x", var("x")), var("z"))));
+    assert_eq!(next_redex(&term), Some(vec![Dir::AbsBody, Dir::AppArg]));
+    assert_eq!(next_redex(&abs("x", var("x"))), None);
+}
+
+#[test]
+fn reduce_steps_stops_at_normal_form_or_limit() {
+    use crate::{abs, app, var};
+
+    // (\x. x) ((\y. y) z)
+    let term = app(abs("x", var("x")), app(abs("y", var("y")), var("z")));
+
+    assert_eq!(
+        reduce_steps(term.clone(), 10),
+        vec![term.clone(), app(abs("y", var("y")), var("z")), var("z")]
+    );
+    assert_eq!(reduce_steps(term.clone(), 1).len(), 2);
+}
+
+#[test]
+fn normalize_reduces_church_addition() {
+    use crate::app;
+    use crate::church::{add, church_numeral};
+
+    let sum = app(app(add(), church_numeral(1)), church_numeral(2));
+    assert_eq!(normalize(sum, 100), Ok(church_numeral(3)));
+}
+
+#[test]
+fn normalize_renames_binders_only_on_conflict() {
+    use crate::{abs, app, var};
+
+    // \f. \x. f x
+    let apply = abs("f", abs("x", app(var("f"), var("x"))));
+    assert_eq!(
+        normalize(app(apply.clone(), var("g")), 10),
+        Ok(abs("x", app(var("g"), var("x"))))
+    );
+    assert_eq!(
+        normalize(app(apply, var("x")), 10),
+        Ok(abs("#x0", app(var("x"), var("#x0"))))
+    );
+}
+
+#[test]
+fn normalize_gives_up_on_omega() {
+    use crate::{abs, app, var};
+
+    let omega = app(
+        abs("x", app(var("x"), var("x"))),
+        abs("x", app(var("x"), var("x"))),
+    );
+    assert_eq!(normalize(omega, 10), Err(EvalError::StepLimit(10)));
+}
+
+#[test]
+fn normal_forms_are_returned_as_they_are() {
+    use crate::{abs, app, var};
+
+    assert!(is_normal_form(&abs("x", var("x"))));
+    assert!(!is_normal_form(&app(abs("x", var("x")), var("y"))));
+    assert!(!is_normal_form(&abs("x", app(var("f"), var("x")))));
+    assert!(is_normal_form(&abs("x", app(var("x"), var("x")))));
+
+    // The same box comes back out, so nothing was rebuilt
+    let term = abs("x", app(var("f"), abs("y", var("y"))));
+    let Expr::Abs(_, body) = &term else {
+        unreachable!()
+    };
+    let before: *const Expr = body.as_ref();
+    let moved = normalize(term, 10).unwrap();
+    let Expr::Abs(_, body) = &moved else {
+        unreachable!()
+    };
+    assert_eq!(before, body.as_ref() as *const Expr);
+
+    assert!(matches!(normalize_cow(&moved, 10), Ok(Cow::Borrowed(_))));
+    let redex = app(abs("x", var("x")), var("y"));
+    assert_eq!(normalize_cow(&redex, 10), Ok(Cow::Owned(var("y"))));
+}
+
+#[test]
+fn sharing_makes_call_by_need_cheapest() {
+    use std::collections::HashMap;
+
+    use crate::church::{church_numeral, mul};
+    use crate::{abs, app, eval, var};
+
+    // (\x. x x) (mul 2 2): both copies of the product are reduced by name,
+    // one by need
+    let term = app(abs("x", app(var("x"), var("x"))), var("four"));
+    let four = app(app(mul(), church_numeral(2)), church_numeral(2));
+    let ctx = HashMap::from([("four".to_string(), eval(four, HashMap::new()).unwrap())]);
+    let all = [
+        Strategy::CallByName,
+        Strategy::CallByValue,
+        Strategy::CallByNeed,
+    ];
+    let costs = strategy_cost(&term, &ctx, &all, 1_000);
+
+    let [(_, Some(by_name)), (_, Some(_)), (_, Some(by_need))] = costs[..] else {
+        panic!("{:?}", costs)
+    };
+    assert!(by_need < by_name, "{:?}", costs);
+    let omega = app(
+        abs("x", app(var("x"), var("x"))),
+        abs("x", app(var("x"), var("x"))),
+    );
+    assert_eq!(
+        strategy_cost(&omega, &HashMap::new(), &[Strategy::CallByNeed], 50),
+        vec![(Strategy::CallByNeed, None)]
+    );
+
+    // Call-by-need also wins races that only normal order can finish
+    let discard = app(abs("x", abs("y", var("y"))), omega);
+    let by_value_or_need = [Strategy::CallByValue, Strategy::CallByNeed];
+    let (_, winner) = race_all(&by_value_or_need, discard, &HashMap::new(), 10);
+    assert_eq!(winner, Strategy::CallByNeed);
+}
+
+#[test]
+fn race_all_names_the_strategy_that_finished() {
+    use std::collections::HashMap;
+
+    use crate::{abs, app, eval, var};
+
+    // (\x. \y. y) ((\x. x x) (\x. x x)) only finishes in normal order
+    let omega = app(
+        abs("x", app(var("x"), var("x"))),
+        abs("x", app(var("x"), var("x"))),
+    );
+    let term = app(abs("x", abs("y", var("y"))), omega);
+    let both = [Strategy::CallByValue, Strategy::CallByName];
+    let (value, winner) = race_all(&both, term, &HashMap::new(), 10);
+    assert_eq!(winner, Strategy::CallByName);
+    assert_eq!(quote(&value), abs("y", var("y")));
+
+    // Bound variables are substituted first, and ties go to the first listed
+    let ctx = HashMap::from([(
+        "id".to_string(),
+        eval(abs("z", var("z")), HashMap::new()).unwrap(),
+    )]);
+    let (value, winner) = race_all(&both, app(var("id"), var("id")), &ctx, 10);
+    assert_eq!(winner, Strategy::CallByValue);
+    assert_eq!(quote(&value), abs("z", var("z")));
+}
+
+#[test]
+fn eta_reduce_keeps_binders_that_are_used() {
+    use crate::{abs, app, var};
+
+    assert_eq!(eta_reduce(abs("x", app(var("f"), var("x")))), var("f"));
+    assert_eq!(
+        eta_reduce(abs("x", app(var("x"), var("x")))),
+        abs("x", app(var("x"), var("x")))
+    );
+}
+
+#[test]
+fn only_normal_order_skips_a_diverging_argument() {
+    use crate::{abs, app, var};
+
+    // (\x. y) ((\x. x x) (\x. x x))
+    let omega = app(
+        abs("x", app(var("x"), var("x"))),
+        abs("x", app(var("x"), var("x"))),
+    );
+    let term = app(abs("x", var("y")), omega);
+
+    assert_eq!(normalize_no(term.clone(), 100), Ok((var("y"), 1)));
+    assert_eq!(normalize_ao(term, 100), Err(EvalError::StepLimit(100)));
+}
+
+#[test]
+fn both_orders_count_their_steps() {
+    use crate::{abs, app, var};
+
+    // (\x. x) ((\y. y) z)
+    let term = app(abs("x", var("x")), app(abs("y", var("y")), var("z")));
+
+    assert_eq!(normalize_no(term.clone(), 100), Ok((var("z"), 2)));
+    assert_eq!(normalize_ao(term, 100), Ok((var("z"), 2)));
+}
+
+#[test]
+fn reducers_agree_on_church_arithmetic() {
+    use crate::app;
+    use crate::church::{add, church_numeral, mul};
+
+    assert_confluent(&app(app(add(), church_numeral(2)), church_numeral(3)), 1000);
+    assert_confluent(&app(app(mul(), church_numeral(2)), church_numeral(3)), 1000);
+}
+
+#[test]
+fn reducers_agree_under_binders() {
+    use crate::{abs, app, var};
+
+    // \z. (\x. \y. x) z ((\w. w) z)
+    let term = abs(
+        "z",
+        app(
+            app(abs("x", abs("y", var("x"))), var("z")),
+            app(abs("w", var("w")), var("z")),
+        ),
+    );
+    assert_confluent(&term, 100);
+}
+
+#[test]
+fn reducers_agree_when_only_normal_order_terminates() {
+    use crate::{abs, app, var};
+
+    // (\x. \y. y) ((\x. x x) (\x. x x))
+    let omega = app(
+        abs("x", app(var("x"), var("x"))),
+        abs("x", app(var("x"), var("x"))),
+    );
+    assert_confluent(&app(abs("x", abs("y", var("y"))), omega), 100);
+}
+
+#[test]
+fn engines_agree_on_church_arithmetic() {
+    use crate::vm::church_examples;
+    use crate::{abs, app, var};
+
+    for expr in church_examples() {
+        cross_check(&expr, 10_000);
+    }
+    let omega = app(
+        abs("x", app(var("x"), var("x"))),
+        abs("x", app(var("x"), var("x"))),
+    );
+    cross_check(&omega, 1_000);
+}
+
+#[test]
+fn engines_agree_on_generated_terms() {
+    use crate::generate::gen_term;
+
+    for seed in 0..200 {
+        for density in [0.2, 0.5, 0.8] {
+            cross_check(&gen_term(seed, 8, density), 10_000);
+        }
+    }
+}
+
+#[test]
+fn whnf_expr_stops_at_a_neutral_head() {
+    use std::collections::HashMap;
+
+    use crate::{abs, app, var};
+
+    // x ((\y. y) z)
+    let term = app(var("x"), app(abs("y", var("y")), var("z")));
+    assert_eq!(
+        whnf_expr(term.clone(), HashMap::new(), OpenVarPolicy::Constant),
+        Ok(term.clone())
+    );
+    assert_eq!(
+        whnf_expr(term.clone(), HashMap::new(), OpenVarPolicy::Error),
+        Err(EvalError::UnboundVariable("x".to_string()))
+    );
+
+    // (\f. f a) (\b. x b), with `x` bound to a closure
+    let term = app(
+        abs("f", app(var("f"), var("a"))),
+        abs("b", app(var("x"), var("b"))),
+    );
+    let ctx = HashMap::from([(
+        "x".to_string(),
+        crate::eval(abs("p", abs("q", var("p"))), HashMap::new()).unwrap(),
+    )]);
+    assert_eq!(
+        whnf_expr(term, ctx, OpenVarPolicy::Constant),
+        Ok(abs("q", var("a")))
+    );
+}
+
+#[test]
+fn reduce_for_stops_when_time_runs_out() {
+    use crate::church::{add, church_numeral};
+    use crate::{abs, app, var};
+
+    let omega = app(
+        abs("x", app(var("x"), var("x"))),
+        abs("x", app(var("x"), var("x"))),
+    );
+    assert_eq!(
+        reduce_for(omega.clone(), Duration::from_millis(10)),
+        (omega, false)
+    );
+
+    let sum = app(app(add(), church_numeral(1)), church_numeral(1));
+    assert_eq!(
+        reduce_for(sum, Duration::from_secs(10)),
+        (church_numeral(2), true)
+    );
+}
+
+#[test]
+fn reduction_orders_have_pinned_traces() {
+    use crate::{abs, app, var};
+
+    // (\x. \y. x) ((\z. z) a) ((\w. w) b)
+    let term = app(
+        app(
+            abs("x", abs("y", var("x"))),
+            app(abs("z", var("z")), var("a")),
+        ),
+        app(abs("w", var("w")), var("b")),
+    );
+    let trace = |order| -> Vec<String> {
+        reduce_steps_in(term.clone(), 10, order)
+            .iter()
+            .map(|expr| expr.to_string())
+            .collect()
+    };
+
+    assert_eq!(
+        trace(ReductionOrder::LeftmostOutermost),
+        [
+            "(\\x. \\y. x) ((\\z. z) a) ((\\w. w) b)",
+            "(\\y. (\\z. z) a) ((\\w. w) b)",
+            "(\\z. z) a",
+            "a",
+        ]
+    );
+    assert_eq!(
+        trace(ReductionOrder::LeftmostInnermost),
+        [
+            "(\\x. \\y. x) ((\\z. z) a) ((\\w. w) b)",
+            "(\\x. \\y. x) a ((\\w. w) b)",
+            "(\\y. a) ((\\w. w) b)",
+            "(\\y. a) b",
+            "a",
+        ]
+    );
+    assert_eq!(
+        reduce_steps(term.clone(), 10),
+        reduce_steps_in(term, 10, ReductionOrder::default())
+    );
+}
+
+#[test]
+fn explain_narrates_each_step() {
+    use crate::{abs, app, var};
+
+    assert_eq!(
+        explain(app(abs("x", var("x")), var("y")), 10),
+        ["Applied the function `\\x. x` to `y`, substituting `y` for `x`, giving `y`."]
+    );
+
+    let two_steps = app(abs("x", app(var("x"), var("x"))), abs("z", var("z")));
+    assert_eq!(explain(two_steps.clone(), 10).len(), 2);
+    assert_eq!(explain(two_steps, 1).len(), 1);
+}
+
+#[test]
+fn staged_forcing_reaches_the_pair_first() {
+    use crate::prelude::prelude_context;
+    use crate::{abs, app, var};
+
+    // pair ((\x. x) a) ((\y. y) b)
+    let term = app(
+        app(var("pair"), app(abs("x", var("x")), var("a"))),
+        app(abs("y", var("y")), var("b")),
+    );
+    let stages = force_nf_staged(term, prelude_context()).unwrap();
+    let stages: Vec<_> = stages.iter().map(|expr| expr.to_string()).collect();
+
+    assert_eq!(
+        stages,
+        [
+            "\\s. s ((\\x. x) a) ((\\y. y) b)",
+            "\\s. s a ((\\y. y) b)",
+            "\\s. s a b",
+        ]
+    );
+}
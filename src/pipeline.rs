@@ -0,0 +1,132 @@
+// Chains the term transformations, e.g.
+// `Pipeline::new(src).parse()?.normalize(1_000).eta_reduce().pretty(&opts)`.
+// After parsing, a failing stage is remembered and the later ones are
+// skipped, so the error only has to be handled once at the end.
+use std::fmt;
+
+use crate::normalize::{eta_reduce, normalize};
+use crate::parser::parse;
+use crate::pretty::{pretty, PrettyOpts};
+use crate::{EvalError, Expr};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    Parse,
+    Normalize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PipelineError {
+    pub stage: Stage,
+    pub error: EvalError,
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} failed: {}", self.stage, self.error)
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+pub struct Pipeline<'a> {
+    source: &'a str,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Pipeline { source }
+    }
+
+    pub fn parse(self) -> Result<Transform, PipelineError> {
+        let expr = parse(self.source).map_err(|err| PipelineError {
+            stage: Stage::Parse,
+            error: EvalError::Parse(err),
+        })?;
+        Ok(Transform { expr: Ok(expr) })
+    }
+}
+
+// A parsed term on its way through the pipeline
+pub struct Transform {
+    expr: Result<Expr, PipelineError>,
+}
+
+impl Transform {
+    pub fn normalize(self, max_steps: usize) -> Self {
+        let expr = self.expr.and_then(|expr| {
+            normalize(expr, max_steps).map_err(|error| PipelineError {
+                stage: Stage::Normalize,
+                error,
+            })
+        });
+        Transform { expr }
+    }
+
+    pub fn eta_reduce(self) -> Self {
+        Transform {
+            expr: self.expr.map(eta_reduce),
+        }
+    }
+
+    pub fn expr(self) -> Result<Expr, PipelineError> {
+        self.expr
+    }
+
+    pub fn pretty(self, opts: &PrettyOpts) -> Result<String, PipelineError> {
+        self.expr.map(|expr| pretty(&expr, opts))
+    }
+}
+
+// Step limit used by `run`
+pub const DEFAULT_STEPS: usize = 10_000;
+
+// The whole pipeline with the defaults, for quick experiments: the
+// eta-reduced normal form of `src`, printed
+pub fn run(src: &str) -> Result<String, PipelineError> {
+    Pipeline::new(src)
+        .parse()?
+        .normalize(DEFAULT_STEPS)
+        .eta_reduce()
+        .pretty(&PrettyOpts::default())
+}
+
+#[test]
+fn pipeline_runs_every_stage() -> Result<(), PipelineError> {
+    let opts = PrettyOpts::default();
+
+    // Normalizes to \f. \x. f x, which eta-reduces to \f. f
+    let out = Pipeline::new("(\\n f x. f (n f x)) (\\f x. x)")
+        .parse()?
+        .normalize(100)
+        .eta_reduce()
+        .pretty(&opts)?;
+    assert_eq!(out, "\\f. f");
+
+    let omega = Pipeline::new("(\\x. x x) (\\x. x x)")
+        .parse()?
+        .normalize(10)
+        .eta_reduce()
+        .pretty(&opts);
+    assert_eq!(
+        omega,
+        Err(PipelineError {
+            stage: Stage::Normalize,
+            error: EvalError::StepLimit(10),
+        })
+    );
+
+    let unparsed = Pipeline::new("\\x").parse().err().map(|err| err.stage);
+    assert_eq!(unparsed, Some(Stage::Parse));
+    Ok(())
+}
+
+#[test]
+fn run_prints_the_normal_form() {
+    assert_eq!(run("(\\x. x) (\\y. y)"), Ok("\\y. y".to_string()));
+    assert_eq!(run("\\x. \\y. x y"), Ok("\\x. x".to_string()));
+    assert_eq!(
+        run("(\\x. x x) (\\x. x x)").map_err(|err| err.error),
+        Err(EvalError::StepLimit(DEFAULT_STEPS))
+    );
+}
@@ -0,0 +1,106 @@
+// Standard definitions, available to every program through `prelude_context`
+use crate::church::{add, church_bool, cons, fst, mul, nil, pair, snd, succ};
+use crate::parser::parse;
+use crate::{abs, app, empty_context, eval, var, Context, EvalError, Expr};
+
+pub fn definitions() -> Vec<(&'static str, Expr)> {
+    vec![
+        ("I", abs("x", var("x"))),
+        ("K", abs("x", abs("y", var("x")))),
+        // \x. \y. \z. x z (y z)
+        (
+            "S",
+            abs(
+                "x",
+                abs(
+                    "y",
+                    abs("z", app(app(var("x"), var("z")), app(var("y"), var("z")))),
+                ),
+            ),
+        ),
+        // \f. \g. \x. f (g x)
+        (
+            "B",
+            abs(
+                "f",
+                abs("g", abs("x", app(var("f"), app(var("g"), var("x"))))),
+            ),
+        ),
+        // \f. \x. \y. f y x
+        (
+            "C",
+            abs(
+                "f",
+                abs("x", abs("y", app(app(var("f"), var("y")), var("x")))),
+            ),
+        ),
+        // \f. \x. f x x
+        (
+            "W",
+            abs("f", abs("x", app(app(var("f"), var("x")), var("x")))),
+        ),
+        ("true", church_bool(true)),
+        ("false", church_bool(false)),
+        ("succ", succ()),
+        ("add", add()),
+        ("mul", mul()),
+        ("pair", pair()),
+        ("fst", fst()),
+        ("snd", snd()),
+        ("nil", nil()),
+        ("cons", cons()),
+    ]
+}
+
+// Every prelude definition, already evaluated
+pub fn prelude_context() -> Context {
+    definitions()
+        .into_iter()
+        .map(|(name, expr)| {
+            let value = eval(expr, empty_context()).expect("prelude definitions are closed");
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+// Parses `src`, evaluates it against `ctx` and binds the value to `name`, so
+// definitions can be written in lambda syntax and see the earlier ones
+pub fn register(ctx: &mut Context, name: &str, src: &str) -> Result<(), EvalError> {
+    let expr = parse(src).map_err(EvalError::Parse)?;
+    let value = eval(expr, ctx.clone())?;
+    ctx.insert(name.to_string(), value);
+    Ok(())
+}
+
+#[test]
+fn prelude_terms_evaluate_out_of_the_box() {
+    use crate::quote;
+
+    let value = eval(parse("S K K I").unwrap(), prelude_context()).unwrap();
+    assert_eq!(quote(&value), abs("x", var("x")));
+
+    let value = eval(parse("fst (pair true false)").unwrap(), prelude_context()).unwrap();
+    assert_eq!(quote(&value), abs("t", abs("f", var("t"))));
+}
+
+#[test]
+fn registered_definitions_use_earlier_ones() {
+    let mut ctx = empty_context();
+    register(&mut ctx, "const", "\\x. \\y. x").unwrap();
+    register(&mut ctx, "flip", "\\f. \\a. \\b. f b a").unwrap();
+    register(&mut ctx, "x", "\\p. p").unwrap();
+    register(&mut ctx, "y", "\\q. q").unwrap();
+
+    let value = eval(parse("flip const x y").unwrap(), ctx.clone()).unwrap();
+    assert_eq!(value, ctx["y"]);
+
+    assert!(matches!(
+        register(&mut ctx, "bad", "\\x."),
+        Err(EvalError::Parse(_))
+    ));
+    assert_eq!(
+        register(&mut ctx, "open", "z"),
+        Err(EvalError::UnboundVariable("z".to_string()))
+    );
+    assert!(!ctx.contains_key("open"));
+}
@@ -0,0 +1,565 @@
+// Printing for the terminal: the same text as `Display`, optionally with ANSI
+// colors for binders and for the redex about to fire. Colors only wrap the
+// text, so stripping the escapes gives back the plain output.
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::io::IsTerminal;
+
+use crate::normalize::{next_redex, reduce_steps};
+use crate::parser::{is_name, Assoc};
+use crate::subst::readable_name;
+use crate::{Dir, Expr};
+
+#[cfg(feature = "color")]
+const REDEX: &str = "\x1b[1;33m";
+#[cfg(feature = "color")]
+const BINDER: &str = "\x1b[36m";
+#[cfg(feature = "color")]
+const RESET: &str = "\x1b[0m";
+
+#[derive(Clone, Debug, Default)]
+pub struct PrettyOpts {
+    // Ignored unless built with the `color` feature
+    pub color: bool,
+    // Must match the `ParserConfig` the output is parsed back with
+    pub app_assoc: Assoc,
+    // Renames binders that aren't valid source names, such as those from
+    // `FreshGen`, to readable ones that clash with nothing in the term
+    pub readable_names: bool,
+    // Marks each variable with its De Bruijn index, as in `x@0`, or with
+    // `@free`. The output no longer parses.
+    pub de_bruijn: bool,
+}
+
+impl PrettyOpts {
+    // Colors only when they are compiled in and stdout is a terminal, and
+    // readable names for generated binders
+    pub fn for_stdout() -> Self {
+        PrettyOpts {
+            color: cfg!(feature = "color") && std::io::stdout().is_terminal(),
+            readable_names: true,
+            ..PrettyOpts::default()
+        }
+    }
+}
+
+pub fn pretty(expr: &Expr, opts: &PrettyOpts) -> String {
+    pretty_redex(expr, None, opts)
+}
+
+// Like `pretty`, highlighting the subterm at `redex`
+pub fn pretty_redex(expr: &Expr, redex: Option<&[Dir]>, opts: &PrettyOpts) -> String {
+    let mut out = String::new();
+    if opts.readable_names {
+        let mut taken = HashSet::new();
+        all_names(expr, &mut taken);
+        let readable = readable_binders(expr, &mut taken, &mut Vec::new());
+        write_expr(&readable, &mut Scope::default(), redex, opts, &mut out);
+    } else {
+        write_expr(expr, &mut Scope::default(), redex, opts, &mut out);
+    }
+    out
+}
+
+fn all_names(expr: &Expr, names: &mut HashSet<String>) {
+    match expr {
+        Expr::Var(name) => {
+            names.insert(name.clone());
+        }
+        Expr::Lit(_) => {}
+        Expr::Abs(param, body) => {
+            names.insert(param.clone());
+            all_names(body, names);
+        }
+        Expr::App(f, arg) => {
+            all_names(f, names);
+            all_names(arg, names);
+        }
+        Expr::LetRecGroup(bindings, body) => {
+            for (name, expr) in bindings {
+                names.insert(name.clone());
+                all_names(expr, names);
+            }
+            all_names(body, names);
+        }
+        Expr::If(cond, then, otherwise) => {
+            for child in [cond, then, otherwise] {
+                all_names(child, names);
+            }
+        }
+    }
+}
+
+// Renaming only binders keeps the shape, so redex paths still apply
+fn readable_binders(
+    expr: &Expr,
+    taken: &mut HashSet<String>,
+    renamed: &mut Vec<(String, String)>,
+) -> Expr {
+    match expr {
+        Expr::Var(name) => match renamed.iter().rev().find(|(old, _)| old == name) {
+            Some((_, new)) => Expr::Var(new.clone()),
+            None => expr.clone(),
+        },
+        Expr::Lit(_) => expr.clone(),
+        Expr::Abs(param, body) => {
+            let new = readable_binder(param, taken);
+            renamed.push((param.clone(), new.clone()));
+            let body = readable_binders(body, taken, renamed);
+            renamed.pop();
+            Expr::Abs(new, Box::new(body))
+        }
+        Expr::App(f, arg) => Expr::App(
+            Box::new(readable_binders(f, taken, renamed)),
+            Box::new(readable_binders(arg, taken, renamed)),
+        ),
+        Expr::LetRecGroup(bindings, body) => {
+            let depth = renamed.len();
+            for (name, _) in bindings {
+                let new = readable_binder(name, taken);
+                renamed.push((name.clone(), new));
+            }
+            let names: Vec<_> = renamed[depth..]
+                .iter()
+                .map(|(_, new)| new.clone())
+                .collect();
+            let bindings = names
+                .into_iter()
+                .zip(bindings)
+                .map(|(new, (_, expr))| (new, readable_binders(expr, taken, renamed)))
+                .collect();
+            let body = readable_binders(body, taken, renamed);
+            renamed.truncate(depth);
+            Expr::LetRecGroup(bindings, Box::new(body))
+        }
+        Expr::If(cond, then, otherwise) => Expr::If(
+            Box::new(readable_binders(cond, taken, renamed)),
+            Box::new(readable_binders(then, taken, renamed)),
+            Box::new(readable_binders(otherwise, taken, renamed)),
+        ),
+    }
+}
+
+fn readable_binder(param: &str, taken: &mut HashSet<String>) -> String {
+    if is_name(param) {
+        return param.to_string();
+    }
+    let stem: String = param.chars().filter(|c| c.is_alphanumeric()).collect();
+    let stem = if stem.is_empty() { "v" } else { &stem };
+    let new = readable_name(stem, taken);
+    taken.insert(new.clone());
+    new
+}
+
+// One line per term, each highlighting the redex that produces the next
+pub fn pretty_trace(trace: &[Expr], opts: &PrettyOpts) -> String {
+    let mut out = String::new();
+    for (i, expr) in trace.iter().enumerate() {
+        let redex = if i + 1 < trace.len() {
+            next_redex(expr)
+        } else {
+            None
+        };
+        writeln!(out, "{}", pretty_redex(expr, redex.as_deref(), opts)).unwrap();
+    }
+    out
+}
+
+// One frame per term of `reduce_steps(expr, max)`, highlighted as in
+// `pretty_trace`, for rendering a reduction as an animation
+pub fn reduction_frames(expr: Expr, max: usize, opts: &PrettyOpts) -> Vec<String> {
+    pretty_trace(&reduce_steps(expr, max), opts)
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+// Where the printer is: the path from the root and the binders above it
+#[derive(Default)]
+struct Scope {
+    path: Vec<Dir>,
+    bound: Vec<String>,
+}
+
+fn write_expr(
+    expr: &Expr,
+    scope: &mut Scope,
+    redex: Option<&[Dir]>,
+    opts: &PrettyOpts,
+    out: &mut String,
+) {
+    if redex == Some(scope.path.as_slice()) {
+        // Nested colors would be cut short by the inner reset
+        let plain = PrettyOpts {
+            color: false,
+            ..opts.clone()
+        };
+        let mut text = String::new();
+        write_expr(expr, scope, None, &plain, &mut text);
+        paint(&text, Paint::Redex, opts, out);
+        return;
+    }
+    match expr {
+        Expr::Var(name) => {
+            out.push_str(name);
+            if opts.de_bruijn {
+                match scope.bound.iter().rev().position(|bound| bound == name) {
+                    Some(index) => write!(out, "@{}", index).unwrap(),
+                    None => out.push_str("@free"),
+                }
+            }
+        }
+        Expr::Lit(n) => out.push_str(&n.to_string()),
+        Expr::Abs(param, body) => {
+            paint(&format!("\\{}.", param), Paint::Binder, opts, out);
+            out.push(' ');
+            scope.bound.push(param.clone());
+            child(Dir::AbsBody, body, false, scope, redex, opts, out);
+            scope.bound.pop();
+        }
+        Expr::App(f, arg) => {
+            let right = opts.app_assoc == Assoc::Right;
+            child(
+                Dir::AppFun,
+                f,
+                matches!(
+                    f.as_ref(),
+                    Expr::Abs(..) | Expr::LetRecGroup(..) | Expr::If(..)
+                ) || right && matches!(f.as_ref(), Expr::App(..)),
+                scope,
+                redex,
+                opts,
+                out,
+            );
+            out.push(' ');
+            child(
+                Dir::AppArg,
+                arg,
+                match arg.as_ref() {
+                    Expr::Var(_) | Expr::Lit(_) => false,
+                    Expr::App(..) => !right,
+                    Expr::Abs(..) | Expr::LetRecGroup(..) | Expr::If(..) => true,
+                },
+                scope,
+                redex,
+                opts,
+                out,
+            );
+        }
+        // No path leads inside sugar, so nothing in it is highlighted
+        Expr::LetRecGroup(bindings, body) => {
+            let names = bindings.iter().map(|(name, _)| name.clone());
+            scope.bound.extend(names);
+            out.push_str("letrec ");
+            for (i, (name, expr)) in bindings.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(" and ");
+                }
+                paint(name, Paint::Binder, opts, out);
+                out.push_str(" = ");
+                write_expr(expr, scope, None, opts, out);
+            }
+            out.push_str(" in ");
+            write_expr(body, scope, None, opts, out);
+            let depth = scope.bound.len() - bindings.len();
+            scope.bound.truncate(depth);
+        }
+        Expr::If(cond, then, otherwise) => {
+            out.push_str("if ");
+            write_expr(cond, scope, None, opts, out);
+            out.push_str(" then ");
+            write_expr(then, scope, None, opts, out);
+            out.push_str(" else ");
+            write_expr(otherwise, scope, None, opts, out);
+        }
+    }
+}
+
+fn child(
+    dir: Dir,
+    expr: &Expr,
+    parens: bool,
+    scope: &mut Scope,
+    redex: Option<&[Dir]>,
+    opts: &PrettyOpts,
+    out: &mut String,
+) {
+    if parens {
+        out.push('(');
+    }
+    scope.path.push(dir);
+    write_expr(expr, scope, redex, opts, out);
+    scope.path.pop();
+    if parens {
+        out.push(')');
+    }
+}
+
+enum Paint {
+    Redex,
+    Binder,
+}
+
+#[cfg(feature = "color")]
+fn paint(text: &str, paint: Paint, opts: &PrettyOpts, out: &mut String) {
+    if !opts.color {
+        out.push_str(text);
+        return;
+    }
+    let code = match paint {
+        Paint::Redex => REDEX,
+        Paint::Binder => BINDER,
+    };
+    out.push_str(code);
+    out.push_str(text);
+    out.push_str(RESET);
+}
+
+#[cfg(not(feature = "color"))]
+fn paint(text: &str, _paint: Paint, _opts: &PrettyOpts, out: &mut String) {
+    out.push_str(text);
+}
+
+// LaTeX for math mode, parenthesized like `Display`, with `\,` between a
+// function and its argument. Trailing digits of a name become a subscript and
+// longer names are set in `\mathit`.
+pub fn to_latex(expr: &Expr) -> String {
+    let mut out = String::new();
+    write_latex(expr, &mut out);
+    out
+}
+
+fn write_latex(expr: &Expr, out: &mut String) {
+    match expr {
+        Expr::Var(name) => write_latex_name(name, out),
+        Expr::Lit(n) => out.push_str(&n.to_string()),
+        Expr::Abs(param, body) => {
+            out.push_str("\\lambda ");
+            write_latex_name(param, out);
+            out.push('.');
+            write_latex(body, out);
+        }
+        Expr::LetRecGroup(bindings, body) => {
+            out.push_str("\\mathbf{letrec}\\ ");
+            for (i, (name, expr)) in bindings.iter().enumerate() {
+                if i > 0 {
+                    out.push_str("\\ \\mathbf{and}\\ ");
+                }
+                write_latex_name(name, out);
+                out.push('=');
+                write_latex(expr, out);
+            }
+            out.push_str("\\ \\mathbf{in}\\ ");
+            write_latex(body, out);
+        }
+        Expr::If(cond, then, otherwise) => {
+            out.push_str("\\mathbf{if}\\ ");
+            write_latex(cond, out);
+            out.push_str("\\ \\mathbf{then}\\ ");
+            write_latex(then, out);
+            out.push_str("\\ \\mathbf{else}\\ ");
+            write_latex(otherwise, out);
+        }
+        Expr::App(f, arg) => {
+            latex_child(
+                f,
+                matches!(
+                    f.as_ref(),
+                    Expr::Abs(..) | Expr::LetRecGroup(..) | Expr::If(..)
+                ),
+                out,
+            );
+            out.push_str("\\,");
+            latex_child(
+                arg,
+                !matches!(arg.as_ref(), Expr::Var(_) | Expr::Lit(_)),
+                out,
+            );
+        }
+    }
+}
+
+fn latex_child(expr: &Expr, parens: bool, out: &mut String) {
+    if parens {
+        out.push('(');
+    }
+    write_latex(expr, out);
+    if parens {
+        out.push(')');
+    }
+}
+
+fn write_latex_name(name: &str, out: &mut String) {
+    let stem = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    let stem_latex = stem.replace('_', "\\_");
+    if stem.chars().count() > 1 {
+        write!(out, "\\mathit{{{}}}", stem_latex).unwrap();
+    } else {
+        out.push_str(&stem_latex);
+    }
+    if stem.len() < name.len() {
+        write!(out, "_{{{}}}", &name[stem.len()..]).unwrap();
+    }
+}
+
+#[test]
+fn uncolored_output_matches_display() {
+    use crate::normalize::reduce_steps;
+    use crate::{abs, app, var};
+
+    // (\x. \y. x) ((\z. z) w) v
+    let term = app(
+        app(
+            abs("x", abs("y", var("x"))),
+            app(abs("z", var("z")), var("w")),
+        ),
+        var("v"),
+    );
+    let opts = PrettyOpts::default();
+    assert_eq!(pretty(&term, &opts), term.to_string());
+
+    let trace = reduce_steps(term, 10);
+    let plain: String = trace.iter().map(|expr| format!("{}\n", expr)).collect();
+    assert_eq!(pretty_trace(&trace, &opts), plain);
+}
+
+#[cfg(feature = "color")]
+#[test]
+fn colored_output_strips_to_plain() {
+    use crate::normalize::reduce_steps;
+    use crate::{abs, app, var};
+
+    let term = abs("v", app(abs("x", var("x")), var("v")));
+    let trace = reduce_steps(term, 10);
+    let colored = pretty_trace(
+        &trace,
+        &PrettyOpts {
+            color: true,
+            ..PrettyOpts::default()
+        },
+    );
+
+    assert!(colored.starts_with(&format!(
+        "{}\\v.{} {}(\\x. x) v{}",
+        BINDER, RESET, REDEX, RESET
+    )));
+    let stripped = colored
+        .replace(REDEX, "")
+        .replace(BINDER, "")
+        .replace(RESET, "");
+    assert_eq!(stripped, pretty_trace(&trace, &PrettyOpts::default()));
+}
+
+#[test]
+fn frames_show_one_step_each() {
+    use crate::app;
+    use crate::church::{church_numeral, succ};
+    use crate::normalize::reduce_once;
+    use crate::parser::parse;
+
+    let term = app(succ(), church_numeral(1));
+    let steps = reduce_steps(term.clone(), 100).len() - 1;
+    let frames = reduction_frames(term, 100, &PrettyOpts::default());
+
+    assert_eq!(steps, 3);
+    assert_eq!(frames.len(), steps + 1);
+    for pair in frames.windows(2) {
+        let next = reduce_once(parse(&pair[0]).unwrap());
+        assert_eq!(next, Some(parse(&pair[1]).unwrap()));
+    }
+    assert_eq!(frames[3], "\\f. \\x. f (f x)");
+}
+
+#[test]
+fn generated_binders_print_readably() {
+    use crate::subst::subst;
+    use crate::{abs, app, var};
+
+    let term = abs("x", app(app(var("y"), var("x1")), var("x0")));
+    let result = subst(term, "y", var("x"));
+    let opts = PrettyOpts {
+        readable_names: true,
+        ..PrettyOpts::default()
+    };
+
+    assert_eq!(pretty(&result, &PrettyOpts::default()), "\\#x0. x x1 x0");
+    assert_eq!(pretty(&result, &opts), "\\x2. x x1 x0");
+}
+
+#[test]
+fn de_bruijn_mode_annotates_variables() {
+    use crate::{abs, app, var};
+
+    let opts = PrettyOpts {
+        de_bruijn: true,
+        ..PrettyOpts::default()
+    };
+    let term = abs("x", abs("y", app(var("x"), var("y"))));
+    assert_eq!(pretty(&term, &opts), "\\x. \\y. x@1 y@0");
+
+    // Shadowing picks the nearest binder, and the highlighted redex keeps
+    // the binders around it
+    let term = abs("x", app(abs("x", var("x")), app(var("x"), var("z"))));
+    assert_eq!(pretty(&term, &opts), "\\x. (\\x. x@0) (x@0 z@free)");
+    let term = abs("x", abs("y", app(abs("z", var("z")), var("x"))));
+    assert_eq!(
+        pretty_redex(&term, Some(&[Dir::AbsBody, Dir::AbsBody]), &opts),
+        "\\x. \\y. (\\z. z@0) x@1"
+    );
+}
+
+#[test]
+fn latex_uses_lambda_and_thin_spaces() {
+    use crate::church::church_numeral;
+    use crate::{abs, app, var};
+
+    let latex = to_latex(&church_numeral(2));
+    assert!(latex.contains("\\lambda f.\\lambda x."));
+    assert_eq!(latex, "\\lambda f.\\lambda x.f\\,(f\\,x)");
+
+    let term = app(abs("acc", var("acc")), var("x1"));
+    assert_eq!(
+        to_latex(&term),
+        "(\\lambda \\mathit{acc}.\\mathit{acc})\\,x_{1}"
+    );
+}
+
+#[test]
+fn output_round_trips_under_either_associativity() {
+    use crate::parser::{parse_with, ParserConfig};
+    use crate::{app, var};
+
+    let left = ParserConfig::default();
+    let right = ParserConfig {
+        app_assoc: Assoc::Right,
+    };
+    assert_eq!(
+        parse_with("f g h", &left),
+        Ok(app(app(var("f"), var("g")), var("h")))
+    );
+    assert_eq!(
+        parse_with("f g h", &right),
+        Ok(app(var("f"), app(var("g"), var("h"))))
+    );
+
+    for src in ["f g h", "(f g) h (\\x. x y z) (k l)", "(\\x. x) a b"] {
+        for config in [left, right] {
+            let opts = PrettyOpts {
+                app_assoc: config.app_assoc,
+                ..PrettyOpts::default()
+            };
+            let expr = parse_with(src, &config).unwrap();
+            assert_eq!(parse_with(&pretty(&expr, &opts), &config), Ok(expr));
+        }
+    }
+    assert_eq!(
+        pretty(
+            &app(app(var("f"), var("g")), var("h")),
+            &PrettyOpts {
+                app_assoc: Assoc::Right,
+                ..PrettyOpts::default()
+            }
+        ),
+        "(f g) h"
+    );
+}
@@ -0,0 +1,180 @@
+// Built-in operations supplied by the embedder. A primitive's name evaluates
+// to a constant, and once the spine built on it has `arity` arguments the
+// trampoline's `App` arm replaces it with the result of `apply`.
+//
+// The registry is handed to the trampoline in its `Evaluator` by
+// `eval_with_primitives`, so it applies to that one evaluation and to
+// nothing else running on the same thread.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::subst::free_vars;
+use crate::{eval_in, Context, EvalError, Evaluator, Expr, Value};
+
+pub trait Primitive {
+    fn name(&self) -> &str;
+    fn arity(&self) -> usize;
+    fn apply(&self, args: &[Value]) -> Result<Value, EvalError>;
+}
+
+#[derive(Clone, Default)]
+pub struct Primitives {
+    table: HashMap<String, Rc<dyn Primitive>>,
+}
+
+impl Primitives {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Replaces any primitive registered under the same name
+    pub fn register(&mut self, primitive: impl Primitive + 'static) {
+        self.table
+            .insert(primitive.name().to_string(), Rc::new(primitive));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Rc<dyn Primitive>> {
+        self.table.get(name).cloned()
+    }
+
+    // What a neutral spine evaluates to once `args` is its full argument list
+    pub(crate) fn saturate(&self, head: String, args: Vec<Value>) -> Result<Value, EvalError> {
+        match self.table.get(&head) {
+            Some(primitive) if primitive.arity() == args.len() => primitive.apply(&args),
+            _ => Ok(Value::VNeutral(head, args)),
+        }
+    }
+}
+
+// Like `eval`, with the names in `primitives` bound unless `ctx` shadows them.
+// A primitive of arity 0 is applied once, up front.
+pub fn eval_with_primitives(
+    expr: Expr,
+    mut ctx: Context,
+    primitives: &Primitives,
+) -> Result<Value, EvalError> {
+    let mut unbound: Vec<_> = free_vars(&expr)
+        .into_iter()
+        .filter(|name| !ctx.contains_key(name))
+        .collect();
+    unbound.sort();
+    for name in unbound {
+        let value = match primitives.get(&name) {
+            Some(primitive) if primitive.arity() == 0 => primitive.apply(&[])?,
+            Some(_) => Value::VNeutral(name.clone(), Vec::new()),
+            None => return Err(EvalError::UnboundVariable(name)),
+        };
+        ctx.insert(name, value);
+    }
+    let evaluator = Evaluator {
+        primitives: primitives.clone(),
+        ..Evaluator::default()
+    };
+    eval_in(expr, ctx, Rc::new(evaluator)).try_run()
+}
+
+#[cfg(test)]
+struct Max;
+
+#[cfg(test)]
+impl Primitive for Max {
+    fn name(&self) -> &str {
+        "max"
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn apply(&self, args: &[Value]) -> Result<Value, EvalError> {
+        match args {
+            [Value::VInt(a), Value::VInt(b)] => Ok(Value::VInt(*a.max(b))),
+            [Value::VInt(_), other] | [other, _] => Err(EvalError::NotAFunction(other.clone())),
+            _ => unreachable!("called with {} arguments", args.len()),
+        }
+    }
+}
+
+#[test]
+fn registered_primitives_apply_once_saturated() {
+    use crate::{abs, app, empty_context, var};
+
+    let mut primitives = Primitives::new();
+    primitives.register(Max);
+    let max_of = |a, b| app(app(var("max"), Expr::Lit(a)), Expr::Lit(b));
+
+    assert_eq!(
+        eval_with_primitives(max_of(3, 7), empty_context(), &primitives),
+        Ok(Value::VInt(7))
+    );
+    // Saturated inside a closure body, not only at the top
+    let flipped = app(
+        abs("m", app(app(var("m"), Expr::Lit(9)), Expr::Lit(4))),
+        var("max"),
+    );
+    assert_eq!(
+        eval_with_primitives(flipped, empty_context(), &primitives),
+        Ok(Value::VInt(9))
+    );
+    assert_eq!(
+        eval_with_primitives(app(var("max"), Expr::Lit(3)), empty_context(), &primitives),
+        Ok(Value::VNeutral("max".to_string(), vec![Value::VInt(3)]))
+    );
+    assert_eq!(
+        eval_with_primitives(app(var("min"), Expr::Lit(3)), empty_context(), &primitives),
+        Err(EvalError::UnboundVariable("min".to_string()))
+    );
+    // Other evaluations know nothing of the registry
+    assert_eq!(
+        crate::partial_eval(max_of(3, 7), empty_context()),
+        Ok(app(app(var("max"), Expr::Lit(3)), Expr::Lit(7)))
+    );
+}
+
+#[test]
+fn registry_stays_with_its_evaluation_even_after_a_panic() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use crate::{app, empty_context, partial_eval, var};
+
+    struct Explode;
+
+    impl Primitive for Explode {
+        fn name(&self) -> &str {
+            "explode"
+        }
+
+        fn arity(&self) -> usize {
+            1
+        }
+
+        fn apply(&self, _: &[Value]) -> Result<Value, EvalError> {
+            panic!("explode was applied")
+        }
+    }
+
+    let mut primitives = Primitives::new();
+    primitives.register(Max);
+    primitives.register(Explode);
+    let max_of = app(app(var("max"), Expr::Lit(3)), Expr::Lit(7));
+
+    // Errors from nested applications come back as values
+    let nested = app(var("max"), app(app(var("max"), Expr::Lit(3)), var("max")));
+    assert_eq!(
+        eval_with_primitives(app(nested, Expr::Lit(1)), empty_context(), &primitives),
+        Err(EvalError::NotAFunction(Value::VNeutral(
+            "max".to_string(),
+            Vec::new()
+        )))
+    );
+    let exploded = catch_unwind(AssertUnwindSafe(|| {
+        eval_with_primitives(
+            app(var("explode"), max_of.clone()),
+            empty_context(),
+            &primitives,
+        )
+    }));
+    assert!(exploded.is_err());
+    assert_eq!(partial_eval(max_of.clone(), empty_context()), Ok(max_of));
+}
@@ -0,0 +1,133 @@
+use crate::analysis::{depth, size};
+use crate::format::ResultFormatter;
+use crate::normalize::reduce_steps;
+use crate::parser::{parse, ParseError};
+use crate::session::{sorted_bindings, Session};
+use crate::subst::free_vars;
+use crate::types::infer;
+use crate::{quote, EvalError};
+
+// Turns one line of input into the text to print, `None` for blank lines
+pub fn respond(
+    session: &mut Session,
+    line: &str,
+    formatter: &dyn ResultFormatter,
+) -> Option<String> {
+    if line.trim().is_empty() {
+        return None;
+    }
+    if let Some(command) = line.trim_start().strip_prefix(':') {
+        return Some(run_command(session, command, formatter));
+    }
+    Some(match session.eval_line(line) {
+        Ok(value) => formatter.format_value(&value),
+        Err(err) => formatter.format_error(&err),
+    })
+}
+
+// A trace prints every term along the way, so it stops long before `eval`
+const TRACE_STEPS: usize = 100;
+
+// `:type`, `:free` and `:info` report on a term without evaluating it,
+// `:trace` prints its normal-order reduction, and `:env` lists the
+// definitions made so far, one per line
+fn run_command(session: &Session, command: &str, formatter: &dyn ResultFormatter) -> String {
+    let (name, src) = command
+        .split_once(char::is_whitespace)
+        .unwrap_or((command, ""));
+    if name == "env" {
+        let lines: Vec<_> = sorted_bindings(session.context())
+            .into_iter()
+            .map(|(name, value)| format!("{} = {}", name, quote(value)))
+            .collect();
+        return formatter.format_info(&lines.join("\n"));
+    }
+    let term = match parse(src) {
+        Ok(term) => term,
+        Err(err) => {
+            // Offsets count from the start of the line, past the `:` and name
+            let offset = err.offset + 1 + command.len() - src.len();
+            let err = ParseError { offset, ..err };
+            return formatter.format_error(&EvalError::Parse(err));
+        }
+    };
+    match name {
+        "type" => match infer(&term) {
+            Ok(ty) => formatter.format_info(&ty.to_string()),
+            Err(err) => formatter.format_error(&err),
+        },
+        "free" => {
+            let mut vars: Vec<_> = free_vars(&term).into_iter().collect();
+            vars.sort();
+            formatter.format_info(&format!("{{{}}}", vars.join(", ")))
+        }
+        "info" => formatter.format_info(&format!("size {}, depth {}", size(&term), depth(&term))),
+        "trace" => formatter.format_trace(&reduce_steps(term, TRACE_STEPS)),
+        _ => formatter.format_error(&EvalError::Parse(ParseError {
+            offset: 0,
+            message: format!("Unknown command :{}", name),
+        })),
+    }
+}
+
+#[test]
+fn respond_formats_values_and_errors() {
+    use crate::format::PlainFormatter;
+
+    let mut session = Session::new();
+
+    assert_eq!(
+        respond(&mut session, "id = \\x. x", &PlainFormatter),
+        Some("\\x. x".to_string())
+    );
+    assert_eq!(
+        respond(&mut session, "id y", &PlainFormatter),
+        Some("Error: Variable y not found".to_string())
+    );
+    assert_eq!(respond(&mut session, "  ", &PlainFormatter), None);
+}
+
+#[test]
+fn commands_report_without_evaluating() {
+    use crate::format::PlainFormatter;
+
+    let mut session = Session::new();
+    let mut run = |line| respond(&mut session, line, &PlainFormatter).unwrap();
+
+    assert_eq!(run(":free \\x. x y"), "{y}");
+    assert_eq!(run(":type \\x y. x"), "t0 -> t1 -> t0");
+    assert_eq!(run(":info (\\x. x) y"), "size 4, depth 3");
+    assert_eq!(run(":trace (\\x. x) y"), "(\\x. x) y\ny");
+    assert_eq!(run(":type y"), "Error: Variable y not found");
+    assert_eq!(run(":eval y"), "Error: Unknown command :eval at offset 0");
+    assert_eq!(run(":free (x"), "Error: Expected ')' at offset 8");
+    assert!(session.context().is_empty());
+}
+
+#[test]
+fn env_lists_definitions_by_name() {
+    use crate::format::PlainFormatter;
+
+    let mut session = Session::new();
+    for line in ["k = \\x. \\y. x", "id = \\x. x", "b = k id", "a = id"] {
+        respond(&mut session, line, &PlainFormatter);
+    }
+    let expected = "a = \\x. x\nb = \\y. \\x. x\nid = \\x. x\nk = \\x. \\y. x";
+
+    // A fresh map per attempt, so a different hash order would show up
+    for _ in 0..10 {
+        let mut copy = Session::new();
+        for (name, value) in sorted_bindings(session.context()).into_iter().rev() {
+            let line = format!("{} = {}", name, quote(value));
+            respond(&mut copy, &line, &PlainFormatter);
+        }
+        assert_eq!(
+            respond(&mut copy, ":env", &PlainFormatter).as_deref(),
+            Some(expected)
+        );
+    }
+    assert_eq!(
+        respond(&mut session, ":env", &PlainFormatter).as_deref(),
+        Some(expected)
+    );
+}
@@ -0,0 +1,104 @@
+// Terms as postfix token streams: operands come before the operator that
+// uses them, so a stream parses with one stack and no parentheses
+use crate::parser::ParseError;
+use crate::Expr;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Var(String),
+    Lit(i64),
+    // Binds its name in the term on top of the stack
+    Lam(String),
+    // Applies the second term from the top to the top one
+    App,
+}
+
+pub fn to_rpn(expr: &Expr) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    write_rpn(expr, &mut tokens);
+    tokens
+}
+
+fn write_rpn(expr: &Expr, tokens: &mut Vec<Token>) {
+    match expr {
+        Expr::Var(name) => tokens.push(Token::Var(name.clone())),
+        Expr::Lit(n) => tokens.push(Token::Lit(*n)),
+        Expr::Abs(param, body) => {
+            write_rpn(body, tokens);
+            tokens.push(Token::Lam(param.clone()));
+        }
+        Expr::App(f, arg) => {
+            write_rpn(f, tokens);
+            write_rpn(arg, tokens);
+            tokens.push(Token::App);
+        }
+        Expr::LetRecGroup(..) | Expr::If(..) => write_rpn(&expr.expand(), tokens),
+    }
+}
+
+// Offsets in errors count tokens rather than bytes
+pub fn from_rpn(tokens: &[Token]) -> Result<Expr, ParseError> {
+    let error = |offset, message: &str| ParseError {
+        offset,
+        message: message.to_string(),
+    };
+
+    let mut stack = Vec::new();
+    for (offset, token) in tokens.iter().enumerate() {
+        let expr = match token {
+            Token::Var(name) => Expr::Var(name.clone()),
+            Token::Lit(n) => Expr::Lit(*n),
+            Token::Lam(param) => match stack.pop() {
+                Some(body) => Expr::Abs(param.clone(), Box::new(body)),
+                None => return Err(error(offset, "Expected a body for Lam")),
+            },
+            Token::App => match (stack.pop(), stack.pop()) {
+                (Some(arg), Some(f)) => Expr::App(Box::new(f), Box::new(arg)),
+                _ => return Err(error(offset, "Expected two operands for App")),
+            },
+        };
+        stack.push(expr);
+    }
+    match (stack.pop(), stack.is_empty()) {
+        (Some(expr), true) => Ok(expr),
+        (None, _) => Err(error(tokens.len(), "Expected a term")),
+        (Some(_), false) => Err(error(tokens.len(), "Expected App to combine terms")),
+    }
+}
+
+#[test]
+fn rpn_round_trips() {
+    use crate::church::{add, church_numeral};
+    use crate::{abs, app, var};
+
+    let term = app(abs("x", var("x")), var("y"));
+    assert_eq!(
+        to_rpn(&term),
+        vec![
+            Token::Var("x".to_string()),
+            Token::Lam("x".to_string()),
+            Token::Var("y".to_string()),
+            Token::App,
+        ]
+    );
+
+    for term in [
+        term,
+        app(add(), church_numeral(2)),
+        app(var("f"), Expr::Lit(4)),
+    ] {
+        assert_eq!(from_rpn(&to_rpn(&term)), Ok(term));
+    }
+}
+
+#[test]
+fn unbalanced_rpn_is_an_error() {
+    let f = Token::Var("f".to_string());
+
+    let err = from_rpn(&[f.clone(), Token::App]).unwrap_err();
+    assert_eq!(err.offset, 1);
+    assert_eq!(err.message, "Expected two operands for App");
+
+    assert_eq!(from_rpn(&[f.clone(), f]).unwrap_err().offset, 2);
+    assert_eq!(from_rpn(&[]).unwrap_err().message, "Expected a term");
+}
@@ -0,0 +1,329 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::parser::{is_ident_char, is_name, parse, ParseError};
+use crate::subst::free_vars;
+use crate::{eval, Context, EvalError, Expr, Value};
+
+// The context along with how many transcript lines produced it
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContextSnapshot(Context, usize);
+
+// The state of an interactive session: every name defined so far
+#[derive(Clone, Debug, Default)]
+pub struct Session {
+    context: Context,
+    // Values of terms already evaluated, keyed by their printed form, with
+    // the defined names each one refers to. Redefining a name drops exactly
+    // the entries that mention it.
+    cache: HashMap<String, (Value, HashSet<String>)>,
+    evaluations: usize,
+    // Lines that `eval_line` ran successfully, in order
+    transcript: Vec<String>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    pub fn snapshot(&self) -> ContextSnapshot {
+        ContextSnapshot(self.context.clone(), self.transcript.len())
+    }
+
+    pub fn restore(&mut self, snap: ContextSnapshot) {
+        self.context = snap.0;
+        self.transcript.truncate(snap.1);
+        // Cached values may refer to definitions that no longer exist
+        self.cache.clear();
+    }
+
+    // How many terms have actually been evaluated rather than found in the
+    // cache
+    pub fn evaluations(&self) -> usize {
+        self.evaluations
+    }
+
+    // Runs `;`-separated statements, each either `name = term` or a term to
+    // evaluate, and returns the value of the last one. The `;`s between the
+    // bindings of a `where` clause stay inside their statement. If any statement
+    // fails the bindings made by the earlier ones are rolled back.
+    pub fn eval_line(&mut self, line: &str) -> Result<Value, EvalError> {
+        let snap = self.snapshot();
+        let cache = self.cache.clone();
+        let result = self.eval_statements(line);
+        match result {
+            Ok(_) => self.transcript.push(line.to_string()),
+            Err(_) => {
+                self.restore(snap);
+                self.cache = cache;
+            }
+        }
+        result
+    }
+
+    // Every line that succeeded, one per line, for `replay_transcript`
+    pub fn to_transcript(&self) -> String {
+        self.transcript
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect()
+    }
+
+    fn eval_statements(&mut self, line: &str) -> Result<Value, EvalError> {
+        let mut last = None;
+        for (start, statement) in statements(line) {
+            last = Some(self.eval_statement(statement, start)?);
+        }
+        Ok(last.unwrap())
+    }
+
+    fn eval_statement(&mut self, statement: &str, start: usize) -> Result<Value, EvalError> {
+        let parse_at = |src: &str, offset: usize| {
+            parse(src).map_err(|err| {
+                EvalError::Parse(ParseError {
+                    offset: err.offset + offset,
+                    message: err.message,
+                })
+            })
+        };
+
+        // `=` also appears inside `letrec`, so only a lone name before it
+        // makes a definition
+        match statement.split_once('=') {
+            Some((lhs, term)) if is_name(lhs) => {
+                let name = lhs.trim().to_string();
+                let expr = parse_at(term, start + lhs.len() + 1)?;
+                let value = self.eval_cached(expr)?;
+                self.cache.retain(|_, (_, deps)| !deps.contains(&name));
+                self.context.insert(name, value.clone());
+                Ok(value)
+            }
+            _ => self.eval_cached(parse_at(statement, start)?),
+        }
+    }
+
+    fn eval_cached(&mut self, expr: Expr) -> Result<Value, EvalError> {
+        let key = expr.to_string();
+        if let Some((value, _)) = self.cache.get(&key) {
+            return Ok(value.clone());
+        }
+        let deps: HashSet<_> = free_vars(&expr)
+            .into_iter()
+            .filter(|name| self.context.contains_key(name))
+            .collect();
+        self.evaluations += 1;
+        let value = eval(expr, self.context.clone())?;
+        self.cache.insert(key, (value.clone(), deps));
+        Ok(value)
+    }
+}
+
+// The statements of `line` with the offset each starts at. A `;` ends a
+// statement unless it is inside parentheses or separates the bindings of a
+// `where` clause, which go on for as long as a `name =` follows the `;`.
+fn statements(line: &str) -> Vec<(usize, &str)> {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut depth = 0usize;
+    let mut word = None;
+    let mut in_where = false;
+    for (i, c) in line.char_indices() {
+        if is_ident_char(c) {
+            word.get_or_insert(i);
+            continue;
+        }
+        if let Some(begin) = word.take() {
+            in_where |= depth == 0 && &line[begin..i] == "where";
+        }
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ';' if depth == 0 && !(in_where && starts_binding(&line[i + 1..])) => {
+                statements.push((start, &line[start..i]));
+                start = i + 1;
+                in_where = false;
+            }
+            _ => {}
+        }
+    }
+    statements.push((start, &line[start..]));
+    statements
+}
+
+fn starts_binding(rest: &str) -> bool {
+    rest.split_once('=').is_some_and(|(lhs, _)| is_name(lhs))
+}
+
+// Bindings ordered by name, for output that doesn't depend on `HashMap` order
+pub fn sorted_bindings(ctx: &Context) -> Vec<(&str, &Value)> {
+    let mut bindings: Vec<_> = ctx
+        .iter()
+        .map(|(name, value)| (name.as_str(), value))
+        .collect();
+    bindings.sort_by_key(|&(name, _)| name);
+    bindings
+}
+
+// Runs each non-blank line of a transcript on a new session, stopping at the
+// first one that fails
+pub fn replay_transcript(src: &str) -> Result<Session, EvalError> {
+    let mut session = Session::new();
+    for line in src.lines().filter(|line| !line.trim().is_empty()) {
+        session.eval_line(line)?;
+    }
+    Ok(session)
+}
+
+// Which definition wins when both contexts given to `merge_contexts` bind a
+// name
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MergePolicy {
+    PreferOverlay,
+    PreferBase,
+    ErrorOnConflict,
+}
+
+pub fn merge_contexts(
+    mut base: Context,
+    overlay: Context,
+    policy: MergePolicy,
+) -> Result<Context, EvalError> {
+    if policy == MergePolicy::ErrorOnConflict {
+        if let Some(name) = overlay.keys().filter(|name| base.contains_key(*name)).min() {
+            return Err(EvalError::NameConflict(name.clone()));
+        }
+    }
+    for (name, value) in overlay {
+        if policy != MergePolicy::PreferBase || !base.contains_key(&name) {
+            base.insert(name, value);
+        }
+    }
+    Ok(base)
+}
+
+#[test]
+fn definitions_are_visible_to_later_lines() {
+    let mut session = Session::new();
+    let id = session.eval_line("id = \\x. x").unwrap();
+
+    assert_eq!(session.eval_line("id id"), Ok(id.clone()));
+    assert_eq!(session.eval_line("k = \\x y. x; k id k"), Ok(id));
+    assert!(session.context().contains_key("k"));
+}
+
+#[test]
+fn where_bindings_stay_inside_their_statement() {
+    let mut session = Session::new();
+    let id = session.eval_line("\\b. b").unwrap();
+
+    assert_eq!(
+        session.eval_line("x y where x = \\a. a; y = \\b. b"),
+        Ok(id.clone())
+    );
+    assert_eq!(
+        session.eval_line("f = x where x = \\b. b; f f"),
+        Ok(id.clone())
+    );
+    assert_eq!(session.context()["f"], id);
+    assert!(!session.context().contains_key("x"));
+
+    let mut session = Session::new();
+    session
+        .eval_line("g = (x y where x = \\a. a; y = \\b. b); h = g")
+        .unwrap();
+    assert_eq!(session.context()["g"], id);
+    assert!(session.context().contains_key("h"));
+}
+
+#[test]
+fn failing_line_leaves_context_unchanged() {
+    let mut session = Session::new();
+    session.eval_line("id = \\x. x").unwrap();
+    let before = session.context().clone();
+
+    assert_eq!(
+        session.eval_line("k = \\x y. x; id = k; k missing"),
+        Err(EvalError::UnboundVariable("missing".to_string()))
+    );
+    assert_eq!(session.context(), &before);
+
+    assert!(matches!(
+        session.eval_line("k = \\x y. x; (id"),
+        Err(EvalError::Parse(ParseError { offset: 16, .. }))
+    ));
+    assert_eq!(session.context(), &before);
+}
+
+#[test]
+fn transcripts_replay_to_the_same_context() {
+    let mut session = Session::new();
+    session.eval_line("id = \\x. x; k = \\x y. x").unwrap();
+    session.eval_line("k id").unwrap();
+    assert!(session.eval_line("id missing").is_err());
+    session
+        .eval_line("two = \\f x. f (f x); id = two k")
+        .unwrap();
+
+    let transcript = session.to_transcript();
+    assert_eq!(transcript.lines().count(), 3);
+    let replayed = replay_transcript(&transcript).unwrap();
+
+    assert_eq!(replayed.context().len(), session.context().len());
+    for (name, value) in session.context() {
+        assert_eq!(&replayed.context()[name], value, "{} differs", name);
+    }
+    assert_eq!(replayed.to_transcript(), transcript);
+
+    assert_eq!(
+        replay_transcript("id = \\x. x\n\nid y").err(),
+        Some(EvalError::UnboundVariable("y".to_string()))
+    );
+}
+
+#[test]
+fn merge_policies_resolve_shared_names() {
+    let mut prelude = Session::new();
+    prelude.eval_line("id = \\x. x; k = \\x y. x").unwrap();
+    let mut user = Session::new();
+    user.eval_line("id = \\y. y y; two = \\f x. f (f x)")
+        .unwrap();
+    let base = prelude.context().clone();
+    let overlay = user.context().clone();
+
+    let merged = merge_contexts(base.clone(), overlay.clone(), MergePolicy::PreferOverlay);
+    let merged = merged.unwrap();
+    assert_eq!(merged.len(), 3);
+    assert_eq!(merged["id"], overlay["id"]);
+
+    let merged = merge_contexts(base.clone(), overlay.clone(), MergePolicy::PreferBase);
+    let merged = merged.unwrap();
+    assert_eq!(merged.len(), 3);
+    assert_eq!(merged["id"], base["id"]);
+    assert_eq!(merged["two"], overlay["two"]);
+
+    assert_eq!(
+        merge_contexts(base, overlay, MergePolicy::ErrorOnConflict),
+        Err(EvalError::NameConflict("id".to_string()))
+    );
+}
+
+#[test]
+fn redefinition_invalidates_only_dependent_results() {
+    let mut session = Session::new();
+    session.eval_line("id = \\x. x; k = \\x y. x").unwrap();
+    let first = session.eval_line("id k").unwrap();
+
+    session.eval_line("unrelated = \\z. z").unwrap();
+    let before = session.evaluations();
+    assert_eq!(session.eval_line("id k"), Ok(first.clone()));
+    assert_eq!(session.evaluations(), before);
+
+    session.eval_line("k = \\x y. y").unwrap();
+    let before = session.evaluations();
+    assert_ne!(session.eval_line("id k"), Ok(first));
+    assert_eq!(session.evaluations(), before + 1);
+}
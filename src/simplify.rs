@@ -0,0 +1,238 @@
+// Rewrites that only ever shrink a term, for cleaning up generated terms
+// without risking divergence: eta-reduction, `(\x. x) e` to `e`, and with
+// the `primitive` feature `#add` applied to two literals replaced by their
+// sum. No other beta step is taken.
+#[cfg(feature = "primitive")]
+use crate::primitive::Primitive;
+use crate::primitive::Primitives;
+use crate::subst::free_vars;
+#[cfg(feature = "primitive")]
+use crate::EvalError;
+use crate::{app, Expr, Value};
+
+// The built-in addition `simplify` folds. The parser rejects `#`, so it
+// can't be one of the term's own variables.
+#[cfg(feature = "primitive")]
+pub const NATIVE_ADD: &str = "#add";
+
+#[cfg(feature = "primitive")]
+struct NativeAdd;
+
+#[cfg(feature = "primitive")]
+impl Primitive for NativeAdd {
+    fn name(&self) -> &str {
+        NATIVE_ADD
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn apply(&self, args: &[Value]) -> Result<Value, EvalError> {
+        match args {
+            [Value::VInt(a), Value::VInt(b)] => Ok(Value::VInt(a + b)),
+            [Value::VInt(_), other] | [other, _] => Err(EvalError::NotAFunction(other.clone())),
+            _ => unreachable!("called with {} arguments", args.len()),
+        }
+    }
+}
+
+pub fn simplify(expr: Expr) -> Expr {
+    simplify_with(expr, &builtins())
+}
+
+#[cfg(feature = "primitive")]
+fn builtins() -> Primitives {
+    let mut primitives = Primitives::new();
+    primitives.register(NativeAdd);
+    primitives
+}
+
+#[cfg(not(feature = "primitive"))]
+fn builtins() -> Primitives {
+    Primitives::new()
+}
+
+// Like `simplify`, folding the primitives in `primitives` instead of the
+// built-in ones wherever their name isn't shadowed by a binder
+pub fn simplify_with(expr: Expr, primitives: &Primitives) -> Expr {
+    let mut current = expr;
+    loop {
+        let next = simplify_pass(current.clone(), primitives, &mut Vec::new());
+        if next == current {
+            return next;
+        }
+        current = next;
+    }
+}
+
+// Bottom-up, so a rewrite can expose another one in the node above it
+fn simplify_pass(expr: Expr, primitives: &Primitives, bound: &mut Vec<String>) -> Expr {
+    match expr {
+        Expr::Var(_) | Expr::Lit(_) => expr,
+        Expr::LetRecGroup(bindings, body) => {
+            let depth = bound.len();
+            bound.extend(bindings.iter().map(|(name, _)| name.clone()));
+            let bindings = bindings
+                .into_iter()
+                .map(|(name, expr)| (name, simplify_pass(expr, primitives, bound)))
+                .collect();
+            let body = simplify_pass(*body, primitives, bound);
+            bound.truncate(depth);
+            Expr::LetRecGroup(bindings, Box::new(body))
+        }
+        Expr::If(cond, then, otherwise) => Expr::If(
+            Box::new(simplify_pass(*cond, primitives, bound)),
+            Box::new(simplify_pass(*then, primitives, bound)),
+            Box::new(simplify_pass(*otherwise, primitives, bound)),
+        ),
+        Expr::Abs(param, body) => {
+            bound.push(param.clone());
+            let body = simplify_pass(*body, primitives, bound);
+            bound.pop();
+            match body {
+                Expr::App(f, arg)
+                    if arg.as_var() == Some(param.as_str()) && !free_vars(&f).contains(&param) =>
+                {
+                    *f
+                }
+                body => Expr::Abs(param, Box::new(body)),
+            }
+        }
+        Expr::App(f, arg) => {
+            let f = simplify_pass(*f, primitives, bound);
+            let arg = simplify_pass(*arg, primitives, bound);
+            match f.as_abs() {
+                Some((param, body)) if body.as_var() == Some(param) => arg,
+                _ => fold_primitive(app(f, arg), primitives, bound),
+            }
+        }
+    }
+}
+
+// `p l1 ... ln` for a primitive `p` of arity `n` and literals `li`, with the
+// result in its place if it is an integer
+fn fold_primitive(expr: Expr, primitives: &Primitives, bound: &[String]) -> Expr {
+    let mut args = Vec::new();
+    let mut head = &expr;
+    while let Expr::App(f, arg) = head {
+        let Expr::Lit(n) = **arg else {
+            return expr;
+        };
+        args.push(Value::VInt(n));
+        head = f;
+    }
+    args.reverse();
+    let Some(name) = head
+        .as_var()
+        .filter(|name| !bound.iter().any(|b| b == name))
+    else {
+        return expr;
+    };
+    match primitives.get(name) {
+        Some(primitive) if primitive.arity() == args.len() => match primitive.apply(&args) {
+            Ok(Value::VInt(n)) => Expr::Lit(n),
+            _ => expr,
+        },
+        _ => expr,
+    }
+}
+
+#[test]
+fn simplify_removes_identities_and_eta_redexes() {
+    use crate::{abs, var};
+
+    // (\x. x) (\y. f y)
+    let term = app(abs("x", var("x")), abs("y", app(var("f"), var("y"))));
+    assert_eq!(simplify(term), var("f"));
+
+    // Only the identity is applied, \x. x x stays a redex
+    let omega = app(
+        abs("x", app(var("x"), var("x"))),
+        abs("x", app(var("x"), var("x"))),
+    );
+    assert_eq!(simplify(omega.clone()), omega);
+    // \x. (\z. z) g x needs both rewrites, one exposing the other
+    let nested = abs("x", app(app(abs("z", var("z")), var("g")), var("x")));
+    assert_eq!(simplify(nested), var("g"));
+}
+
+#[cfg(feature = "primitive")]
+#[test]
+fn simplify_folds_native_addition() {
+    use crate::{abs, var};
+
+    let add = |a, b| app(app(var(NATIVE_ADD), a), b);
+
+    assert_eq!(simplify(add(Expr::Lit(1), Expr::Lit(2))), Expr::Lit(3));
+    assert_eq!(
+        simplify(add(add(Expr::Lit(1), Expr::Lit(2)), Expr::Lit(4))),
+        Expr::Lit(7)
+    );
+    // An identity in the way is removed first
+    let wrapped = add(app(abs("x", var("x")), Expr::Lit(1)), Expr::Lit(2));
+    assert_eq!(simplify(wrapped), Expr::Lit(3));
+    let open = add(Expr::Lit(1), var("n"));
+    assert_eq!(simplify(open.clone()), open);
+    let shadowed = abs(NATIVE_ADD, add(Expr::Lit(1), Expr::Lit(2)));
+    assert_eq!(simplify(shadowed.clone()), shadowed);
+}
+
+#[cfg(not(feature = "primitive"))]
+#[test]
+fn simplify_folds_nothing_without_the_primitive_feature() {
+    use crate::var;
+
+    let add = app(app(var("#add"), Expr::Lit(1)), Expr::Lit(2));
+    assert_eq!(simplify(add.clone()), add);
+}
+
+#[test]
+fn simplify_with_folds_registered_primitives() {
+    use crate::primitive::Primitive;
+    use crate::{abs, var, EvalError};
+
+    struct Add;
+
+    impl Primitive for Add {
+        fn name(&self) -> &str {
+            "add"
+        }
+
+        fn arity(&self) -> usize {
+            2
+        }
+
+        fn apply(&self, args: &[Value]) -> Result<Value, EvalError> {
+            match args {
+                [Value::VInt(a), Value::VInt(b)] => Ok(Value::VInt(a + b)),
+                _ => Err(EvalError::NotAFunction(args[0].clone())),
+            }
+        }
+    }
+
+    let mut primitives = Primitives::new();
+    primitives.register(Add);
+    let add = |a, b| app(app(var("add"), a), b);
+
+    assert_eq!(
+        simplify_with(add(Expr::Lit(1), Expr::Lit(2)), &primitives),
+        Expr::Lit(3)
+    );
+    assert_eq!(
+        simplify_with(
+            add(add(Expr::Lit(1), Expr::Lit(2)), Expr::Lit(4)),
+            &primitives
+        ),
+        Expr::Lit(7)
+    );
+    // Not saturated with literals, or not the primitive
+    let open = add(Expr::Lit(1), var("n"));
+    assert_eq!(simplify_with(open.clone(), &primitives), open);
+    let shadowed = abs("add", add(Expr::Lit(1), Expr::Lit(2)));
+    assert_eq!(simplify_with(shadowed.clone(), &primitives), shadowed);
+    assert_eq!(
+        simplify(add(Expr::Lit(1), Expr::Lit(2))),
+        add(Expr::Lit(1), Expr::Lit(2))
+    );
+}
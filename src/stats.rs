@@ -0,0 +1,128 @@
+// How much memory an evaluation needs, measured by the trampoline evaluator
+// as it goes
+use std::rc::Rc;
+
+use crate::subst::free_vars;
+use crate::{eval_in, Context, Evaluator, Expr, Value};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResourceStats {
+    // The largest context any subterm was evaluated in
+    pub max_context_entries: usize,
+    // The most `Continue` closures running at once. Each one finishes the
+    // function and argument of its application with nested `run`s, so this
+    // is how deep those runs nest.
+    pub max_trampoline_queue_closures: usize,
+    // Closures built, `letrec` knots tied and neutral spines extended
+    pub total_values_allocated: usize,
+    // Context entries dropped by `eval_with_gc` because the term being
+    // evaluated could not refer to them
+    pub bindings_collected: usize,
+}
+
+#[derive(Default)]
+pub struct Meter {
+    pub stats: ResourceStats,
+    running: usize,
+    // Prune the context of every this many evaluations, never if `None`
+    gc_every: Option<usize>,
+    evaluations: usize,
+}
+
+impl Meter {
+    // The context `expr` is evaluated in, pruned if this evaluation is one
+    // to collect at. Starts with the first, which has the largest context.
+    pub fn enter(&mut self, expr: &Expr, mut context: Context) -> Context {
+        let evaluation = self.evaluations;
+        self.evaluations += 1;
+        if let Some(every) = self.gc_every {
+            if evaluation.is_multiple_of(every.max(1)) {
+                let live = free_vars(expr);
+                let before = context.len();
+                context.retain(|name, _| live.contains(name));
+                self.stats.bindings_collected += before - context.len();
+            }
+        }
+        self.stats.max_context_entries = self.stats.max_context_entries.max(context.len());
+        context
+    }
+
+    pub fn push(&mut self) {
+        self.running += 1;
+        self.stats.max_trampoline_queue_closures =
+            self.stats.max_trampoline_queue_closures.max(self.running);
+    }
+
+    pub fn pop(&mut self) {
+        self.running -= 1;
+    }
+}
+
+pub fn eval_with_stats(expr: Expr, ctx: Context) -> (Value, ResourceStats) {
+    run_metered(expr, ctx, Meter::default())
+}
+
+// Like `eval_with_stats`, but every `every` evaluations the context is cut
+// down to the free variables of the term about to be evaluated, so closures
+// built from it stop holding on to bindings they can never use. Values come
+// out with smaller captured contexts and so compare unequal to those of
+// `eval_with_trampoline`, but quote to the same terms.
+pub fn eval_with_gc(expr: Expr, ctx: Context, every: usize) -> (Value, ResourceStats) {
+    let meter = Meter {
+        gc_every: Some(every),
+        ..Meter::default()
+    };
+    run_metered(expr, ctx, meter)
+}
+
+fn run_metered(expr: Expr, ctx: Context, meter: Meter) -> (Value, ResourceStats) {
+    let evaluator = Rc::new(Evaluator {
+        meter: Some(meter.into()),
+        ..Evaluator::default()
+    });
+    let value = eval_in(expr, ctx, evaluator.clone()).run();
+    let stats = match &evaluator.meter {
+        Some(meter) => meter.take().stats,
+        None => ResourceStats::default(),
+    };
+    (value, stats)
+}
+
+#[test]
+fn peak_context_grows_with_nested_bindings() {
+    use crate::{abs, app, eval_with_trampoline, var};
+    use std::collections::HashMap;
+
+    // (\a. (\b. (\c. c) b) a) (\x. x)
+    let term = app(
+        abs(
+            "a",
+            app(abs("b", app(abs("c", var("c")), var("b"))), var("a")),
+        ),
+        abs("x", var("x")),
+    );
+    let (value, stats) = eval_with_stats(term.clone(), HashMap::new());
+
+    assert_eq!(value, eval_with_trampoline(term, HashMap::new()).run());
+    assert_eq!(stats.max_context_entries, 3);
+    assert_eq!(stats.total_values_allocated, 4);
+    assert_eq!(stats.max_trampoline_queue_closures, 1);
+}
+
+#[test]
+fn gc_keeps_results_and_shrinks_contexts() {
+    use crate::parser::parse;
+    use crate::prelude::prelude_context;
+    use crate::quote;
+
+    // (2 + 3) * 4, with I standing for one
+    let term = parse("mul (add (succ I) (succ (succ I))) (succ (succ (succ I)))").unwrap();
+    let (plain, plain_stats) = eval_with_stats(term.clone(), prelude_context());
+    let (collected, gc_stats) = eval_with_gc(term, prelude_context(), 4);
+
+    assert_eq!(quote(&collected), quote(&plain));
+    assert_eq!(u64::try_from(&collected), Ok(20));
+    assert_eq!(plain_stats.bindings_collected, 0);
+    assert!(gc_stats.bindings_collected > 0);
+    assert!(gc_stats.max_context_entries < plain_stats.max_context_entries);
+}
@@ -0,0 +1,403 @@
+use std::collections::HashSet;
+
+use crate::analysis::size;
+use crate::{EvalError, Expr};
+
+pub fn free_vars(expr: &Expr) -> HashSet<String> {
+    match expr {
+        Expr::Var(name) => HashSet::from([name.clone()]),
+        Expr::Lit(_) => HashSet::new(),
+        Expr::Abs(param, body) => {
+            let mut vars = free_vars(body);
+            vars.remove(param);
+            vars
+        }
+        Expr::App(f, arg) => {
+            let mut vars = free_vars(f);
+            vars.extend(free_vars(arg));
+            vars
+        }
+        Expr::LetRecGroup(bindings, body) => {
+            let mut vars = free_vars(body);
+            for (_, expr) in bindings {
+                vars.extend(free_vars(expr));
+            }
+            for (name, _) in bindings {
+                vars.remove(name);
+            }
+            vars
+        }
+        Expr::If(cond, then, otherwise) => {
+            let mut vars = free_vars(cond);
+            vars.extend(free_vars(then));
+            vars.extend(free_vars(otherwise));
+            vars
+        }
+    }
+}
+
+// Whether `var` is free in `expr` and not bound by the group at its top
+fn binds_or_misses(expr: &Expr, var: &str) -> bool {
+    match expr {
+        Expr::LetRecGroup(bindings, _) if bindings.iter().any(|(name, _)| name == var) => true,
+        _ => !free_vars(expr).contains(var),
+    }
+}
+
+// The group with the names that occur in `value_vars` renamed, so that
+// substituting for `var` inside it captures nothing
+fn rename_group(
+    bindings: Vec<(String, Expr)>,
+    body: Expr,
+    var: &str,
+    value_vars: &HashSet<String>,
+) -> (Vec<(String, Expr)>, Expr) {
+    let mut avoid = value_vars.clone();
+    avoid.insert(var.to_string());
+    avoid.extend(free_vars(&body));
+    for (name, expr) in &bindings {
+        avoid.insert(name.clone());
+        avoid.extend(free_vars(expr));
+    }
+    let mut names: Vec<_> = bindings.iter().map(|(name, _)| name.clone()).collect();
+    let mut exprs: Vec<_> = bindings.into_iter().map(|(_, expr)| expr).collect();
+    let mut body = body;
+    for name in &mut names {
+        if !value_vars.contains(name) {
+            continue;
+        }
+        let renamed = fresh_name(name, &avoid);
+        avoid.insert(renamed.clone());
+        let new = || Expr::Var(renamed.clone());
+        exprs = exprs
+            .into_iter()
+            .map(|expr| subst(expr, name, new()))
+            .collect();
+        body = subst(body, name, new());
+        *name = renamed;
+    }
+    (names.into_iter().zip(exprs).collect(), body)
+}
+
+// Picks `base` with a numeric suffix (x0, x1, ...) that is not in `avoid`,
+// for names meant to be read, such as decoded binders
+pub fn readable_name(base: &str, avoid: &HashSet<String>) -> String {
+    let stem = base.trim_end_matches(|c: char| c.is_ascii_digit());
+    (0..)
+        .map(|i| format!("{}{}", stem, i))
+        .find(|candidate| !avoid.contains(candidate))
+        .unwrap()
+}
+
+// Picks a name for a renamed binder from `FreshGen`'s reserved prefix (#x0,
+// #x1, ... for base `x`) that is not in `avoid`. No name from source can
+// clash with it, so `avoid` only matters for names generated earlier.
+pub fn fresh_name(base: &str, avoid: &HashSet<String>) -> String {
+    let mut gen = FreshGen::new();
+    loop {
+        let name = gen.fresh(base);
+        if !avoid.contains(&name) {
+            return name;
+        }
+    }
+}
+
+// Hands out names that start with `prefix` and end in a counter. With a
+// prefix the parser rejects, such as the default `#`, they can't clash with
+// any name from source or with each other, so no avoid set is needed. Use
+// one generator for everything derived from the same term.
+#[derive(Clone, Debug)]
+pub struct FreshGen {
+    prefix: String,
+    next: usize,
+}
+
+impl Default for FreshGen {
+    fn default() -> Self {
+        FreshGen::with_prefix("#")
+    }
+}
+
+impl FreshGen {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_prefix(prefix: &str) -> Self {
+        FreshGen {
+            prefix: prefix.to_string(),
+            next: 0,
+        }
+    }
+
+    // `#x0`, `#x1`, ... for base `x`, keeping the stem for readability
+    pub fn fresh(&mut self, base: &str) -> String {
+        let stem = base
+            .trim_start_matches(self.prefix.as_str())
+            .trim_end_matches(|c: char| c.is_ascii_digit());
+        let name = format!("{}{}{}", self.prefix, stem, self.next);
+        self.next += 1;
+        name
+    }
+}
+
+// Capture-avoiding substitution of `value` for the free occurrences of `var`
+pub fn subst(expr: Expr, var: &str, value: Expr) -> Expr {
+    match expr {
+        Expr::Var(name) if name == var => value,
+        Expr::Var(_) | Expr::Lit(_) => expr,
+        Expr::App(f, arg) => Expr::App(
+            Box::new(subst(*f, var, value.clone())),
+            Box::new(subst(*arg, var, value)),
+        ),
+        Expr::Abs(ref param, ref body) if param == var || !free_vars(body).contains(var) => expr,
+        Expr::Abs(param, body) => {
+            let value_vars = free_vars(&value);
+            if value_vars.contains(&param) {
+                let mut avoid = value_vars;
+                avoid.extend(free_vars(&body));
+                avoid.insert(var.to_string());
+                let renamed = fresh_name(&param, &avoid);
+                let body = subst(*body, &param, Expr::Var(renamed.clone()));
+                Expr::Abs(renamed, Box::new(subst(body, var, value)))
+            } else {
+                Expr::Abs(param, Box::new(subst(*body, var, value)))
+            }
+        }
+        Expr::LetRecGroup(..) if binds_or_misses(&expr, var) => expr,
+        Expr::LetRecGroup(bindings, body) => subst_group(bindings, *body, var, value),
+        Expr::If(cond, then, otherwise) => subst_if(*cond, *then, *otherwise, var, value),
+    }
+}
+
+// The sugar nodes are substituted into apart from `subst`, so as not to make
+// its frames, one per level of the term, any bigger
+fn subst_group(bindings: Vec<(String, Expr)>, body: Expr, var: &str, value: Expr) -> Expr {
+    let (bindings, body) = rename_group(bindings, body, var, &free_vars(&value));
+    let bindings = bindings
+        .into_iter()
+        .map(|(name, expr)| (name, subst(expr, var, value.clone())))
+        .collect();
+    Expr::LetRecGroup(bindings, Box::new(subst(body, var, value)))
+}
+
+fn subst_if(cond: Expr, then: Expr, otherwise: Expr, var: &str, value: Expr) -> Expr {
+    Expr::If(
+        Box::new(subst(cond, var, value.clone())),
+        Box::new(subst(then, var, value.clone())),
+        Box::new(subst(otherwise, var, value)),
+    )
+}
+
+// Like `subst`, but every node of the result is paid for out of `budget`,
+// so substitutions that would duplicate `value` too many times fail with
+// `SubstBudgetExceeded` instead of exhausting memory
+pub fn subst_guarded(
+    expr: Expr,
+    var: &str,
+    value: Expr,
+    budget: &mut usize,
+) -> Result<Expr, EvalError> {
+    let value_size = size(&value);
+    subst_guarded_inner(expr, var, &value, value_size, budget)
+}
+
+fn spend(budget: &mut usize, nodes: usize) -> Result<(), EvalError> {
+    *budget = budget
+        .checked_sub(nodes)
+        .ok_or(EvalError::SubstBudgetExceeded)?;
+    Ok(())
+}
+
+fn subst_guarded_inner(
+    expr: Expr,
+    var: &str,
+    value: &Expr,
+    value_size: usize,
+    budget: &mut usize,
+) -> Result<Expr, EvalError> {
+    match expr {
+        Expr::Var(name) if name == var => {
+            spend(budget, value_size)?;
+            Ok(value.clone())
+        }
+        Expr::Var(_) | Expr::Lit(_) => {
+            spend(budget, 1)?;
+            Ok(expr)
+        }
+        Expr::App(f, arg) => {
+            spend(budget, 1)?;
+            let f = subst_guarded_inner(*f, var, value, value_size, budget)?;
+            let arg = subst_guarded_inner(*arg, var, value, value_size, budget)?;
+            Ok(Expr::App(Box::new(f), Box::new(arg)))
+        }
+        Expr::Abs(ref param, ref body) if param == var || !free_vars(body).contains(var) => {
+            spend(budget, size(&expr))?;
+            Ok(expr)
+        }
+        Expr::Abs(param, body) => {
+            spend(budget, 1)?;
+            let value_vars = free_vars(value);
+            if value_vars.contains(&param) {
+                let mut avoid = value_vars;
+                avoid.extend(free_vars(&body));
+                avoid.insert(var.to_string());
+                let renamed = fresh_name(&param, &avoid);
+                let body = subst(*body, &param, Expr::Var(renamed.clone()));
+                let body = subst_guarded_inner(body, var, value, value_size, budget)?;
+                Ok(Expr::Abs(renamed, Box::new(body)))
+            } else {
+                let body = subst_guarded_inner(*body, var, value, value_size, budget)?;
+                Ok(Expr::Abs(param, Box::new(body)))
+            }
+        }
+        Expr::LetRecGroup(..) if binds_or_misses(&expr, var) => {
+            spend(budget, size(&expr))?;
+            Ok(expr)
+        }
+        Expr::LetRecGroup(bindings, body) => {
+            spend(budget, 1)?;
+            let (bindings, body) = rename_group(bindings, *body, var, &free_vars(value));
+            let bindings = bindings
+                .into_iter()
+                .map(|(name, expr)| {
+                    let expr = subst_guarded_inner(expr, var, value, value_size, budget)?;
+                    Ok((name, expr))
+                })
+                .collect::<Result<_, EvalError>>()?;
+            let body = subst_guarded_inner(body, var, value, value_size, budget)?;
+            Ok(Expr::LetRecGroup(bindings, Box::new(body)))
+        }
+        Expr::If(cond, then, otherwise) => {
+            spend(budget, 1)?;
+            let cond = subst_guarded_inner(*cond, var, value, value_size, budget)?;
+            let then = subst_guarded_inner(*then, var, value, value_size, budget)?;
+            let otherwise = subst_guarded_inner(*otherwise, var, value, value_size, budget)?;
+            Ok(Expr::If(
+                Box::new(cond),
+                Box::new(then),
+                Box::new(otherwise),
+            ))
+        }
+    }
+}
+
+// Substitution without renaming, returning the binders that captured a free
+// variable of `value`. Only useful to show what goes wrong without `subst`.
+pub fn subst_naive(expr: Expr, var: &str, value: Expr) -> (Expr, Vec<String>) {
+    let mut captured = Vec::new();
+    let result = subst_naive_inner(expr, var, &value, &free_vars(&value), &mut captured);
+    (result, captured)
+}
+
+fn subst_naive_inner(
+    expr: Expr,
+    var: &str,
+    value: &Expr,
+    value_vars: &HashSet<String>,
+    captured: &mut Vec<String>,
+) -> Expr {
+    match expr {
+        Expr::Var(name) if name == var => value.clone(),
+        Expr::Var(_) | Expr::Lit(_) => expr,
+        Expr::App(f, arg) => Expr::App(
+            Box::new(subst_naive_inner(*f, var, value, value_vars, captured)),
+            Box::new(subst_naive_inner(*arg, var, value, value_vars, captured)),
+        ),
+        Expr::Abs(ref param, ref body) if param == var || !free_vars(body).contains(var) => expr,
+        Expr::Abs(param, body) => {
+            if value_vars.contains(&param) && !captured.contains(&param) {
+                captured.push(param.clone());
+            }
+            let body = subst_naive_inner(*body, var, value, value_vars, captured);
+            Expr::Abs(param, Box::new(body))
+        }
+        Expr::LetRecGroup(..) if binds_or_misses(&expr, var) => expr,
+        Expr::LetRecGroup(bindings, body) => {
+            for (name, _) in &bindings {
+                if value_vars.contains(name) && !captured.contains(name) {
+                    captured.push(name.clone());
+                }
+            }
+            let bindings = bindings
+                .into_iter()
+                .map(|(name, expr)| {
+                    let expr = subst_naive_inner(expr, var, value, value_vars, captured);
+                    (name, expr)
+                })
+                .collect();
+            let body = subst_naive_inner(*body, var, value, value_vars, captured);
+            Expr::LetRecGroup(bindings, Box::new(body))
+        }
+        Expr::If(cond, then, otherwise) => Expr::If(
+            Box::new(subst_naive_inner(*cond, var, value, value_vars, captured)),
+            Box::new(subst_naive_inner(*then, var, value, value_vars, captured)),
+            Box::new(subst_naive_inner(
+                *otherwise, var, value, value_vars, captured,
+            )),
+        ),
+    }
+}
+
+#[test]
+fn naive_substitution_reports_capture() {
+    use crate::{abs, var};
+
+    // (\y. x)[x := y]
+    let term = abs("y", var("x"));
+
+    let (naive, captured) = subst_naive(term.clone(), "x", var("y"));
+    let safe = subst(term, "x", var("y"));
+
+    assert_eq!(captured, vec!["y".to_string()]);
+    assert_eq!(naive, abs("y", var("y")));
+    assert_eq!(safe, abs("#y0", var("y")));
+    assert_ne!(naive, safe);
+}
+
+#[test]
+fn generated_names_never_meet_source_names() {
+    use crate::normalize::normalize;
+    use crate::parser::is_name;
+    use crate::{abs, app, var};
+
+    // (\x. y x1 x0)[y := x], where a numeric suffix would have to skip x0
+    // and x1
+    let term = abs("x", app(app(var("y"), var("x1")), var("x0")));
+    let result = subst(term.clone(), "y", var("x"));
+
+    let Expr::Abs(renamed, _) = &result else {
+        panic!("expected an abstraction")
+    };
+    assert_eq!(renamed, "#x0");
+    assert!(!is_name(renamed));
+    assert_eq!(result, abs("#x0", app(app(var("x"), var("x1")), var("x0"))));
+    // The same renaming when normalizing (\y. \x. y x1 x0) x
+    assert_eq!(normalize(app(abs("y", term), var("x")), 10), Ok(result));
+
+    let mut gen = FreshGen::new();
+    assert_eq!(gen.fresh("x"), "#x0");
+    assert_eq!(gen.fresh("#x0"), "#x1");
+    assert_eq!(FreshGen::with_prefix("%").fresh("y"), "%y0");
+    assert_eq!(fresh_name("x1", &HashSet::from(["#x0".to_string()])), "#x1");
+}
+
+#[test]
+fn guarded_subst_stops_exponential_growth() {
+    use crate::{abs, app, var};
+
+    // Substituting into `x x` over and over doubles the term every time
+    let mut budget = 10_000;
+    let result = (0..64).try_fold(var("y"), |term, _| {
+        subst_guarded(app(var("x"), var("x")), "x", term, &mut budget)
+    });
+    assert_eq!(result, Err(EvalError::SubstBudgetExceeded));
+
+    let term = abs("y", app(var("x"), var("y")));
+    let mut budget = 100;
+    assert_eq!(
+        subst_guarded(term.clone(), "x", var("y"), &mut budget),
+        Ok(subst(term, "x", var("y")))
+    );
+    assert_eq!(budget, 100 - 4);
+}
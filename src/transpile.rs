@@ -0,0 +1,143 @@
+// Emits Rust source for a closed term. Every value is a `Lam`, a shared
+// closure from `Lam` to `Lam`, so untyped terms still type-check:
+// abstractions become `move |v| ...` closures and applications become
+// `call`s. Binders are renamed to `v0`, `v1`, ... so any name is a valid
+// Rust identifier.
+use std::fmt::Write;
+
+use crate::subst::free_vars;
+use crate::{rename_canonical, EvalError, Expr, Value};
+
+const RUNTIME: &str = "\
+#[derive(Clone)]
+pub struct Lam(std::rc::Rc<dyn Fn(Lam) -> Lam>);
+
+impl Lam {
+    pub fn call(&self, arg: Lam) -> Lam {
+        (self.0)(arg)
+    }
+}
+
+fn lam(f: impl Fn(Lam) -> Lam + 'static) -> Lam {
+    Lam(std::rc::Rc::new(f))
+}
+";
+
+// The output defines `Lam` along with `fn_name`, so it is meant to be a
+// file of its own. Free variables and literals have no `Lam` to become.
+pub fn transpile_to_rust(expr: &Expr, fn_name: &str) -> Result<String, EvalError> {
+    let mut free: Vec<_> = free_vars(expr).into_iter().collect();
+    free.sort();
+    if let Some(name) = free.into_iter().next() {
+        return Err(EvalError::UnboundVariable(name));
+    }
+    let mut body = String::new();
+    write_rust(&rename_canonical(expr), &mut body)?;
+    Ok(format!(
+        "// Generated from {}\n{}\npub fn {}() -> Lam {{\n    {}\n}}\n",
+        expr, RUNTIME, fn_name, body
+    ))
+}
+
+fn write_rust(expr: &Expr, out: &mut String) -> Result<(), EvalError> {
+    match expr {
+        // Closures are `Fn`, so captured values are cloned rather than moved
+        Expr::Var(name) => write!(out, "{}.clone()", name).unwrap(),
+        Expr::Lit(n) => return Err(EvalError::NotAFunction(Value::VInt(*n))),
+        Expr::LetRecGroup(..) | Expr::If(..) => write_rust(&expr.expand(), out)?,
+        Expr::Abs(param, body) => {
+            // Each closure gets its own copy of what it captures
+            let mut captured: Vec<_> = free_vars(expr).into_iter().collect();
+            captured.sort();
+            if !captured.is_empty() {
+                out.push_str("{ ");
+                for name in &captured {
+                    write!(out, "let {} = {}.clone(); ", name, name).unwrap();
+                }
+            }
+            write!(out, "lam(move |{}| ", param).unwrap();
+            write_rust(body, out)?;
+            out.push(')');
+            if !captured.is_empty() {
+                out.push_str(" }");
+            }
+        }
+        Expr::App(f, arg) => {
+            out.push('(');
+            write_rust(f, out)?;
+            out.push_str(").call(");
+            write_rust(arg, out)?;
+            out.push(')');
+        }
+    }
+    Ok(())
+}
+
+// Type-checks a transpiled file as a library crate of its own
+#[cfg(test)]
+fn assert_compiles(rust: &str, fn_name: &str) {
+    use std::process::Command;
+
+    let dir = std::env::temp_dir().join(format!("transpile-{}-{}", std::process::id(), fn_name));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join(format!("{}.rs", fn_name));
+    std::fs::write(&file, rust).unwrap();
+    let output = Command::new(std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string()))
+        .args([
+            "--crate-type",
+            "lib",
+            "--emit",
+            "metadata",
+            "--edition",
+            "2021",
+            "--out-dir",
+        ])
+        .arg(&dir)
+        .arg(&file)
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(
+        output.status.success(),
+        "{}\n{}",
+        rust,
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn identity_becomes_a_closure() {
+    use crate::church::{church_numeral, mul};
+    use crate::{abs, app, var};
+
+    let rust = transpile_to_rust(&abs("x", var("x")), "id").unwrap();
+    assert!(rust.contains("pub fn id() -> Lam {\n    lam(move |v0| v0.clone())\n}"));
+    assert_compiles(&rust, "id");
+
+    // K's inner closure copies the outer parameter before capturing it
+    let rust = transpile_to_rust(&abs("x", abs("y", var("x"))), "k").unwrap();
+    assert!(rust.contains("lam(move |v0| { let v0 = v0.clone(); lam(move |v1| v0.clone()) })"));
+    assert_compiles(&rust, "k");
+
+    let rust = transpile_to_rust(&app(abs("x", var("x")), abs("y", var("y"))), "i").unwrap();
+    assert!(rust.contains("(lam(move |v0| v0.clone())).call(lam(move |v1| v1.clone()))"));
+    assert_compiles(&rust, "i");
+
+    // Captures two binders deep, and applications under binders
+    let product = app(app(mul(), church_numeral(2)), church_numeral(3));
+    assert_compiles(&transpile_to_rust(&product, "product").unwrap(), "product");
+}
+
+#[test]
+fn open_terms_and_literals_are_rejected() {
+    use crate::{abs, app, var};
+
+    assert_eq!(
+        transpile_to_rust(&abs("x", app(var("x"), var("y"))), "f"),
+        Err(EvalError::UnboundVariable("y".to_string()))
+    );
+    assert_eq!(
+        transpile_to_rust(&abs("x", Expr::Lit(1)), "f"),
+        Err(EvalError::NotAFunction(Value::VInt(1)))
+    );
+}
@@ -0,0 +1,262 @@
+// Simple types without annotations: the most general type of a term is
+// inferred by unification, and terms like `\x. x x` have none
+use std::fmt;
+use std::marker::PhantomData;
+
+use crate::{eval_with_trampoline, Context, Expr, Value};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    Var(usize),
+    Int,
+    Arrow(Box<Type>, Box<Type>),
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Var(id) => write!(f, "t{}", id),
+            Type::Int => write!(f, "Int"),
+            Type::Arrow(from, to) => match from.as_ref() {
+                Type::Arrow(..) => write!(f, "({}) -> {}", from, to),
+                Type::Var(_) | Type::Int => write!(f, "{} -> {}", from, to),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypeError {
+    UnboundVariable(String),
+    // Unifying a type variable with a type that contains it
+    InfiniteType(Type, Type),
+    Mismatch(Type, Type),
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypeError::UnboundVariable(name) => write!(f, "Variable {} not found", name),
+            TypeError::InfiniteType(var, ty) => {
+                write!(f, "Cannot construct the infinite type {} = {}", var, ty)
+            }
+            TypeError::Mismatch(a, b) => write!(f, "Cannot unify {} with {}", a, b),
+        }
+    }
+}
+
+impl std::error::Error for TypeError {}
+
+// The principal type of a closed term, with its variables numbered from t0
+// in the order they appear
+pub fn infer(expr: &Expr) -> Result<Type, TypeError> {
+    let mut state = Infer {
+        bindings: Vec::new(),
+    };
+    let ty = state.infer(expr, &mut Vec::new())?;
+    let ty = state.resolve(&ty);
+    Ok(renumber(&ty, &mut Vec::new()))
+}
+
+struct Infer {
+    // What each type variable has been unified with so far
+    bindings: Vec<Option<Type>>,
+}
+
+impl Infer {
+    fn fresh(&mut self) -> Type {
+        self.bindings.push(None);
+        Type::Var(self.bindings.len() - 1)
+    }
+
+    fn infer<'a>(
+        &mut self,
+        expr: &'a Expr,
+        env: &mut Vec<(&'a str, Type)>,
+    ) -> Result<Type, TypeError> {
+        match expr {
+            Expr::Var(name) => env
+                .iter()
+                .rev()
+                .find(|(bound, _)| bound == name)
+                .map(|(_, ty)| ty.clone())
+                .ok_or_else(|| TypeError::UnboundVariable(name.clone())),
+            Expr::Lit(_) => Ok(Type::Int),
+            Expr::LetRecGroup(..) | Expr::If(..) => self.infer(&expr.expand(), &mut env.clone()),
+            Expr::Abs(param, body) => {
+                let param_ty = self.fresh();
+                env.push((param, param_ty.clone()));
+                let body_ty = self.infer(body, env);
+                env.pop();
+                Ok(Type::Arrow(Box::new(param_ty), Box::new(body_ty?)))
+            }
+            Expr::App(f, arg) => {
+                let f_ty = self.infer(f, env)?;
+                let arg_ty = self.infer(arg, env)?;
+                let result_ty = self.fresh();
+                let expected = Type::Arrow(Box::new(arg_ty), Box::new(result_ty.clone()));
+                self.unify(&f_ty, &expected)?;
+                Ok(result_ty)
+            }
+        }
+    }
+
+    // Follows bindings until the outermost constructor is known
+    fn shallow(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Var(id) => match &self.bindings[*id] {
+                Some(bound) => self.shallow(bound),
+                None => ty.clone(),
+            },
+            Type::Int | Type::Arrow(..) => ty.clone(),
+        }
+    }
+
+    fn resolve(&self, ty: &Type) -> Type {
+        match self.shallow(ty) {
+            Type::Var(id) => Type::Var(id),
+            Type::Int => Type::Int,
+            Type::Arrow(from, to) => {
+                Type::Arrow(Box::new(self.resolve(&from)), Box::new(self.resolve(&to)))
+            }
+        }
+    }
+
+    fn occurs(&self, id: usize, ty: &Type) -> bool {
+        match self.shallow(ty) {
+            Type::Var(other) => other == id,
+            Type::Int => false,
+            Type::Arrow(from, to) => self.occurs(id, &from) || self.occurs(id, &to),
+        }
+    }
+
+    fn unify(&mut self, a: &Type, b: &Type) -> Result<(), TypeError> {
+        match (self.shallow(a), self.shallow(b)) {
+            (Type::Var(a), Type::Var(b)) if a == b => Ok(()),
+            (Type::Var(id), ty) | (ty, Type::Var(id)) => {
+                if self.occurs(id, &ty) {
+                    return Err(TypeError::InfiniteType(Type::Var(id), self.resolve(&ty)));
+                }
+                self.bindings[id] = Some(ty);
+                Ok(())
+            }
+            (Type::Arrow(a_from, a_to), Type::Arrow(b_from, b_to)) => {
+                self.unify(&a_from, &b_from)?;
+                self.unify(&a_to, &b_to)
+            }
+            (Type::Int, Type::Int) => Ok(()),
+            (a, b) => Err(TypeError::Mismatch(self.resolve(&a), self.resolve(&b))),
+        }
+    }
+}
+
+fn renumber(ty: &Type, seen: &mut Vec<usize>) -> Type {
+    match ty {
+        Type::Var(id) => match seen.iter().position(|seen| seen == id) {
+            Some(index) => Type::Var(index),
+            None => {
+                seen.push(*id);
+                Type::Var(seen.len() - 1)
+            }
+        },
+        Type::Int => Type::Int,
+        Type::Arrow(from, to) => {
+            let from = renumber(from, seen);
+            Type::Arrow(Box::new(from), Box::new(renumber(to, seen)))
+        }
+    }
+}
+
+// Whether a `Term` has been through `check`
+pub struct Unchecked;
+pub struct Checked;
+
+// An expression tagged with what is known about it, so that only terms that
+// passed the type checker can be evaluated through `Term::eval`
+pub struct Term<State> {
+    expr: Expr,
+    state: PhantomData<State>,
+}
+
+impl<State> Term<State> {
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+}
+
+impl Term<Unchecked> {
+    pub fn new(expr: Expr) -> Self {
+        Term {
+            expr,
+            state: PhantomData,
+        }
+    }
+
+    pub fn check(self) -> Result<Term<Checked>, TypeError> {
+        infer(&self.expr)?;
+        Ok(Term {
+            expr: self.expr,
+            state: PhantomData,
+        })
+    }
+}
+
+impl Term<Checked> {
+    // Well-typed terms are closed and strongly normalizing, so this neither
+    // panics on an unbound variable nor diverges
+    pub fn eval(self) -> Value {
+        eval_with_trampoline(self.expr, Context::new()).run()
+    }
+}
+
+#[test]
+fn infers_principal_types() {
+    use crate::{abs, app, var};
+
+    let k = abs("x", abs("y", var("x")));
+    // \f. \g. \x. f x (g x)
+    let s = abs(
+        "f",
+        abs(
+            "g",
+            abs("x", app(app(var("f"), var("x")), app(var("g"), var("x")))),
+        ),
+    );
+
+    assert_eq!(infer(&k).unwrap().to_string(), "t0 -> t1 -> t0");
+    assert_eq!(
+        infer(&s).unwrap().to_string(),
+        "(t0 -> t1 -> t2) -> (t0 -> t1) -> t0 -> t2"
+    );
+    assert!(matches!(
+        infer(&abs("x", app(var("x"), var("x")))),
+        Err(TypeError::InfiniteType(..))
+    ));
+    assert_eq!(
+        infer(&var("y")),
+        Err(TypeError::UnboundVariable("y".to_string()))
+    );
+    assert_eq!(
+        infer(&app(abs("x", var("x")), Expr::Lit(1))).unwrap(),
+        Type::Int
+    );
+    assert!(matches!(
+        infer(&app(Expr::Lit(1), Expr::Lit(2))),
+        Err(TypeError::Mismatch(Type::Int, Type::Arrow(..)))
+    ));
+}
+
+#[test]
+fn only_checked_terms_evaluate() {
+    use crate::{abs, app, quote, var};
+
+    let term: Term<Unchecked> = Term::new(app(abs("x", var("x")), abs("y", var("y"))));
+    let checked: Term<Checked> = term.check().unwrap();
+    assert_eq!(quote(&checked.eval()), abs("y", var("y")));
+
+    let omega = app(
+        abs("x", app(var("x"), var("x"))),
+        abs("x", app(var("x"), var("x"))),
+    );
+    assert!(Term::new(omega).check().is_err());
+}
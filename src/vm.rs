@@ -0,0 +1,345 @@
+// A Krivine abstract machine for closed terms. Applications push their
+// argument as an unevaluated closure, abstractions grab the top of the stack
+// and variables enter the closure they are bound to.
+
+use std::rc::Rc;
+
+use crate::{abs, var, Context, EvalError, Expr, Value};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instr {
+    Access(String),
+    Grab(String),
+    Push(Vec<Instr>),
+    Lit(i64),
+}
+
+pub fn compile(expr: &Expr) -> Vec<Instr> {
+    let mut code = Vec::new();
+    compile_into(expr, &mut code);
+    code
+}
+
+fn compile_into(expr: &Expr, code: &mut Vec<Instr>) {
+    match expr {
+        Expr::Var(name) => code.push(Instr::Access(name.clone())),
+        Expr::Lit(n) => code.push(Instr::Lit(*n)),
+        Expr::Abs(param, body) => {
+            code.push(Instr::Grab(param.clone()));
+            compile_into(body, code);
+        }
+        Expr::App(f, arg) => {
+            code.push(Instr::Push(compile(arg)));
+            compile_into(f, code);
+        }
+        Expr::LetRecGroup(..) | Expr::If(..) => compile_into(&expr.expand(), code),
+    }
+}
+
+fn decompile(code: &[Instr]) -> Result<Expr, EvalError> {
+    match code.split_first() {
+        Some((Instr::Access(name), _)) => Ok(Expr::Var(name.clone())),
+        Some((Instr::Lit(n), _)) => Ok(Expr::Lit(*n)),
+        Some((Instr::Grab(param), rest)) => {
+            Ok(Expr::Abs(param.clone(), Box::new(decompile(rest)?)))
+        }
+        Some((Instr::Push(arg), rest)) => Ok(Expr::App(
+            Box::new(decompile(rest)?),
+            Box::new(decompile(arg)?),
+        )),
+        None => Err(EvalError::MalformedCode),
+    }
+}
+
+#[derive(Clone)]
+struct Closure<'a> {
+    code: &'a [Instr],
+    env: Env<'a>,
+}
+
+#[derive(Clone, Default)]
+struct Env<'a>(Option<Rc<Binding<'a>>>);
+
+struct Binding<'a> {
+    name: &'a str,
+    closure: Closure<'a>,
+    next: Env<'a>,
+}
+
+impl<'a> Env<'a> {
+    fn bind(&self, name: &'a str, closure: Closure<'a>) -> Env<'a> {
+        Env(Some(Rc::new(Binding {
+            name,
+            closure,
+            next: self.clone(),
+        })))
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Closure<'a>> {
+        let mut current = &self.0;
+        while let Some(binding) = current {
+            if binding.name == name {
+                return Some(&binding.closure);
+            }
+            current = &binding.next.0;
+        }
+        None
+    }
+}
+
+// Machine steps left before giving up
+struct Fuel {
+    left: usize,
+    limit: usize,
+}
+
+impl Fuel {
+    fn new(limit: usize) -> Self {
+        Fuel { left: limit, limit }
+    }
+
+    fn burn(&mut self) -> Result<(), EvalError> {
+        match self.left.checked_sub(1) {
+            Some(left) => {
+                self.left = left;
+                Ok(())
+            }
+            None => Err(EvalError::StepLimit(self.limit)),
+        }
+    }
+}
+
+// Runs a closure until it needs an argument the stack doesn't have
+fn whnf<'a>(closure: Closure<'a>, fuel: &mut Fuel) -> Result<Closure<'a>, EvalError> {
+    whnf_until(closure, fuel, &[]).map(|(closure, _)| closure)
+}
+
+// Like `whnf`, also stopping on reaching any code in `stop`. Returns how many
+// arguments were left on the stack, which is 0 unless it stopped there.
+fn whnf_until<'a>(
+    closure: Closure<'a>,
+    fuel: &mut Fuel,
+    stop: &[&[Instr]],
+) -> Result<(Closure<'a>, usize), EvalError> {
+    let Closure { mut code, mut env } = closure;
+    let mut stack = Vec::new();
+    loop {
+        if stop.iter().any(|stop| std::ptr::eq(code, *stop)) {
+            return Ok((Closure { code, env }, stack.len()));
+        }
+        fuel.burn()?;
+        match code.split_first() {
+            Some((Instr::Access(name), _)) => {
+                let target = env
+                    .lookup(name)
+                    .cloned()
+                    .ok_or_else(|| EvalError::UnboundVariable(name.clone()))?;
+                code = target.code;
+                env = target.env;
+            }
+            Some((Instr::Push(arg), rest)) => {
+                stack.push(Closure {
+                    code: arg,
+                    env: env.clone(),
+                });
+                code = rest;
+            }
+            Some((Instr::Grab(param), rest)) => match stack.pop() {
+                Some(arg) => {
+                    env = env.bind(param, arg);
+                    code = rest;
+                }
+                None => return Ok((Closure { code, env }, 0)),
+            },
+            Some((Instr::Lit(n), _)) => match stack.is_empty() {
+                true => return Ok((Closure { code, env }, 0)),
+                false => return Err(EvalError::NotAFunction(Value::VInt(*n))),
+            },
+            None => return Err(EvalError::MalformedCode),
+        }
+    }
+}
+
+// Forces every binding so the result has the same shape as the closures
+// built by `eval_with_trampoline`
+fn to_value(closure: Closure, fuel: &mut Fuel) -> Result<Value, EvalError> {
+    let Closure { code, env } = whnf(closure, fuel)?;
+    let (param, body) = match code.split_first() {
+        Some((Instr::Grab(param), body)) => (param, body),
+        Some((Instr::Lit(n), _)) => return Ok(Value::VInt(*n)),
+        _ => return Err(EvalError::MalformedCode),
+    };
+
+    let mut context = Context::new();
+    let mut current = &env.0;
+    while let Some(binding) = current {
+        if !context.contains_key(binding.name) {
+            let value = to_value(binding.closure.clone(), fuel)?;
+            context.insert(binding.name.to_string(), value);
+        }
+        current = &binding.next.0;
+    }
+
+    Ok(Value::VClosure(
+        Rc::new(context),
+        param.clone(),
+        Rc::new(decompile(body)?),
+    ))
+}
+
+pub fn run_vm(code: &[Instr]) -> Result<Value, EvalError> {
+    run_vm_within(code, usize::MAX)
+}
+
+// Like `run_vm`, failing with `StepLimit` after `max_steps` machine steps
+pub fn run_vm_within(code: &[Instr], max_steps: usize) -> Result<Value, EvalError> {
+    let closure = Closure {
+        code,
+        env: Env::default(),
+    };
+    to_value(closure, &mut Fuel::new(max_steps))
+}
+
+// Counts a Church numeral by running `n (\p. \k. p) (\k. k)`. Every `f` of
+// the numeral stops at a closure whose `p` is the rest of the count and zero
+// stops at `\k. k`, so the numeral's normal form is never built. The machine
+// stops before either `\k` so that a term passing them more arguments, which
+// no numeral does, is rejected. `None` if `expr` isn't a numeral or needs
+// more than `max_steps` machine steps.
+pub fn decode_numeral(expr: &Expr, max_steps: usize) -> Option<u64> {
+    let zero = compile(&abs("k", var("k")));
+    let succ = compile(&abs("p", abs("k", var("p"))));
+    let mut code = vec![Instr::Push(zero), Instr::Push(succ)];
+    compile_into(expr, &mut code);
+    let (Instr::Push(zero), Instr::Push(succ)) = (&code[0], &code[1]) else {
+        unreachable!()
+    };
+
+    let mut fuel = Fuel::new(max_steps);
+    let mut closure = Closure {
+        code: &code,
+        env: Env::default(),
+    };
+    let mut count = 0;
+    let stop = [zero.as_slice(), &succ[1..]];
+    loop {
+        let (stopped, left) = whnf_until(closure, &mut fuel, &stop).ok()?;
+        if left > 0 {
+            return None;
+        }
+        closure = stopped;
+        if std::ptr::eq(closure.code, zero.as_slice()) {
+            return Some(count);
+        }
+        if !std::ptr::eq(closure.code, &succ[1..]) {
+            return None;
+        }
+        closure = closure.env.lookup("p")?.clone();
+        count += 1;
+    }
+}
+
+#[cfg(test)]
+pub fn church_examples() -> Vec<Expr> {
+    use crate::app;
+    use crate::church::{add, church_numeral, mul, succ};
+
+    vec![
+        church_numeral(0),
+        church_numeral(3),
+        app(succ(), church_numeral(2)),
+        app(app(add(), church_numeral(2)), church_numeral(3)),
+        app(app(mul(), church_numeral(2)), church_numeral(3)),
+        app(
+            app(mul(), app(succ(), church_numeral(1))),
+            app(app(add(), church_numeral(1)), church_numeral(2)),
+        ),
+    ]
+}
+
+#[test]
+fn vm_matches_trampoline_on_church_arithmetic() {
+    use std::collections::HashMap;
+
+    use crate::eval_with_trampoline;
+
+    for expr in church_examples() {
+        let expected = eval_with_trampoline(expr.clone(), HashMap::new()).run();
+        assert_eq!(run_vm(&compile(&expr)), Ok(expected));
+    }
+}
+
+#[test]
+fn vm_reports_unbound_variables() {
+    use crate::app;
+
+    let code = compile(&app(var("f"), var("x")));
+    assert_eq!(
+        run_vm(&code),
+        Err(EvalError::UnboundVariable("f".to_string()))
+    );
+    assert_eq!(run_vm(&[]), Err(EvalError::MalformedCode));
+}
+
+#[test]
+fn decode_numeral_counts_without_normalizing() {
+    use crate::app;
+    use crate::church::{church_numeral, mul};
+
+    let thousand = app(app(mul(), church_numeral(10)), church_numeral(100));
+    let omega = app(
+        abs("x", app(var("x"), var("x"))),
+        abs("x", app(var("x"), var("x"))),
+    );
+
+    assert_eq!(decode_numeral(&church_numeral(0), 100), Some(0));
+    assert_eq!(decode_numeral(&thousand, 100_000), Some(1_000));
+    assert_eq!(decode_numeral(&thousand, 100), None);
+    assert_eq!(decode_numeral(&abs("x", abs("y", var("x"))), 100), None);
+    // One, eta-reduced
+    assert_eq!(decode_numeral(&abs("f", var("f")), 100), Some(1));
+    // Probes applied to more arguments than a numeral gives them
+    let extra_zero = abs("a", abs("b", app(app(var("a"), var("b")), var("b"))));
+    let extra_succ = abs(
+        "f",
+        abs("x", app(app(var("f"), var("x")), app(var("f"), var("x")))),
+    );
+    assert_eq!(decode_numeral(&extra_zero, 100), None);
+    assert_eq!(decode_numeral(&extra_succ, 100), None);
+    assert_eq!(decode_numeral(&omega, 1_000), None);
+}
+
+// Run with `cargo test --release -- --ignored --nocapture`
+#[test]
+#[ignore]
+fn bench_vm_against_trampoline() {
+    use std::collections::HashMap;
+    use std::time::Instant;
+
+    use crate::eval_with_trampoline;
+
+    const ROUNDS: usize = 1_000;
+
+    let programs: Vec<_> = church_examples()
+        .into_iter()
+        .map(|expr| (compile(&expr), expr))
+        .collect();
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for (_, expr) in &programs {
+            eval_with_trampoline(expr.clone(), HashMap::new()).run();
+        }
+    }
+    let trampoline = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for (code, _) in &programs {
+            run_vm(code).unwrap();
+        }
+    }
+    let vm = start.elapsed();
+
+    println!("trampoline: {:?}, vm: {:?}", trampoline, vm);
+}
@@ -0,0 +1,28 @@
+use std::process::Command;
+
+fn run(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_trampoline-lc"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn dump_ast_prints_the_parsed_term() {
+    let output = run(&["--dump-ast", "\\x. x"]);
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("\"Abs\""));
+    assert_eq!(stdout, "{\"Abs\":[\"x\",{\"Var\":\"x\"}]}\n");
+}
+
+#[test]
+fn dump_ast_reports_parse_errors() {
+    let output = run(&["--dump-ast", "(\\x. x"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr, "Error: Expected ')' at offset 6\n");
+}